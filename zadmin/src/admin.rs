@@ -0,0 +1,292 @@
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use domain::base::Rtype;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use zshutdown::SHUTDOWN;
+use zcacher::{valid_name, CacheEntry, ZCacher};
+use zconfig::Admin as AdminConf;
+use zpreloader::ZPreloader;
+use zreload::ZReloader;
+use zqueue::{ZQueueHander, ZRequestQueue, ZResponseQueue};
+use zstats::{metrics, STATS};
+use crate::http::{self, Request, Response};
+
+// 单个连接的处理超时
+const TIMEOUT: Duration = Duration::from_secs(10);
+const PROMETHEUS: &str = "text/plain; version=0.0.4";
+
+/// HTTP管理接口
+///
+/// - `GET /cache` 列出缓存, `GET /cache/{name}` 查看域名的缓存
+/// - `DELETE /cache/{name}` 删除域名的缓存, `DELETE /cache?suffix={name}` 删除域名及子域名的缓存, `DELETE /cache` 清空缓存
+/// - `POST /cache/{name}/refresh?type={rtype}` 立即重新解析
+/// - `GET /stats` 运行统计, `GET /metrics` Prometheus格式的指标
+/// - `POST /reload/preload` 重新加载预加载文件, `POST /reload/config` 重新加载配置文件
+#[derive(Clone)]
+pub struct ZAdmin {
+    listen: Option<SocketAddr>,
+    cacher: Arc<ZCacher>,
+    queues: Option<(Arc<ZRequestQueue>, Arc<ZResponseQueue>)>,
+    preloader: Option<Arc<ZPreloader>>,
+    reloader: Option<Arc<ZReloader>>,
+}
+
+fn entry_json(entry: &CacheEntry) -> Value {
+    json!({
+        "name": entry.name,
+        "type": entry.rtype.to_string(),
+        "ttl": entry.ttl,
+        "ip": entry.ip.map(|ip| ip.to_string()),
+        "answers": entry.answers,
+    })
+}
+
+fn entries_json(entries: &[CacheEntry]) -> Value {
+    json!({
+        "count": entries.len(),
+        "entries": entries.iter().map(entry_json).collect::<Vec<_>>(),
+    })
+}
+
+impl ZAdmin {
+
+    pub fn build(conf: Option<AdminConf>, cacher: Arc<ZCacher>) -> Result<Self> {
+        let listen = match conf {
+            Some(conf) => Some(conf.listen.parse::<SocketAddr>()?),
+            None => None,
+        };
+        Ok(Self { listen, cacher, queues: None, preloader: None, reloader: None })
+    }
+
+    /// 统计中包含请求及应答队列的长度
+    pub fn with_queues(mut self, req_q: Arc<ZRequestQueue>, res_q: Arc<ZResponseQueue>) -> Self {
+        self.queues = Some((req_q, res_q));
+        self
+    }
+
+    pub fn with_preloader(mut self, preloader: Arc<ZPreloader>) -> Self {
+        self.preloader = Some(preloader);
+        self
+    }
+
+    pub fn with_reloader(mut self, reloader: Arc<ZReloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    fn stats(&self) -> Value {
+        let mut stats = STATS.to_json();
+        if let Some((req_q, res_q)) = &self.queues {
+            stats["queues"] = json!({ "request": req_q.len(), "response": res_q.len() });
+        }
+        stats
+    }
+
+    fn metrics(&self) -> String {
+        let mut out = STATS.to_prometheus();
+        if let Some((req_q, res_q)) = &self.queues {
+            let queues = [("request", req_q.len(), req_q.capacity()), ("response", res_q.len(), res_q.capacity())];
+            metrics::header(&mut out, "zzdns_queue_length", "gauge", "Messages waiting in the worker queues.");
+            for (name, len, _) in queues {
+                metrics::sample(&mut out, "zzdns_queue_length", &[("queue", name)], len as f64);
+            }
+            metrics::header(&mut out, "zzdns_queue_capacity", "gauge", "Capacity of the worker queues.");
+            for (name, _, cap) in queues {
+                metrics::sample(&mut out, "zzdns_queue_capacity", &[("queue", name)], cap as f64);
+            }
+            metrics::header(&mut out, "zzdns_queue_saturation", "gauge", "Fraction of the worker queue capacity in use.");
+            for (name, len, cap) in queues {
+                metrics::sample(&mut out, "zzdns_queue_saturation", &[("queue", name)], len as f64 / cap.max(1) as f64);
+            }
+        }
+        out
+    }
+
+    pub async fn serve(&self) -> Result<()> {
+        let listen = match self.listen {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let listener = TcpListener::bind(listen).await?;
+        info!("zadmin listening on {}", listen);
+        loop {
+            tokio::select! {
+                res = listener.accept() => {
+                    let (mut stream, src) = match res {
+                        Ok(r) => r,
+                        Err(e) => {
+                            error!("Failed to accept admin connection, error:{:?}", e);
+                            continue;
+                        }
+                    };
+                    let admin = self.clone();
+                    tokio::spawn(async move {
+                        let res = tokio::time::timeout(TIMEOUT, async {
+                            let req = match http::read_request(&mut stream).await {
+                                Ok(r) => r,
+                                Err(e) => return http::write_response(&mut stream, &Response::error(400, e)).await,
+                            };
+                            info!("admin request from {}: {} {}", src, req.method, req.path);
+                            let res = admin.route(&req).await;
+                            http::write_response(&mut stream, &res).await
+                        }).await;
+                        if let Ok(Err(e)) = res {
+                            debug!("admin connection error, client: {}, error:{:?}", src, e);
+                        }
+                    });
+                }
+                _ = SHUTDOWN.wait() => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    pub async fn route(&self, req: &Request) -> Response {
+        let segments: Vec<&str> = req.path.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect();
+        match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["cache"]) => Response::ok(entries_json(&self.cacher.entries().await)),
+            ("GET", ["cache", name]) => {
+                let entries = self.cacher.lookup(name).await;
+                if entries.is_empty() {
+                    return Response::error(404, format!("{} is not cached", name));
+                }
+                Response::ok(entries_json(&entries))
+            },
+            ("DELETE", ["cache"]) => {
+                let res = match req.query.get("suffix") {
+                    Some(suffix) => self.cacher.flush_suffix(suffix).await,
+                    None => self.cacher.flush_all().await,
+                };
+                flushed(res)
+            },
+            ("DELETE", ["cache", name]) => flushed(self.cacher.flush(name).await),
+            ("POST", ["cache", name, "refresh"]) => {
+                let rtype = match req.query.get("type").map(|t| Rtype::from_str(&t.to_ascii_uppercase())) {
+                    Some(Ok(rtype)) => rtype,
+                    Some(Err(_)) => return Response::error(400, "invalid record type"),
+                    None => Rtype::A,
+                };
+                if let Err(e) = valid_name(name) {
+                    return Response::error(400, e);
+                }
+                match self.cacher.refresh(name, rtype).await {
+                    Ok(_) => Response::ok(entries_json(&self.cacher.lookup(name).await)),
+                    Err(e) => Response::error(502, e),
+                }
+            },
+            ("GET", ["stats"]) => Response::ok(self.stats()),
+            ("GET", ["metrics"]) => Response::text(self.metrics(), PROMETHEUS),
+            ("POST", ["reload", "preload"]) => match &self.preloader {
+                Some(preloader) => Response::ok(json!({ "pushed": preloader.reload().await })),
+                None => Response::error(404, "preloader is not enabled"),
+            },
+            // 配置无效时返回400, 应用失败时返回500, 均保留当前配置
+            ("POST", ["reload", "config"]) => match &self.reloader {
+                Some(reloader) => match reloader.load() {
+                    Ok(conf) => match reloader.apply(conf).await {
+                        Ok(changed) => Response::ok(json!({ "reloaded": true, "restart_required": changed })),
+                        Err(e) => Response::error(500, format!("{:#}", e)),
+                    },
+                    Err(e) => Response::error(400, format!("{:#}", e)),
+                },
+                None => Response::error(404, "config reload is not enabled"),
+            },
+            (_, ["cache" | "stats" | "metrics" | "reload", ..]) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+}
+
+fn flushed(res: Result<usize>) -> Response {
+    match res {
+        Ok(count) => Response::ok(json!({ "flushed": count })),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use zdnssec::ZValidator;
+    use zupstream::ZUpstream;
+    use super::*;
+
+    async fn admin() -> ZAdmin {
+        let conf = serde_json::from_value(json!({"max_size": 100, "max_ttl": 600, "min_ttl": 60})).unwrap();
+        let upstream = Arc::new(ZUpstream::build(Vec::new()).await.unwrap());
+        let validator = Arc::new(ZValidator::build(None, upstream.clone()).unwrap());
+        let cacher = Arc::new(ZCacher::new(conf, upstream, validator).unwrap());
+        ZAdmin::build(None, cacher).unwrap()
+    }
+
+    fn request(method: &str, path: &str, query: &[(&str, &str)]) -> Request {
+        let query: HashMap<String, String> = query.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Request { method: method.to_string(), path: path.to_string(), query }
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let admin = admin().await;
+        assert_eq!(admin.route(&request("GET", "/cache", &[])).await, Response::ok(json!({"count": 0, "entries": []})));
+        assert_eq!(admin.route(&request("GET", "/cache/example.com", &[])).await.status, 404);
+        assert_eq!(admin.route(&request("DELETE", "/cache/", &[])).await, Response::ok(json!({"flushed": 0})));
+        assert_eq!(admin.route(&request("DELETE", "/cache", &[("suffix", "example.com")])).await.status, 200);
+        assert_eq!(admin.route(&request("POST", "/cache/example.com/refresh", &[("type", "BOGUS")])).await.status, 400);
+        assert_eq!(admin.route(&request("POST", "/cache/a..b/refresh", &[])).await.status, 400);
+        let long = format!("/cache/{}.com/refresh", "a".repeat(64));
+        assert_eq!(admin.route(&request("POST", &long, &[])).await.status, 400);
+        assert_eq!(admin.route(&request("PUT", "/cache", &[])).await.status, 405);
+        assert_eq!(admin.route(&request("GET", "/", &[])).await.status, 404);
+        assert_eq!(admin.route(&request("POST", "/reload/preload", &[])).await.status, 404);
+        assert_eq!(admin.route(&request("POST", "/reload/config", &[])).await.status, 404);
+        assert_eq!(admin.route(&request("GET", "/reload/config", &[])).await.status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let req_q = Arc::new(ZRequestQueue::new(8));
+        let res_q = Arc::new(ZResponseQueue::new(8));
+        req_q.send(("127.0.0.1:5353".parse().unwrap(), bytes::Bytes::new(), std::time::SystemTime::now())).await.unwrap();
+        let admin = admin().await.with_queues(req_q, res_q);
+        let res = admin.route(&request("GET", "/stats", &[])).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body["queues"], json!({"request": 1, "response": 0}));
+        assert!(res.body["queries"]["total"].is_u64());
+        assert!(res.body["cache"]["hit_ratio"].is_f64());
+        assert_eq!(admin.route(&request("DELETE", "/stats", &[])).await.status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let req_q = Arc::new(ZRequestQueue::new(4));
+        let res_q = Arc::new(ZResponseQueue::new(4));
+        req_q.send(("127.0.0.1:5353".parse().unwrap(), bytes::Bytes::new(), std::time::SystemTime::now())).await.unwrap();
+        let admin = admin().await.with_queues(req_q, res_q);
+        let res = admin.route(&request("GET", "/metrics", &[])).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.content_type, PROMETHEUS);
+        let text = res.body.as_str().unwrap();
+        assert!(text.contains("# TYPE zzdns_cache_hits_total counter\n"));
+        assert!(text.contains("zzdns_queue_length{queue=\"request\"} 1\n"));
+        assert!(text.contains("zzdns_queue_capacity{queue=\"response\"} 4\n"));
+        assert!(text.contains("zzdns_queue_saturation{queue=\"request\"} 0.25\n"));
+    }
+
+    #[test]
+    fn test_entry_json() {
+        let entry = CacheEntry {
+            name: "example.com".to_string(),
+            rtype: Rtype::A,
+            ttl: 120,
+            ip: Some("93.184.216.34".parse().unwrap()),
+            answers: vec!["example.com A 93.184.216.34".to_string()],
+        };
+        assert_eq!(entry_json(&entry), json!({
+            "name": "example.com", "type": "A", "ttl": 120, "ip": "93.184.216.34",
+            "answers": ["example.com A 93.184.216.34"],
+        }));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// 请求头的最大长度, 管理接口不接收请求体
+const MAX_HEAD: usize = 8192;
+const JSON: &str = "application/json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+    pub content_type: &'static str,
+}
+
+impl Response {
+
+    pub fn ok(body: Value) -> Self {
+        Self { status: 200, body, content_type: JSON }
+    }
+
+    /// 纯文本应答, 原样输出
+    pub fn text(body: String, content_type: &'static str) -> Self {
+        Self { status: 200, body: Value::String(body), content_type }
+    }
+
+    pub fn error(status: u16, message: impl ToString) -> Self {
+        Self { status, body: serde_json::json!({ "error": message.to_string() }), content_type: JSON }
+    }
+}
+
+pub fn parse(head: &str) -> Result<Request> {
+    let line = head.lines().next().ok_or_else(|| anyhow!("empty request"))?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v)) if v.starts_with("HTTP/1.") => (m, t),
+        _ => return Err(anyhow!("invalid request line: {:?}", line)),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = url::form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+    Ok(Request { method: method.to_ascii_uppercase(), path: path.to_string(), query })
+}
+
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Request> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() > MAX_HEAD {
+            return Err(anyhow!("request header too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("connection closed"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    parse(&String::from_utf8_lossy(&buf))
+}
+
+pub async fn write_response<S: AsyncWrite + Unpin>(stream: &mut S, res: &Response) -> Result<()> {
+    let body = match &res.body {
+        Value::String(text) if res.content_type != JSON => text.as_bytes().to_vec(),
+        body => serde_json::to_vec_pretty(body)?,
+    };
+    let reason = match res.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        _ => "Error",
+    };
+    let head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        res.status, reason, res.content_type, body.len());
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let req = parse("delete /cache?suffix=example.com&x=a%20b HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert_eq!(req.method, "DELETE");
+        assert_eq!(req.path, "/cache");
+        assert_eq!(req.query.get("suffix").map(String::as_str), Some("example.com"));
+        assert_eq!(req.query.get("x").map(String::as_str), Some("a b"));
+
+        let req = parse("GET /cache/www.example.com HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(req.path, "/cache/www.example.com");
+        assert!(req.query.is_empty());
+
+        assert!(parse("GET /cache\r\n\r\n").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client.write_all(b"GET /cache HTTP/1.1\r\nHost: x\r\n\r\n").await.unwrap();
+        let req = read_request(&mut server).await.unwrap();
+        assert_eq!(req.path, "/cache");
+        write_response(&mut server, &Response::error(404, "not found")).await.unwrap();
+        drop(server);
+        let mut out = String::new();
+        client.read_to_string(&mut out).await.unwrap();
+        assert!(out.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(out.ends_with("{\n  \"error\": \"not found\"\n}"));
+
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        write_response(&mut server, &Response::text("up 1\n".to_string(), "text/plain")).await.unwrap();
+        drop(server);
+        let mut out = String::new();
+        client.read_to_string(&mut out).await.unwrap();
+        assert!(out.contains("Content-Type: text/plain\r\n"));
+        assert!(out.ends_with("\r\n\r\nup 1\n"));
+    }
+}
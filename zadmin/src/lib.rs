@@ -0,0 +1,6 @@
+#[macro_use] extern crate log;
+
+mod http;
+mod admin;
+
+pub use admin::ZAdmin;
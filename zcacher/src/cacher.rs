@@ -1,40 +1,150 @@
 
-use std::{sync::Arc, collections::HashSet, str::FromStr, time::Duration};
-use anyhow::Result;
+use std::{sync::{Arc, Mutex, RwLock, atomic::{AtomicUsize, Ordering}}, collections::{HashMap, HashSet}, net::IpAddr, str::FromStr, time::Duration};
+use anyhow::{anyhow, Result};
 use async_channel::{bounded, Sender, Receiver};
 use bytes::{Bytes, BytesMut};
 use domain::base::{MessageBuilder, Question, Rtype, Message, iana::{Rcode, Class}, Dname};
-use domain::rdata;
-use stretto::AsyncCache;
-use tokio::signal;
+use domain::rdata::{self, AllRecordData};
+use tokio::sync::Semaphore;
+use tokio::time::MissedTickBehavior;
+use zdnssec::{dnssec_query, Security, ZValidator};
 use zupstream::ZUpstream;
 use zconfig::Cache as CacheConf;
 use zspeedtest::ZSpeedTest;
+use zstats::STATS;
+use crate::snapshot::{self, Entry};
+use crate::store::{self, Store};
 
+// 默认每5分钟保存一次缓存快照
+const SNAPSHOT_INTERVAL: u64 = 300;
+// 剩余TTL低于该值的缓存允许重新解析刷新
+const REFRESH_TTL: u64 = 60;
+// 默认最多同时解析16个域名
+const REFRESH_CONCURRENCY: usize = 16;
+// 默认每秒最多开始解析50个域名
+const REFRESH_RATE: u32 = 50;
+// 缓存队列的最大长度, 不超过缓存条目数
+const QUEUE_SIZE: usize = 65536;
+
+// 已缓存的域名及其插入时间, stretto不支持遍历, 据此读取缓存, 定期移除已淘汰的键
+type Keys = Arc<Mutex<HashMap<String, u64>>>;
+
+// 正在解析的域名, 同一域名同时只解析一次
+#[derive(Clone, Default)]
+struct Inflight(Arc<Mutex<HashSet<String>>>);
+
+struct InflightGuard {
+    inflight: Inflight,
+    key: String,
+}
+
+impl Inflight {
+    fn begin(&self, key: &str) -> Option<InflightGuard> {
+        if !self.0.lock().unwrap().insert(key.to_string()) {
+            return None;
+        }
+        Some(InflightGuard { inflight: self.clone(), key: key.to_string() })
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.inflight.0.lock().unwrap().remove(&self.key);
+    }
+}
+
+
+/// 缓存条目, 用于查看缓存内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub name: String,
+    pub rtype: Rtype,
+    pub ttl: u32,
+    pub ip: Option<IpAddr>, // 应答中的地址, A记录为测速选出的IP
+    pub answers: Vec<String>,
+}
 
 #[derive(Clone)]
 pub struct ZCacher {
-    cache: AsyncCache<String, Bytes>,
+    cache: Store,
     q_sender: Arc<Sender<String>>,
     q_receiver: Arc<Receiver<String>>,
     upstream: Arc<ZUpstream>,
-    conf: Arc<CacheConf>,
+    validator: Arc<ZValidator>,
+    conf: Arc<RwLock<Arc<CacheConf>>>,
+    keys: Keys,
+    inflight: Inflight,
+    permits: Arc<Semaphore>,
+    concurrency: Arc<AtomicUsize>, // 当前的许可总数
 }
 
 impl ZCacher {
-    pub fn new(conf: CacheConf, zupstream: Arc<ZUpstream>) -> Self {
-        let cache: AsyncCache<String, Bytes> = AsyncCache::new(conf.max_size.into(), 1e6 as i64,
-        tokio::spawn).unwrap();
-        let (s, r) = bounded::<String>(conf.max_size.into());
-        Self { cache, q_sender: Arc::new(s), q_receiver: Arc::new(r), upstream: zupstream, conf: Arc::new(conf) }
+    pub fn new(conf: CacheConf, zupstream: Arc<ZUpstream>, validator: Arc<ZValidator>) -> Result<Self> {
+        let cache = store::build(&conf)?;
+        let (s, r) = bounded::<String>(conf.max_size.clamp(1, QUEUE_SIZE));
+        let concurrency = conf.refresh_concurrency.unwrap_or(REFRESH_CONCURRENCY).max(1);
+        Ok(Self { cache, q_sender: Arc::new(s), q_receiver: Arc::new(r), upstream: zupstream, validator,
+            permits: Arc::new(Semaphore::new(concurrency)), concurrency: Arc::new(AtomicUsize::new(concurrency)),
+            conf: Arc::new(RwLock::new(Arc::new(conf))), keys: Arc::new(Mutex::new(HashMap::new())), inflight: Inflight::default() })
+    }
+
+    fn conf(&self) -> Arc<CacheConf> {
+        self.conf.read().unwrap().clone()
+    }
+
+    /// 更新TTL范围及后台解析的限制, 缓存后端及容量需重启后生效.
+    pub fn reload(&self, conf: CacheConf) {
+        let old = self.conf();
+        if conf.backend != old.backend || conf.max_size != old.max_size || conf.max_bytes != old.max_bytes
+            || conf.redis != old.redis || conf.redis_prefix != old.redis_prefix {
+            warn!("Changes to the cache backend and size take effect after restart");
+        }
+        self.resize(conf.refresh_concurrency.unwrap_or(REFRESH_CONCURRENCY).max(1));
+        *self.conf.write().unwrap() = Arc::new(conf);
+    }
+
+    // 调整后台解析的并发数, 减少时等进行中的解析结束后收回许可
+    fn resize(&self, size: usize) {
+        let old = self.concurrency.swap(size, Ordering::Relaxed);
+        if size > old {
+            self.permits.add_permits(size - old);
+        } else if size < old {
+            let permits = self.permits.clone();
+            tokio::spawn(async move {
+                if let Ok(permit) = permits.acquire_many_owned((old - size) as u32).await {
+                    permit.forget();
+                }
+            });
+        }
     }
     
     // 接收缓存队列域名解析
     pub async fn serve(&self) -> Result<()> {
         info!("zcacher running...");
+        let period = Duration::from_secs(self.conf().snapshot_interval.unwrap_or(SNAPSHOT_INTERVAL).max(1));
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        let mut rate = self.conf().refresh_rate.unwrap_or(REFRESH_RATE).max(1);
+        let mut limiter = tokio::time::interval(Duration::from_secs(1) / rate);
+        limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
+            // 重新加载配置后按新的速率限制
+            let current = self.conf().refresh_rate.unwrap_or(REFRESH_RATE).max(1);
+            if current != rate {
+                rate = current;
+                limiter = tokio::time::interval(Duration::from_secs(1) / rate);
+                limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            }
             tokio::select! {
-                res = self.q_receiver.recv() => {
+                // 取得并发许可并按速率限制后再读取队列
+                (permit, res) = async {
+                    let permit = self.permits.clone().acquire_owned().await;
+                    limiter.tick().await;
+                    (permit, self.q_receiver.recv().await)
+                } => {
+                    // 关闭后不再解析队列中剩余的域名
+                    if self.q_receiver.is_closed() {
+                        return self.stop().await;
+                    }
                     let domain = match res {
                         Ok(r) => r,
                         Err(e) => {
@@ -42,16 +152,29 @@ impl ZCacher {
                             continue;
                         }
                     };
+                    let guard = match self.inflight.begin(&domain) {
+                        Some(g) => g,
+                        None => continue,
+                    };
                     let cache = self.cache.clone();
                     let upstream = self.upstream.clone();
-                    let conf = self.conf.clone();
+                    let validator = self.validator.clone();
+                    let conf = self.conf();
+                    let keys = self.keys.clone();
                     tokio::spawn(async move {
-                        let _ = handle(cache, upstream, conf, domain).await;
+                        let _ = handle(cache, upstream, validator, conf, keys, domain, false).await;
+                        drop(guard);
+                        drop(permit);
                     });
 
                 }
-                _ = signal::ctrl_c() => {
-                    return self.stop().await;
+                // 未配置快照时也定期移除已被淘汰或过期的键
+                _ = interval.tick() => {
+                    if self.conf().snapshot_file.is_none() {
+                        self.prune().await;
+                    } else if let Err(e) = self.save_snapshot().await {
+                        error!("Failed to save cache snapshot, error:{:?}", e);
+                    }
                 }
             }
         }
@@ -61,17 +184,111 @@ impl ZCacher {
         self.serve().await
     }
 
+    /// 关闭解析队列, 后台任务停止并保存快照
+    pub fn close(&self) {
+        self.q_sender.close();
+    }
+
     pub async fn stop(&self) -> Result<()> {
+        if self.conf().snapshot_file.is_some() {
+            self.save_snapshot().await?;
+        }
         Ok(())
     }
 
+    /// 将未过期的缓存写入快照文件, 先写临时文件再重命名, 避免中途退出损坏快照.
+    pub async fn save_snapshot(&self) -> Result<()> {
+        let path = match self.conf().snapshot_file.clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let entries = self.prune().await;
+        let tmp = format!("{}.tmp", path);
+        tokio::fs::write(&tmp, snapshot::encode(snapshot::now(), &entries)).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        info!("Saved cache snapshot, count:{:?}", entries.len());
+        Ok(())
+    }
+
+    // 移除缓存中已不存在的键, 返回未过期的条目
+    async fn prune(&self) -> Vec<Entry> {
+        let keys: Vec<(String, u64)> = self.keys.lock().unwrap().iter().map(|(k, v)| (k.clone(), *v)).collect();
+        let mut entries = Vec::new();
+        let mut expired = Vec::new();
+        for (domain, inserted_at) in keys {
+            match self.cache.get(&domain).await {
+                Some((msg, ttl)) => {
+                    let ttl = ttl.as_secs() as u32;
+                    if ttl > 0 {
+                        entries.push(Entry { domain, inserted_at, ttl, msg });
+                    }
+                },
+                None => expired.push(domain),
+            }
+        }
+        {
+            let mut keys = self.keys.lock().unwrap();
+            for domain in expired {
+                keys.remove(&domain);
+            }
+        }
+        entries
+    }
+
+    /// 启动时加载快照, 按保存后经过的时间扣减TTL并丢弃已过期的条目.
+    pub async fn load_snapshot(&self) -> Result<usize> {
+        let path = match self.conf().snapshot_file.clone() {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let (saved_at, entries) = snapshot::decode(data.into())?;
+        let elapsed = snapshot::now().saturating_sub(saved_at);
+        let mut count = 0;
+        for entry in entries {
+            let ttl = u64::from(entry.ttl).saturating_sub(elapsed);
+            if ttl == 0 {
+                continue;
+            }
+            if self.cache.insert(entry.domain.clone(), entry.msg, Duration::from_secs(ttl)).await {
+                self.keys.lock().unwrap().insert(entry.domain, entry.inserted_at);
+                count += 1;
+            }
+        }
+        self.cache.wait().await?;
+        info!("Loaded cache snapshot, count:{:?}", count);
+        Ok(count)
+    }
+
     pub async fn get(&self, domain: String) -> Option<(Bytes, u32)> {
-        if let Some(res) =  self.cache.get(&domain) {
-            let bytes = res.value().clone();
-            let ttl = res.ttl().as_secs() as u32;
+        let res = self.get_key(domain).await;
+        match res.is_some() {
+            true => STATS.cache_hit(),
+            false => STATS.cache_miss(),
+        }
+        res
+    }
+
+    /// 读取其它类型记录的缓存, 只有预加载的记录会被缓存, 未命中不计入统计.
+    pub async fn get_record(&self, domain: &str, rtype: Rtype) -> Option<(Bytes, u32)> {
+        let res = self.get_key(key(domain, rtype)).await;
+        if res.is_some() {
+            STATS.cache_hit();
+        }
+        res
+    }
+
+    async fn get_key(&self, key: String) -> Option<(Bytes, u32)> {
+        if let Some((bytes, ttl)) =  self.cache.get(&key).await {
+            let ttl = ttl.as_secs() as u32;
             let keep_ttl = 15;
             if ttl < keep_ttl {
-                let _ = self.push(domain).await;
+                // 队列已满时不阻塞客户端查询
+                let _ = self.q_sender.try_send(key);
                 Some((bytes, keep_ttl))
             }else{
                 Some((bytes, ttl - keep_ttl))
@@ -82,26 +299,154 @@ impl ZCacher {
     }
 
 
+    /// 域名未缓存或即将过期时返回true.
+    pub async fn expiring(&self, domain: &str) -> bool {
+        expiring(&self.cache, domain).await
+    }
+
     pub async fn push(&self, domain: String) -> Result<()> {
         let _ = self.q_sender.send(domain).await;
         Ok(())
     }
+
+    /// 全部缓存条目, 按域名排序.
+    pub async fn entries(&self) -> Vec<CacheEntry> {
+        self.find(|_| true).await
+    }
+
+    /// 域名的各类型缓存
+    pub async fn lookup(&self, name: &str) -> Vec<CacheEntry> {
+        let name = normalize(name);
+        self.find(|n| n == name).await
+    }
+
+    /// 删除域名的缓存, 返回删除的条目数.
+    pub async fn flush(&self, name: &str) -> Result<usize> {
+        let name = normalize(name);
+        self.remove(|n| n == name).await
+    }
+
+    /// 删除域名及其子域名的缓存
+    pub async fn flush_suffix(&self, suffix: &str) -> Result<usize> {
+        let suffix = normalize(suffix);
+        let dotted = format!(".{}", suffix);
+        self.remove(|n| n == suffix || n.ends_with(&dotted)).await
+    }
+
+    pub async fn flush_all(&self) -> Result<usize> {
+        self.remove(|_| true).await
+    }
+
+    /// 立即重新解析并更新缓存, 不论缓存是否即将过期.
+    pub async fn refresh(&self, name: &str, rtype: Rtype) -> Result<()> {
+        valid_name(name)?;
+        let key = key(&normalize(name), rtype);
+        let _guard = self.inflight.begin(&key).ok_or_else(|| anyhow!("{} is being refreshed", key))?;
+        handle(self.cache.clone(), self.upstream.clone(), self.validator.clone(), self.conf(),
+            self.keys.clone(), key, true).await
+    }
+
+    fn tracked(&self, matches: impl Fn(&str) -> bool) -> Vec<String> {
+        self.keys.lock().unwrap().keys().filter(|k| matches(&parse_key(k).0.to_ascii_lowercase())).cloned().collect()
+    }
+
+    async fn find(&self, matches: impl Fn(&str) -> bool) -> Vec<CacheEntry> {
+        let mut entries = Vec::new();
+        for key in self.tracked(matches) {
+            let (bytes, ttl) = match self.cache.get(&key).await {
+                Some(r) => r,
+                None => continue,
+            };
+            let (name, rtype) = parse_key(&key);
+            let mut entry = CacheEntry { name: name.to_string(), rtype, ttl: ttl.as_secs() as u32, ip: None, answers: Vec::new() };
+            if let Ok(msg) = Message::from_octets(bytes) {
+                for rr in msg.answer().into_iter().flat_map(|a| a.limit_to::<AllRecordData<_, _>>()).flatten() {
+                    match rr.data() {
+                        AllRecordData::A(a) => { entry.ip.get_or_insert(IpAddr::V4(a.addr())); },
+                        AllRecordData::Aaaa(a) => { entry.ip.get_or_insert(IpAddr::V6(a.addr())); },
+                        _ => {},
+                    }
+                    entry.answers.push(format!("{} {} {}", rr.owner(), rr.rtype(), rr.data()));
+                }
+            }
+            entries.push(entry);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.rtype.to_int().cmp(&b.rtype.to_int())));
+        entries
+    }
+
+    async fn remove(&self, matches: impl Fn(&str) -> bool) -> Result<usize> {
+        let keys = self.tracked(matches);
+        for key in keys.iter() {
+            self.cache.remove(key).await;
+            self.keys.lock().unwrap().remove(key);
+        }
+        self.cache.wait().await?;
+        info!("Flushed cache, count:{:?}", keys.len());
+        Ok(keys.len())
+    }
+
+    /// 将指定类型的记录加入缓存队列, A记录与 `push` 相同.
+    pub async fn push_record(&self, domain: &str, rtype: Rtype) -> Result<()> {
+        self.push(key(domain, rtype)).await
+    }
+}
+
+// A记录以域名为键, 其它类型为 `域名 类型`
+fn key(domain: &str, rtype: Rtype) -> String {
+    match rtype {
+        Rtype::A => domain.to_string(),
+        _ => format!("{} {}", domain, rtype),
+    }
+}
+
+/// 检查域名是否合法, 如标签为空或超过63字节时返回错误.
+pub fn valid_name(name: &str) -> Result<()> {
+    Dname::bytes_from_str(name).map(|_| ()).map_err(|e| anyhow!("Invalid domain name {:?}: {}", name, e))
 }
 
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
 
-async fn handle(cache: AsyncCache<String, Bytes>, upstream: Arc<ZUpstream>, conf: Arc<CacheConf>, domain: String) -> Result<()> {
+fn parse_key(key: &str) -> (&str, Rtype) {
+    match key.split_once(' ') {
+        Some((domain, rtype)) => (domain, Rtype::from_str(rtype).unwrap_or(Rtype::A)),
+        None => (key, Rtype::A),
+    }
+}
 
-    if cache.clone().get(&domain).is_some() {
+
+async fn expiring(cache: &Store, domain: &str) -> bool {
+    cache.ttl(domain).await.is_none_or(|ttl| ttl < Duration::from_secs(REFRESH_TTL))
+}
+
+#[allow(clippy::unnecessary_unwrap)]
+async fn handle(cache: Store, upstream: Arc<ZUpstream>, validator: Arc<ZValidator>,
+    conf: Arc<CacheConf>, keys: Keys, domain: String, force: bool) -> Result<()> {
+
+    if !force && !expiring(&cache, &domain).await {
         return Ok(());
     };
+    let (name, rtype) = parse_key(&domain);
+    if rtype != Rtype::A {
+        let name = name.to_string();
+        return handle_record(cache, upstream, validator, conf, keys, name, rtype).await;
+    }
 
-    let qname = domain::base::Dname::bytes_from_str(domain.as_str()).unwrap();
+    let qname = domain::base::Dname::bytes_from_str(domain.as_str())?;
     let qmsg_builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?;
     let mut question_builder = qmsg_builder.question();
-    question_builder.push(Question::new_in(qname.clone(), Rtype::A)).unwrap();
+    question_builder.push(Question::new_in(qname.clone(), Rtype::A))?;
     let qmsg = question_builder.into_message().into_octets();
+    let qmsg_msg = Message::from_octets(qmsg.clone())?;
 
-    let rbytes_list = upstream.query_all(&qmsg).await.unwrap();
+    let rbytes_list = match validator.is_enabled() {
+        true => upstream.query_all(&dnssec_query(&qmsg_msg)?).await?,
+        false => upstream.query_all(&qmsg).await?,
+    };
+    // 所有采用的应答都验证通过时, 缓存的应答设置AD位
+    let mut secure = validator.is_enabled();
 
     let mut cname_list = HashSet::new();
     let mut ip_list = HashSet::new();
@@ -110,6 +455,15 @@ async fn handle(cache: AsyncCache<String, Bytes>, upstream: Arc<ZUpstream>, conf
     for rbytes in rbytes_list {
         if let Ok(rmsg) = Message::from_octets(rbytes) {
 
+            match validator.check(&qmsg_msg, &rmsg).await {
+                Security::Secure => {},
+                Security::Insecure => secure = false,
+                Security::Bogus(reason) => {
+                    warn!("dnssec validation failed, domain: {:?}, reason: {}", domain, reason);
+                    continue;
+                },
+            }
+
             let (_, answer, _, _) = match rmsg.sections() {
                 Ok(s) => s,
                 Err(_) => {continue}
@@ -157,23 +511,189 @@ async fn handle(cache: AsyncCache<String, Bytes>, upstream: Arc<ZUpstream>, conf
     .start_answer(&Message::from_octets(qmsg)?, Rcode::NoError)?;
     let header = rmsg.header_mut();
     header.set_ra(true);
+    header.set_ad(secure && !ip_list.is_empty());
 
     let mut prev_domain = domain;
     let mut cur_domain = prev_domain.clone();
  
     for name in cname_list.iter() {
         cur_domain = name.clone();
-        let dname = Dname::bytes_from_str(&prev_domain).unwrap();
-        let cname = rdata::Cname::from(Dname::bytes_from_str(name).unwrap());
-        rmsg.push((dname, Class::In, ttl, cname)).unwrap();
+        let dname = Dname::bytes_from_str(&prev_domain)?;
+        let cname = rdata::Cname::from(Dname::bytes_from_str(name)?);
+        rmsg.push((dname, Class::In, ttl, cname))?;
         prev_domain = name.clone();
     }
     
-    let cur_domain = Dname::bytes_from_str(cur_domain.to_string().as_str()).unwrap();
+    let cur_domain = Dname::bytes_from_str(cur_domain.to_string().as_str())?;
     let ip = ZSpeedTest::query(ip_list.into_iter().collect()).await?;
-    rmsg.push((cur_domain.clone(), Class::In, ttl, rdata::A::from_str(&ip.to_string()).unwrap())).unwrap();
-    let status = cache.insert_with_ttl(qname.to_string(), rmsg.into_message().into_octets(), 2, Duration::from_secs(100)).await;
+    rmsg.push((cur_domain.clone(), Class::In, ttl, rdata::A::from_str(&ip.to_string())?))?;
+    let status = cache.insert(qname.to_string(), rmsg.into_message().into_octets(), Duration::from_secs(100)).await;
+    if status {
+        keys.lock().unwrap().insert(qname.to_string(), snapshot::now());
+        STATS.cache_refresh();
+    }
     
     info!("cache domain: {:?}, status={:?}", qname.to_string(), status);
     Ok(())
-}
\ No newline at end of file
+}
+// 缓存其它类型的记录: 采用第一个验证通过且有应答记录的结果, 不做测速.
+async fn handle_record(cache: Store, upstream: Arc<ZUpstream>, validator: Arc<ZValidator>,
+    conf: Arc<CacheConf>, keys: Keys, domain: String, rtype: Rtype) -> Result<()> {
+
+    let qname = Dname::bytes_from_str(&domain)?;
+    let mut qmsg_builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?;
+    qmsg_builder.header_mut().set_rd(true);
+    let mut question_builder = qmsg_builder.question();
+    question_builder.push(Question::new_in(qname, rtype))?;
+    let qmsg = Message::from_octets(question_builder.into_message().into_octets())?;
+
+    let rbytes_list = match validator.is_enabled() {
+        true => upstream.query_all(&dnssec_query(&qmsg)?).await?,
+        false => upstream.query_all(qmsg.as_octets()).await?,
+    };
+
+    for rbytes in rbytes_list {
+        let rmsg = match Message::from_octets(rbytes) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        if rmsg.header().rcode() != Rcode::NoError || rmsg.header_counts().ancount() == 0 {
+            continue;
+        }
+        let secure = match validator.check(&qmsg, &rmsg).await {
+            Security::Secure => true,
+            Security::Insecure => false,
+            Security::Bogus(reason) => {
+                warn!("dnssec validation failed, domain: {:?}, rtype: {}, reason: {}", domain, rtype, reason);
+                continue;
+            },
+        };
+
+        let mut ttl = u32::MAX;
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+            .start_answer(&qmsg, Rcode::NoError)?;
+        builder.header_mut().set_ra(true);
+        builder.header_mut().set_ad(secure);
+        for rr in rmsg.answer()?.flatten() {
+            if matches!(rr.rtype(), Rtype::Rrsig | Rtype::Nsec | Rtype::Nsec3 | Rtype::Opt) {
+                continue;
+            }
+            if let Ok(Some(record)) = rr.to_record::<AllRecordData<_, _>>() {
+                ttl = ttl.min(record.ttl());
+                builder.push(record)?;
+            }
+        }
+        if ttl == u32::MAX {
+            continue;
+        }
+        let ttl = ttl.clamp(conf.min_ttl.into(), conf.max_ttl.into());
+        let key = key(&domain, rtype);
+        let status = cache.insert(key.clone(), builder.into_message().into_octets(), Duration::from_secs(ttl.into())).await;
+        if status {
+            keys.lock().unwrap().insert(key, snapshot::now());
+            STATS.cache_refresh();
+        }
+        info!("cache domain: {:?}, rtype: {}, status={:?}", domain, rtype, status);
+        return Ok(());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn cacher() -> ZCacher {
+        let conf = serde_json::from_str(r#"{"max_size": 100, "max_ttl": 600, "min_ttl": 60}"#).unwrap();
+        let upstream = Arc::new(ZUpstream::build(Vec::new()).await.unwrap());
+        let validator = Arc::new(ZValidator::build(None, upstream.clone()).unwrap());
+        ZCacher::new(conf, upstream, validator).unwrap()
+    }
+
+    async fn insert(cacher: &ZCacher, name: &str, rtype: Rtype, answer: &str) {
+        let qname = Dname::<Bytes>::from_str(name).unwrap();
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().answer();
+        match rtype {
+            Rtype::A => builder.push((qname, Class::In, 300, rdata::A::from_str(answer).unwrap())).unwrap(),
+            _ => builder.push((qname, Class::In, 300, rdata::Aaaa::from_str(answer).unwrap())).unwrap(),
+        }
+        let key = key(name, rtype);
+        cacher.cache.insert(key.clone(), builder.into_message().into_octets(), Duration::from_secs(300)).await;
+        cacher.keys.lock().unwrap().insert(key, snapshot::now());
+    }
+
+    #[tokio::test]
+    async fn test_inspect() {
+        let cacher = cacher().await;
+        insert(&cacher, "example.com", Rtype::A, "93.184.216.34").await;
+        insert(&cacher, "example.com", Rtype::Aaaa, "2606:2800:220:1::1").await;
+        insert(&cacher, "www.example.com", Rtype::A, "93.184.216.35").await;
+        insert(&cacher, "example.org", Rtype::A, "93.184.216.36").await;
+        cacher.cache.wait().await.unwrap();
+
+        let entries = cacher.entries().await;
+        assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+            vec!["example.com", "example.com", "example.org", "www.example.com"]);
+        let found = cacher.lookup("Example.com.").await;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].rtype, Rtype::A);
+        assert_eq!(found[0].ip, Some("93.184.216.34".parse().unwrap()));
+        assert_eq!(found[0].answers, vec!["example.com A 93.184.216.34".to_string()]);
+        assert!(found[0].ttl > 290);
+        assert_eq!(found[1].ip, Some("2606:2800:220:1::1".parse().unwrap()));
+
+        assert_eq!(cacher.flush("www.example.com").await.unwrap(), 1);
+        assert!(cacher.lookup("www.example.com").await.is_empty());
+        insert(&cacher, "www.example.com", Rtype::A, "93.184.216.35").await;
+        cacher.cache.wait().await.unwrap();
+        assert_eq!(cacher.flush_suffix("example.com").await.unwrap(), 3);
+        assert_eq!(cacher.entries().await.len(), 1);
+        assert_eq!(cacher.flush_all().await.unwrap(), 1);
+        assert!(cacher.entries().await.is_empty());
+        assert!(cacher.get("example.org".to_string()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let cacher = cacher().await;
+        insert(&cacher, "example.com", Rtype::A, "93.184.216.34").await;
+        cacher.cache.wait().await.unwrap();
+        // 缓存已淘汰的键
+        cacher.keys.lock().unwrap().insert("evicted.example.com".to_string(), snapshot::now());
+        let entries = cacher.prune().await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].domain, "example.com");
+        assert_eq!(cacher.keys.lock().unwrap().keys().collect::<Vec<_>>(), vec!["example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let cacher = cacher().await;
+        let serving = cacher.clone();
+        let handle = tokio::spawn(async move { serving.start().await });
+        cacher.push("example.com".to_string()).await.unwrap();
+        cacher.close();
+        // 关闭后后台任务退出, 不再解析队列中的域名
+        tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        assert!(cacher.push("example.org".to_string()).await.is_ok());
+    }
+
+    #[test]
+    fn test_inflight() {
+        let inflight = Inflight::default();
+        let guard = inflight.begin("example.com").unwrap();
+        assert!(inflight.begin("example.com").is_none());
+        assert!(inflight.begin("example.org").is_some());
+        drop(guard);
+        assert!(inflight.begin("example.com").is_some());
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("example.com", Rtype::A), "example.com");
+        assert_eq!(key("example.com", Rtype::Aaaa), "example.com AAAA");
+        assert_eq!(parse_key("example.com"), ("example.com", Rtype::A));
+        assert_eq!(parse_key("example.com AAAA"), ("example.com", Rtype::Aaaa));
+        assert_eq!(parse_key("example.com MX"), ("example.com", Rtype::Mx));
+    }
+}
@@ -1,3 +1,9 @@
 #[macro_use] extern crate log;
 mod cacher;
-pub use cacher::ZCacher;
\ No newline at end of file
+mod snapshot;
+mod store;
+mod memory;
+mod lru;
+mod redis;
+pub use cacher::{valid_name, CacheEntry, ZCacher};
+pub use store::CacheStore;
\ No newline at end of file
@@ -0,0 +1,152 @@
+use std::{collections::{BTreeMap, HashMap}, sync::Mutex, time::{Duration, Instant}};
+use async_trait::async_trait;
+use bytes::Bytes;
+use crate::store::CacheStore;
+
+// 默认内存上限64MB
+pub const MAX_BYTES: u64 = 64 * 1024 * 1024;
+// 每个条目除键值外的估算开销
+const ENTRY_OVERHEAD: u64 = 64;
+
+struct Entry {
+    value: Bytes,
+    expires: Instant,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    order: BTreeMap<u64, String>, // 访问序号 -> 键, 最小的最久未访问
+    tick: u64,
+    used: u64,
+}
+
+fn cost(key: &str, value: &Bytes) -> u64 {
+    (key.len() + value.len()) as u64 + ENTRY_OVERHEAD
+}
+
+impl Lru {
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.used -= cost(key, &entry.value);
+        }
+    }
+
+    fn touch(&mut self, key: &str) -> Option<&Entry> {
+        let now = Instant::now();
+        let expired = self.entries.get(key)?.expires <= now;
+        if expired {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        entry.tick = tick;
+        self.order.insert(tick, key.to_string());
+        Some(entry)
+    }
+}
+
+/// 按字节数限制大小的LRU缓存, 超出上限时淘汰最久未访问的条目.
+pub struct LruStore {
+    max_bytes: u64,
+    inner: Mutex<Lru>,
+}
+
+impl LruStore {
+    pub fn new(max_bytes: u64) -> Self {
+        Self { max_bytes, inner: Mutex::new(Lru::default()) }
+    }
+}
+
+#[async_trait]
+impl CacheStore for LruStore {
+
+    async fn get(&self, key: &str) -> Option<(Bytes, Duration)> {
+        let mut lru = self.inner.lock().unwrap();
+        let entry = lru.touch(key)?;
+        Some((entry.value.clone(), entry.expires.saturating_duration_since(Instant::now())))
+    }
+
+    async fn ttl(&self, key: &str) -> Option<Duration> {
+        let lru = self.inner.lock().unwrap();
+        let ttl = lru.entries.get(key)?.expires.saturating_duration_since(Instant::now());
+        (!ttl.is_zero()).then_some(ttl)
+    }
+
+    async fn insert(&self, key: String, value: Bytes, ttl: Duration) -> bool {
+        let size = cost(&key, &value);
+        if size > self.max_bytes {
+            return false;
+        }
+        let mut lru = self.inner.lock().unwrap();
+        lru.remove(&key);
+        while lru.used + size > self.max_bytes {
+            let oldest = match lru.order.first_key_value() {
+                Some((_, k)) => k.clone(),
+                None => break,
+            };
+            lru.remove(&oldest);
+        }
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(key, Entry { value, expires: Instant::now() + ttl, tick });
+        lru.used += size;
+        true
+    }
+
+    async fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn test_evict_by_bytes() {
+        let value = Bytes::from(vec![0u8; 100]);
+        let size = cost("a.com", &value);
+        let store = LruStore::new(size * 2);
+        assert!(store.insert("a.com".to_string(), value.clone(), TTL).await);
+        assert!(store.insert("b.com".to_string(), value.clone(), TTL).await);
+        // 访问a.com后, b.com成为最久未访问的条目
+        assert!(store.get("a.com").await.is_some());
+        assert!(store.insert("c.com".to_string(), value.clone(), TTL).await);
+        assert!(store.get("b.com").await.is_none());
+        assert!(store.get("a.com").await.is_some());
+        assert!(store.get("c.com").await.is_some());
+        assert_eq!(store.inner.lock().unwrap().used, size * 2);
+
+        store.remove("a.com").await;
+        assert!(store.get("a.com").await.is_none());
+        assert_eq!(store.inner.lock().unwrap().used, size);
+
+        // 超过上限的值不缓存
+        assert!(!store.insert("big.com".to_string(), Bytes::from(vec![0u8; 1000]), TTL).await);
+    }
+
+    #[tokio::test]
+    async fn test_expire() {
+        let store = LruStore::new(MAX_BYTES);
+        store.insert("a.com".to_string(), Bytes::from_static(b"a"), Duration::from_millis(20)).await;
+        store.insert("b.com".to_string(), Bytes::from_static(b"b"), TTL).await;
+        assert!(store.ttl("a.com").await.is_some());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(store.ttl("a.com").await.is_none());
+        assert!(store.get("a.com").await.is_none());
+        let (value, ttl) = store.get("b.com").await.unwrap();
+        assert_eq!(value, Bytes::from_static(b"b"));
+        assert!(ttl > Duration::from_secs(50));
+        assert_eq!(store.inner.lock().unwrap().entries.len(), 1);
+    }
+}
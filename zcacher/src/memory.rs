@@ -0,0 +1,43 @@
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use stretto::AsyncCache;
+use crate::store::CacheStore;
+
+/// 进程内缓存, 按条目数量限制大小.
+pub struct MemoryStore {
+    cache: AsyncCache<String, Bytes>,
+}
+
+impl MemoryStore {
+    pub fn new(max_size: usize) -> Result<Self> {
+        let cache = AsyncCache::new(max_size, 1e6 as i64, tokio::spawn)?;
+        Ok(Self { cache })
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+
+    async fn get(&self, key: &str) -> Option<(Bytes, Duration)> {
+        let res = self.cache.get(key)?;
+        Some((res.value().clone(), res.ttl()))
+    }
+
+    async fn ttl(&self, key: &str) -> Option<Duration> {
+        self.cache.get_ttl(key)
+    }
+
+    async fn insert(&self, key: String, value: Bytes, ttl: Duration) -> bool {
+        self.cache.insert_with_ttl(key, value, 2, ttl).await
+    }
+
+    async fn remove(&self, key: &str) {
+        self.cache.remove(&key.to_string()).await
+    }
+
+    async fn wait(&self) -> Result<()> {
+        Ok(self.cache.wait().await?)
+    }
+}
@@ -0,0 +1,329 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use zconfig::RedisAddr;
+use crate::store::CacheStore;
+
+// 单次请求的超时, Redis不可用时不影响解析
+const TIMEOUT: Duration = Duration::from_millis(500);
+const PREFIX: &str = "zzdns:";
+// 最多同时使用的连接数
+const POOL_SIZE: usize = 8;
+// 连接失败后在该时间内直接返回错误, 不再尝试连接
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// RESP协议的应答
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Status(String),
+    Error(String),
+    Int(i64),
+    Bulk(Option<Bytes>),
+    Array(Vec<Reply>),
+}
+
+/// Redis协议的共享缓存, 多个zzdns实例可共用同一缓存.
+pub struct RedisStore {
+    addr: String,
+    password: Option<String>,
+    db: Option<u32>,
+    prefix: String,
+    idle: Mutex<Vec<BufStream<TcpStream>>>, // 空闲连接
+    permits: Semaphore,
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl RedisStore {
+
+    /// 地址格式见 [`RedisAddr`].
+    pub fn new(url: &str, prefix: Option<String>) -> Result<Self> {
+        let RedisAddr { addr, password, db } = url.parse()?;
+        Ok(Self { addr, password, db, prefix: prefix.unwrap_or_else(|| PREFIX.to_string()),
+            idle: Mutex::new(Vec::new()), permits: Semaphore::new(POOL_SIZE), down_until: Mutex::new(None) })
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>> {
+        let mut stream = BufStream::new(TcpStream::connect(&self.addr).await?);
+        if let Some(password) = &self.password {
+            expect_ok(roundtrip(&mut stream, &[vec![b"AUTH".to_vec(), password.as_bytes().to_vec()]]).await?)?;
+        }
+        if let Some(db) = self.db {
+            expect_ok(roundtrip(&mut stream, &[vec![b"SELECT".to_vec(), db.to_string().into_bytes()]]).await?)?;
+        }
+        Ok(stream)
+    }
+
+    // 取出空闲连接或新建连接, 连接失败后一段时间内直接返回错误.
+    async fn checkout(&self) -> Result<BufStream<TcpStream>> {
+        if let Some(stream) = self.idle.lock().unwrap().pop() {
+            return Ok(stream);
+        }
+        if self.down_until.lock().unwrap().is_some_and(|until| Instant::now() < until) {
+            return Err(anyhow!("redis {} unavailable", self.addr));
+        }
+        let res = match tokio::time::timeout(TIMEOUT, self.connect()).await {
+            Ok(res) => res,
+            Err(_) => Err(anyhow!("redis {} connect timeout", self.addr)),
+        };
+        *self.down_until.lock().unwrap() = res.is_err().then(|| Instant::now() + RETRY_DELAY);
+        res
+    }
+
+    // 以管道方式发送命令, 每个请求独占一个连接, 连接出错时重连一次.
+    async fn call(&self, cmds: &[Vec<Vec<u8>>]) -> Result<Vec<Reply>> {
+        let _permit = tokio::time::timeout(TIMEOUT, self.permits.acquire()).await
+            .map_err(|_| anyhow!("redis {} busy", self.addr))??;
+        for _ in 0..2 {
+            let mut stream = self.checkout().await?;
+            match tokio::time::timeout(TIMEOUT, roundtrip(&mut stream, cmds)).await {
+                Ok(Ok(replies)) => {
+                    self.idle.lock().unwrap().push(stream);
+                    return Ok(replies);
+                },
+                Ok(Err(e)) => debug!("redis request failed, error: {:?}", e),
+                Err(_) => debug!("redis request timeout"),
+            }
+        }
+        Err(anyhow!("redis {} unavailable", self.addr))
+    }
+
+    fn key(&self, key: &str) -> Vec<u8> {
+        format!("{}{}", self.prefix, key).into_bytes()
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+
+    async fn get(&self, key: &str) -> Option<(Bytes, Duration)> {
+        let key = self.key(key);
+        let replies = self.call(&[
+            vec![b"GET".to_vec(), key.clone()],
+            vec![b"PTTL".to_vec(), key],
+        ]).await.map_err(|e| warn!("{}", e)).ok()?;
+        match replies.as_slice() {
+            [Reply::Bulk(Some(value)), Reply::Int(ms)] if *ms > 0 => Some((value.clone(), Duration::from_millis(*ms as u64))),
+            _ => None,
+        }
+    }
+
+    async fn ttl(&self, key: &str) -> Option<Duration> {
+        let replies = self.call(&[vec![b"PTTL".to_vec(), self.key(key)]]).await.map_err(|e| warn!("{}", e)).ok()?;
+        match replies.as_slice() {
+            [Reply::Int(ms)] if *ms > 0 => Some(Duration::from_millis(*ms as u64)),
+            _ => None,
+        }
+    }
+
+    async fn insert(&self, key: String, value: Bytes, ttl: Duration) -> bool {
+        let ms = ttl.as_millis().max(1).to_string().into_bytes();
+        let cmd = vec![b"SET".to_vec(), self.key(&key), value.to_vec(), b"PX".to_vec(), ms];
+        match self.call(&[cmd]).await {
+            Ok(replies) => replies.first().is_some_and(|r| *r == Reply::Status("OK".to_string())),
+            Err(e) => {
+                warn!("{}", e);
+                false
+            },
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        if let Err(e) = self.call(&[vec![b"DEL".to_vec(), self.key(key)]]).await {
+            warn!("{}", e);
+        }
+    }
+}
+
+fn expect_ok(replies: Vec<Reply>) -> Result<()> {
+    match replies.first() {
+        Some(Reply::Status(_)) => Ok(()),
+        Some(Reply::Error(e)) => Err(anyhow!("redis error: {}", e)),
+        reply => Err(anyhow!("unexpected redis reply: {:?}", reply)),
+    }
+}
+
+fn encode(buf: &mut Vec<u8>, cmd: &[Vec<u8>]) {
+    buf.extend_from_slice(format!("*{}\r\n", cmd.len()).as_bytes());
+    for arg in cmd {
+        buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buf.extend_from_slice(arg);
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
+async fn roundtrip(stream: &mut BufStream<TcpStream>, cmds: &[Vec<Vec<u8>>]) -> Result<Vec<Reply>> {
+    let mut buf = Vec::new();
+    for cmd in cmds {
+        encode(&mut buf, cmd);
+    }
+    stream.write_all(&buf).await?;
+    stream.flush().await?;
+    let mut replies = Vec::with_capacity(cmds.len());
+    for _ in cmds {
+        replies.push(read_reply(stream).await?);
+    }
+    Ok(replies)
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(anyhow!("redis connection closed"));
+    }
+    Ok(line.trim_end_matches("\r\n").to_string())
+}
+
+async fn read_reply(stream: &mut BufStream<TcpStream>) -> Result<Reply> {
+    let line = read_line(stream).await?;
+    let (kind, rest) = line.split_at(line.len().min(1));
+    match kind {
+        "+" => Ok(Reply::Status(rest.to_string())),
+        "-" => Ok(Reply::Error(rest.to_string())),
+        ":" => Ok(Reply::Int(rest.parse()?)),
+        "$" => {
+            let len: i64 = rest.parse()?;
+            if len < 0 {
+                return Ok(Reply::Bulk(None));
+            }
+            let mut data = vec![0u8; len as usize + 2];
+            stream.read_exact(&mut data).await?;
+            data.truncate(len as usize);
+            Ok(Reply::Bulk(Some(Bytes::from(data))))
+        },
+        "*" => {
+            let len: i64 = rest.parse()?;
+            let mut items = Vec::new();
+            for _ in 0..len.max(0) {
+                items.push(Box::pin(read_reply(stream)).await?);
+            }
+            Ok(Reply::Array(items))
+        },
+        _ => Err(anyhow!("invalid redis reply: {:?}", line)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::net::TcpListener;
+    use super::*;
+
+    type Db = Arc<Mutex<HashMap<Vec<u8>, (Vec<u8>, Instant)>>>;
+
+    // 只实现GET/SET PX/PTTL/DEL/SELECT的Redis替身
+    async fn serve(listener: TcpListener, db: Db) {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let db = db.clone();
+            tokio::spawn(async move {
+                let mut stream = BufStream::new(socket);
+                while let Ok(Reply::Array(args)) = read_reply(&mut stream).await {
+                    let args: Vec<Vec<u8>> = args.into_iter().filter_map(|a| match a {
+                        Reply::Bulk(Some(b)) => Some(b.to_vec()),
+                        _ => None,
+                    }).collect();
+                    let reply = {
+                        let mut db = db.lock().unwrap();
+                        db.retain(|_, (_, expires)| *expires > Instant::now());
+                        match args[0].to_ascii_uppercase().as_slice() {
+                            b"SELECT" => "+OK\r\n".as_bytes().to_vec(),
+                            b"SET" => {
+                                let ms: u64 = String::from_utf8_lossy(&args[4]).parse().unwrap();
+                                db.insert(args[1].clone(), (args[2].clone(), Instant::now() + Duration::from_millis(ms)));
+                                b"+OK\r\n".to_vec()
+                            },
+                            b"GET" => match db.get(&args[1]) {
+                                Some((v, _)) => [format!("${}\r\n", v.len()).as_bytes(), v, b"\r\n"].concat(),
+                                None => b"$-1\r\n".to_vec(),
+                            },
+                            b"DEL" => format!(":{}\r\n", db.remove(&args[1]).is_some() as i64).into_bytes(),
+                            b"PTTL" => match db.get(&args[1]) {
+                                Some((_, e)) => format!(":{}\r\n", e.duration_since(Instant::now()).as_millis()).into_bytes(),
+                                None => b":-2\r\n".to_vec(),
+                            },
+                            _ => b"-ERR unknown command\r\n".to_vec(),
+                        }
+                    };
+                    if stream.write_all(&reply).await.is_err() || stream.flush().await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        let store = RedisStore::new("redis://:secret@10.0.0.1/2", None).unwrap();
+        assert_eq!(store.addr, "10.0.0.1:6379");
+        assert_eq!(store.password.as_deref(), Some("secret"));
+        assert_eq!(store.db, Some(2));
+        assert_eq!(store.prefix, PREFIX);
+        let store = RedisStore::new("127.0.0.1:7000", Some("dns:".to_string())).unwrap();
+        assert_eq!(store.addr, "127.0.0.1:7000");
+        assert_eq!(store.db, None);
+        assert_eq!(store.key("a.com"), b"dns:a.com");
+        assert!(RedisStore::new("redis://host/x", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}/1", listener.local_addr().unwrap());
+        let db = Db::default();
+        tokio::spawn(serve(listener, db.clone()));
+
+        // 两个实例共用同一缓存
+        let a = RedisStore::new(&url, None).unwrap();
+        let b = RedisStore::new(&url, None).unwrap();
+        assert!(a.insert("example.com".to_string(), Bytes::from_static(b"\x00\r\n\xff"), Duration::from_secs(60)).await);
+        let (value, ttl) = b.get("example.com").await.unwrap();
+        assert_eq!(value, Bytes::from_static(b"\x00\r\n\xff"));
+        assert!(ttl > Duration::from_secs(50));
+        assert!(b.ttl("example.com").await.is_some());
+        assert!(b.get("example.org").await.is_none());
+        assert!(db.lock().unwrap().contains_key(b"zzdns:example.com".as_slice()));
+        b.remove("example.com").await;
+        assert!(a.get("example.com").await.is_none());
+
+        a.insert("short.com".to_string(), Bytes::from_static(b"x"), Duration::from_millis(20)).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(b.get("short.com").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unavailable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let store = RedisStore::new(&addr.to_string(), None).unwrap();
+        assert!(store.get("example.com").await.is_none());
+        // 连接失败后暂不重连
+        assert!(store.down_until.lock().unwrap().is_some());
+        assert!(!store.insert("example.com".to_string(), Bytes::new(), Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_pool() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, Db::default()));
+
+        // 并发请求使用多个连接, 完成后放回空闲连接
+        let store = Arc::new(RedisStore::new(&url, None).unwrap());
+        let tasks: Vec<_> = (0..32).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move { store.insert(format!("{}.com", i), Bytes::from_static(b"x"), Duration::from_secs(60)).await })
+        }).collect();
+        for task in tasks {
+            assert!(task.await.unwrap());
+        }
+        let idle = store.idle.lock().unwrap().len();
+        assert!((1..=POOL_SIZE).contains(&idle), "{}", idle);
+        assert!(store.get("31.com").await.is_some());
+    }
+}
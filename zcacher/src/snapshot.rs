@@ -0,0 +1,97 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+// 快照文件格式: 魔数 + 版本 + 保存时间, 之后依次为各缓存条目
+const MAGIC: &[u8; 4] = b"ZZDC";
+const VERSION: u8 = 1;
+
+/// 快照中的一条缓存
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub domain: String,
+    pub inserted_at: u64, // 插入时间(unix秒)
+    pub ttl: u32,         // 保存时的剩余TTL
+    pub msg: Bytes,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn encode(saved_at: u64, entries: &[Entry]) -> Bytes {
+    let size = entries.iter().map(|e| e.domain.len() + e.msg.len() + 18).sum::<usize>();
+    let mut buf = BytesMut::with_capacity(13 + size);
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+    buf.put_u64(saved_at);
+    for entry in entries {
+        buf.put_u16(entry.domain.len() as u16);
+        buf.put_slice(entry.domain.as_bytes());
+        buf.put_u64(entry.inserted_at);
+        buf.put_u32(entry.ttl);
+        buf.put_u32(entry.msg.len() as u32);
+        buf.put_slice(&entry.msg);
+    }
+    buf.freeze()
+}
+
+/// 解析快照, 返回保存时间及全部条目.
+pub fn decode(mut buf: Bytes) -> Result<(u64, Vec<Entry>)> {
+    if buf.len() < 13 || &buf[..4] != MAGIC {
+        return Err(anyhow!("invalid cache snapshot"));
+    }
+    buf.advance(4);
+    let version = buf.get_u8();
+    if version != VERSION {
+        return Err(anyhow!("unsupported cache snapshot version: {}", version));
+    }
+    let saved_at = buf.get_u64();
+    let mut entries = Vec::new();
+    while buf.has_remaining() {
+        if buf.remaining() < 2 {
+            return Err(anyhow!("truncated cache snapshot"));
+        }
+        let len = buf.get_u16() as usize;
+        if buf.remaining() < len + 16 {
+            return Err(anyhow!("truncated cache snapshot"));
+        }
+        let domain = String::from_utf8(buf.split_to(len).to_vec())?;
+        let inserted_at = buf.get_u64();
+        let ttl = buf.get_u32();
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < len {
+            return Err(anyhow!("truncated cache snapshot"));
+        }
+        let msg = buf.split_to(len);
+        entries.push(Entry { domain, inserted_at, ttl, msg });
+    }
+    Ok((saved_at, entries))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(domain: &str, ttl: u32) -> Entry {
+        Entry { domain: domain.to_string(), inserted_at: 1_700_000_000, ttl, msg: Bytes::from_static(b"\x12\x34\x81\x80") }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let entries = vec![entry("example.com", 300), entry("www.example.org", 42)];
+        let (saved_at, decoded) = decode(encode(1_700_000_100, &entries)).unwrap();
+        assert_eq!(saved_at, 1_700_000_100);
+        assert_eq!(decoded, entries);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(decode(Bytes::from_static(b"nope")).is_err());
+        let data = encode(1, &[entry("example.com", 300)]);
+        assert!(decode(data.slice(..data.len() - 1)).is_err());
+        let mut data = BytesMut::from(&encode(1, &[])[..]);
+        data[4] = 9;
+        assert!(decode(data.freeze()).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::{sync::Arc, time::Duration};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use zconfig::Cache as CacheConf;
+use crate::{lru::LruStore, memory::MemoryStore, redis::RedisStore};
+
+/// 缓存存储, 值为应答报文, 按TTL过期.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+
+    /// 读取未过期的值及剩余TTL
+    async fn get(&self, key: &str) -> Option<(Bytes, Duration)>;
+
+    /// 剩余TTL, 不计入访问统计
+    async fn ttl(&self, key: &str) -> Option<Duration>;
+
+    async fn insert(&self, key: String, value: Bytes, ttl: Duration) -> bool;
+
+    async fn remove(&self, key: &str);
+
+    /// 等待写入生效
+    async fn wait(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub type Store = Arc<dyn CacheStore>;
+
+pub fn build(conf: &CacheConf) -> Result<Store> {
+    match conf.backend.as_deref().unwrap_or("memory") {
+        "memory" => Ok(Arc::new(MemoryStore::new(conf.max_size)?)),
+        "lru" => Ok(Arc::new(LruStore::new(conf.max_bytes.unwrap_or(crate::lru::MAX_BYTES)))),
+        "redis" => {
+            let url = conf.redis.as_deref().ok_or_else(|| anyhow!("redis backend requires `redis` address"))?;
+            Ok(Arc::new(RedisStore::new(url, conf.redis_prefix.clone())?))
+        },
+        backend => Err(anyhow!("unknown cache backend: {}", backend)),
+    }
+}
@@ -1,12 +1,15 @@
-use std::net::Ipv4Addr;
-
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::env;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::overrides::Override;
+use crate::validate::validate;
 
-const CONFIG_FILE: &str = "config/config.json";
+const CONFIG_FILES: [&str; 4] = ["config/config.json", "config/config.toml", "config/config.yaml", "config/config.yml"];
 
 /// 服务 配置
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -15,82 +18,363 @@ pub struct Server {
     pub worker: u16, // 工人数量
     pub qsize: u16, // 消息队列大小
     pub stype: Option<String>, // 服务器类型, 默认UDP.
+    pub shutdown_timeout: Option<u64>, // 关闭时等待处理完队列中请求的时间(秒), 默认5
+    pub ratelimit: Option<RateLimit>, // 按客户端网段限速, 默认不限制
+    pub acl: Option<Acl>, // 允许查询的客户端, 默认不限制
+}
+
+// 访问控制配置, 先匹配deny, 配置allow时只允许其中的客户端
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Acl {
+    pub allow: Option<Vec<String>>, // 允许的客户端网段(CIDR)或IP地址
+    pub deny: Option<Vec<String>>, // 拒绝的客户端网段(CIDR)或IP地址
+    pub action: Option<String>, // 拒绝时: refused(默认)或drop(丢弃)
+}
+
+// 限速配置, 按客户端网段统计
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    pub qps: Option<u32>, // 每秒查询数, 默认不限制
+    pub burst: Option<u32>, // 允许的突发查询数, 默认等于qps
+    pub rrl: Option<u32>, // 每秒相同应答(域名、类型及应答码相同)数, 默认不限制
+    pub ipv4_prefix: Option<u8>, // IPv4网段前缀长度, 默认24
+    pub ipv6_prefix: Option<u8>, // IPv6网段前缀长度, 默认56
+    pub action: Option<String>, // 超出限制时: drop(默认, 丢弃), refused, truncate(TC=1)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Cache {
-    pub max_size: u16, // 
+    pub max_size: usize, // memory后端的最大条目数
+    pub backend: Option<String>, // 缓存后端: memory(默认), lru(按字节数限制), redis(多个实例共享)
+    pub max_bytes: Option<u64>, // lru后端的内存上限(字节), 默认64MB
+    pub redis: Option<String>, // redis后端地址, 如 redis://127.0.0.1:6379/0
+    pub redis_prefix: Option<String>, // redis键前缀, 默认 zzdns:
     pub max_ttl: u16, //
     pub min_ttl: u16, //
-    pub preload_file: String,
+    pub preload_file: Option<String>, // 预加载的域名列表
+    pub preload_files: Option<Vec<String>>, // 更多预加载文件或 http(s) 地址, 文件名支持 `*` `?` 通配符, 内容变化时自动重新加载
+    pub popular_file: Option<String>, // 热门域名列表, 按查询次数统计, 启动时预加载并在缓存过期前主动刷新
+    pub popular_size: Option<u16>, // 热门域名数量, 默认1000
+    pub snapshot_file: Option<String>, // 缓存快照文件, 启动时加载, 定期及退出时保存
+    pub snapshot_interval: Option<u64>, // 快照保存间隔(秒), 默认300
+    pub refresh_concurrency: Option<usize>, // 后台同时解析的域名数, 默认16
+    pub refresh_rate: Option<u32>, // 后台每秒最多开始解析的域名数, 默认50
+}
+
+impl Cache {
+
+    /// preload_file 与 preload_files 合并后的预加载文件列表
+    pub fn preload_files(&self) -> Vec<String> {
+        self.preload_file.iter().chain(self.preload_files.iter().flatten()).cloned().collect()
+    }
+}
+
+/// redis后端地址, 格式为 `redis://[:password@]host[:port][/db]`, 也可直接使用 `host:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisAddr {
+    pub addr: String, // host:port, 默认端口6379
+    pub password: Option<String>,
+    pub db: Option<u32>,
+}
+
+impl std::str::FromStr for RedisAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("redis://").unwrap_or(url);
+        let (auth, rest) = match rest.rsplit_once('@') {
+            Some((auth, rest)) => (Some(auth), rest),
+            None => (None, rest),
+        };
+        let (host, db) = match rest.split_once('/') {
+            Some((host, db)) if !db.is_empty() => (host, Some(db.parse::<u32>().map_err(|_| anyhow!("invalid redis db: {}", db))?)),
+            Some((host, _)) => (host, None),
+            None => (rest, None),
+        };
+        if host.is_empty() {
+            return Err(anyhow!("invalid redis address: {}", url));
+        }
+        let addr = match host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() && !host.ends_with(']') => host.to_string(),
+            _ => format!("{}:6379", host),
+        };
+        let password = auth.map(|a| a.strip_prefix(':').unwrap_or(a).to_string());
+        Ok(Self { addr, password, db })
+    }
+}
+
+/// 上游类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamType {
+    Udp,
+    Tcp,
+    Tls,
+    Https,
+    Quic,
+    Recursive, // 从根服务器迭代解析, host为根提示文件, "."使用内置地址
+}
+
+impl UpstreamType {
+
+    fn from_scheme(scheme: &str) -> Option<Self> {
+        match scheme {
+            "udp" => Some(Self::Udp),
+            "tcp" => Some(Self::Tcp),
+            "tls" => Some(Self::Tls),
+            "http" | "https" => Some(Self::Https),
+            "quic" => Some(Self::Quic),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::Tls | Self::Quic => 853,
+            Self::Https => 443,
+            _ => 53,
+        }
+    }
+}
+
+impl fmt::Display for UpstreamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Udp => "udp",
+            Self::Tcp => "tcp",
+            Self::Tls => "tls",
+            Self::Https => "https",
+            Self::Quic => "quic",
+            Self::Recursive => "recursive",
+        };
+        f.write_str(name)
+    }
 }
 
 // 上游服务器配置
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Upstream {
-    pub uptype: Option<String>, 
-    pub host: String,
-    pub port: Option<u16>
+    pub uptype: Option<UpstreamType>, // 未配置时按host推断
+    pub host: String, // IP地址(可带端口), 如 1.1.1.1, 1.1.1.1:5353, 2606:4700::1111, [::1]:5353; 或带scheme, 如 tcp://8.8.8.8, https://dns.google/dns-query
+    pub port: Option<u16> // host中未指定端口时使用; recursive类型时为权威服务器的端口
+}
+
+// 解析IP地址, 可带端口, IPv6带端口时需用[]括起
+fn socket_addr(s: &str, port: u16) -> Option<SocketAddr> {
+    let s = s.trim_end_matches('/');
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    let ip = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+    ip.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, port))
 }
 
 impl Upstream {
 
-    pub fn get_type(&self) -> String { 
-        if self.uptype.is_some() {
-            return self.uptype.clone().unwrap();
+    // 上游类型及地址, 配置的uptype与host的scheme须一致
+    fn parse(&self) -> Result<(UpstreamType, String)> {
+        if self.uptype == Some(UpstreamType::Recursive) {
+            return Ok((UpstreamType::Recursive, self.host.clone()));
         }
-        match self.get_host().parse::<SocketAddr>() {
-            Ok(_) => "udp".to_string(),
-            Err(_) => "https".to_string(),
+        let host = self.host.trim();
+        let scheme = match host.split_once("://") {
+            Some((scheme, _)) => Some(UpstreamType::from_scheme(&scheme.to_ascii_lowercase())
+                .ok_or_else(|| anyhow!("unknown scheme {:?} in upstream host {:?}", scheme, self.host))?),
+            None => None,
+        };
+        if let (Some(uptype), Some(scheme)) = (self.uptype, scheme) {
+            if uptype != scheme {
+                return Err(anyhow!("uptype {} conflicts with the scheme of upstream host {:?}", uptype, self.host));
+            }
+        }
+        let invalid = |e: &dyn fmt::Display| anyhow!("invalid upstream host {:?}, expected an IP address or URL: {}", self.host, e);
+        match self.uptype.or(scheme) {
+            Some(UpstreamType::Https) => {
+                let url = if scheme.is_some() { host.to_string() } else { format!("https://{}", host) };
+                let url = url::Url::parse(&url).map_err(|e| invalid(&e))?;
+                if url.host_str().is_none() {
+                    return Err(invalid(&"missing host"));
+                }
+                Ok((UpstreamType::Https, url.to_string()))
+            },
+            Some(uptype) => {
+                let addr = host.split_once("://").map_or(host, |(_, addr)| addr);
+                let addr = socket_addr(addr, self.port.unwrap_or(uptype.default_port()))
+                    .ok_or_else(|| invalid(&format!("{} upstreams require an IP address", uptype)))?;
+                Ok((uptype, addr.to_string()))
+            },
+            None => {
+                if let Some(addr) = socket_addr(host, self.port.unwrap_or(53)) {
+                    return Ok((UpstreamType::Udp, addr.to_string()));
+                }
+                url::Url::parse(host).map_err(|e| invalid(&e))?;
+                Err(invalid(&"unknown scheme"))
+            },
         }
     }
 
-    pub fn get_host(&self) -> String {
-        let host:Option<String>= match self.host.parse::<Ipv4Addr>() {
-            Ok(h) => Some(format!("{:?}:{}", h, self.port.unwrap_or(53))), 
-            Err(_) => None,
-        };
+    /// 上游类型, 未配置时按host推断: 带scheme时按scheme, 否则为udp
+    pub fn get_type(&self) -> Result<UpstreamType> {
+        Ok(self.parse()?.0)
+    }
 
-        if let Some(r) = host {
-            r
-        }else{
-            url::Url::parse(self.host.as_str()).unwrap().to_string()
-        }
-        
+    /// 上游地址: https为URL, recursive为根提示文件, 其余为 ip:port
+    pub fn get_host(&self) -> Result<String> {
+        Ok(self.parse()?.1)
     }
     
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// 域名过滤配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Filter {
+    pub blocklists: Vec<String>, // 拦截列表文件, 支持hosts/域名列表/Adblock格式
+    pub allowlists: Option<Vec<String>>, // 放行列表文件, 优先于拦截列表
+    pub response: Option<String>, // 拦截应答方式: nxdomain(默认), null, refused
+    pub policies: Option<Vec<FilterPolicy>>, // 按客户端网段生效的过滤策略
+}
+
+// 客户端过滤策略, 匹配网段的客户端使用该策略代替默认策略
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FilterPolicy {
+    pub name: String,
+    pub clients: Vec<String>, // 客户端网段(CIDR)或IP地址
+    pub blocklists: Vec<String>,
+    pub allowlists: Option<Vec<String>>,
+    pub response: Option<String>,
+}
+
+// 本地记录配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Local {
+    pub ttl: Option<u32>, // 本地记录默认TTL, 默认60
+    pub records: Option<Vec<LocalRecord>>,
+    pub hosts_files: Option<Vec<String>>, // hosts格式文件, 如 /etc/hosts
+}
+
+// 本地记录, 支持 A/AAAA/CNAME/TXT/PTR/SRV/MX
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalRecord {
+    pub name: String, // 域名, 支持通配符, 如 *.home.lan
+    pub rtype: String, // 记录类型
+    pub value: String, // 记录值, 格式同zone文件, 如MX为 "10 mail.home.lan"
+    pub ttl: Option<u32>,
+}
+
+// 权威区域配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Zone {
+    pub origin: String, // 区域名称, 如 home.lan
+    pub file: String, // RFC 1035格式的zone文件
+}
+
+// 改写规则
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rewrite {
+    pub name: String, // 匹配的域名, 支持通配符 *.example.com
+    pub target: Option<String>, // 改写为该域名解析, 应答记录名称仍为原域名
+    pub answers: Option<Vec<String>>, // 强制应答记录, 如 "A 10.0.0.1"
+    pub strip: Option<Vec<String>>, // 移除的记录类型, 如 ["AAAA", "HTTPS"]
+    pub ttl: Option<u32>, // 强制应答记录的TTL, 默认60
+}
+
+// DNSSEC验证配置, 配置后验证上游应答
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dnssec {
+    pub trust_anchors: Option<Vec<String>>, // DS格式的信任锚, 默认为根区域KSK
+}
+
+// 管理接口配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Admin {
+    pub listen: String, // HTTP监听地址, 如 127.0.0.1:8053
+}
+
+// 查询日志配置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueryLog {
+    pub file: String, // 日志文件
+    pub format: Option<String>, // json(默认, 每行一条)或csv
+    pub max_size: Option<u64>, // 单个文件的最大字节数, 超过后轮转, 默认100MB, 0为不限制
+    pub rotate_interval: Option<u64>, // 按时间轮转的间隔(秒), 如86400, 默认不按时间轮转
+    pub max_files: Option<usize>, // 保留的历史文件数, 默认7
+    pub anonymize: Option<bool>, // 隐藏客户端地址, IPv4只保留/24, IPv6只保留/48
+}
+
+// dnstap输出配置, socket与file二选一
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dnstap {
+    pub socket: Option<String>, // 接收端的Unix socket, 如 /var/run/dnstap.sock
+    pub file: Option<String>, // 输出文件, 启动时覆盖
+    pub identity: Option<String>, // 服务器标识, 默认zzdns
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub server: Server,
     pub upstreams: Vec<Upstream>,
     pub cache: Cache,
+    pub filter: Option<Filter>,
+    pub local: Option<Local>,
+    pub zones: Option<Vec<Zone>>,
+    pub rewrites: Option<Vec<Rewrite>>,
+    pub dnssec: Option<Dnssec>,
+    pub admin: Option<Admin>,
+    pub querylog: Option<QueryLog>,
+    pub dnstap: Option<Dnstap>,
+}
+
+/// 配置文件路径, 依次查找当前目录及上级目录下的 config/config.json(.toml, .yaml, .yml)
+pub fn config_path() -> PathBuf {
+    let current_dir = match env::current_dir(){
+        Ok(path) => path,
+        Err(_err) => PathBuf::from("."),
+    };
+    for dir in [current_dir.clone(), current_dir.join("../")] {
+        for file in CONFIG_FILES {
+            let config_path = dir.join(file);
+            if config_path.exists() {
+                return config_path;
+            }
+        }
+    }
+    current_dir.join("../").join(CONFIG_FILES[0])
+}
+
+/// 读取并解析配置文件
+pub fn load(path: &Path) -> Result<Config> {
+    load_with(path, &[])
+}
+
+// 按扩展名解析配置文件: .toml, .yaml/.yml, 其余为JSON
+fn parse(path: &Path, config_str: &str) -> Result<Value> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "toml" => toml::from_str::<Value>(config_str).map_err(|e| anyhow!("{}", e)),
+        "yaml" | "yml" => serde_yaml::from_str::<Value>(config_str).map_err(|e| anyhow!("{}", e)),
+        _ => serde_json::from_str::<Value>(config_str).map_err(|e| anyhow!("{}", e)),
+    }
+}
+
+/// 读取配置文件, 按顺序覆盖配置项后解析并校验
+pub fn load_with(path: &Path, overrides: &[Override]) -> Result<Config> {
+    let config_str = fs::read_to_string(path).map_err(|e| anyhow!("Fail to read config file(:{:?}), error:{}", path, e))?;
+    let mut value = parse(path, &config_str).map_err(|e| anyhow!("Fail to parse config {:?}, error:{}", path, e))?;
+    for item in overrides {
+        item.apply(&mut value).map_err(|e| anyhow!("Fail to override config, error:{}", e))?;
+    }
+    let conf: Config = serde_path_to_error::deserialize(value)
+        .map_err(|e| anyhow!("Invalid config {:?}, error:{}: {}", path, e.path(), e.inner()))?;
+    validate(&conf).map_err(|e| anyhow!("Invalid config {:?}, {}", path, e))?;
+    Ok(conf)
 }
 
 /// read configuration from json.
 macro_rules! read_config { 
-    ($struct: ident) => ({ 
-        let current_dir = match env::current_dir(){
-            Ok(path) => path,
-            Err(_err) => PathBuf::from("."),
-        };
-        
-        let config_path = current_dir.join(CONFIG_FILE).into_os_string();
-        
-        let config_str = match fs::read_to_string(&config_path) {
-            Ok(str) => str,
-            Err(_) => {
-                let config_path = current_dir.join("../").join(CONFIG_FILE).into_os_string();
-                match fs::read_to_string(&config_path) {
-                    Ok(str) => str,
-                    Err(err) => panic!("Fail to read config file(:{:?}), error:{}", config_path, err),
-                }
-            },
-        };
-        match serde_json::from_str::<$struct>(&config_str.as_str()){
+    () => ({ 
+        match load(&config_path()) {
             Ok(result) => result,
-            Err(err) => panic!("Fail to parse config, error:{}", err),
+            Err(err) => panic!("{}", err),
         }
     })
 }
@@ -98,17 +382,116 @@ macro_rules! read_config {
 
 lazy_static! { 
     #[derive(Debug)]
-    pub static ref CONFIG:Config = read_config!(Config);
+    pub static ref CONFIG:Config = read_config!();
 }
 
 
 #[cfg(test)]
 mod test {
-    use crate::config::CONFIG;
+    use crate::config::{load_with, Upstream, UpstreamType, CONFIG};
+    use crate::overrides::Override;
     #[test]
     fn test_config() {
         for item in CONFIG.upstreams.iter() {
             println!("{:?}, {:?}", item.get_host(), item.get_type());
         }
     }
+
+    #[test]
+    fn test_upstream() {
+        let upstream = |uptype: Option<UpstreamType>, host: &str, port: Option<u16>| {
+            let conf = Upstream { uptype, host: host.to_string(), port };
+            conf.get_type().and_then(|t| Ok((t, conf.get_host()?))).map_err(|e| e.to_string())
+        };
+        let ok = |t: UpstreamType, h: &str| Ok((t, h.to_string()));
+        assert_eq!(upstream(None, "1.1.1.1", None), ok(UpstreamType::Udp, "1.1.1.1:53"));
+        assert_eq!(upstream(None, "1.1.1.1:5353", None), ok(UpstreamType::Udp, "1.1.1.1:5353"));
+        assert_eq!(upstream(None, "2606:4700:4700::1111", None), ok(UpstreamType::Udp, "[2606:4700:4700::1111]:53"));
+        assert_eq!(upstream(None, "[::1]:5353", None), ok(UpstreamType::Udp, "[::1]:5353"));
+        assert_eq!(upstream(None, "[::1]", Some(5300)), ok(UpstreamType::Udp, "[::1]:5300"));
+        assert_eq!(upstream(None, "tcp://8.8.8.8", None), ok(UpstreamType::Tcp, "8.8.8.8:53"));
+        assert_eq!(upstream(None, "udp://[2001:4860:4860::8888]:5353/", None), ok(UpstreamType::Udp, "[2001:4860:4860::8888]:5353"));
+        assert_eq!(upstream(None, "tls://1.1.1.1", None), ok(UpstreamType::Tls, "1.1.1.1:853"));
+        assert_eq!(upstream(None, "https://dns.google/dns-query", None), ok(UpstreamType::Https, "https://dns.google/dns-query"));
+        assert_eq!(upstream(Some(UpstreamType::Tcp), "9.9.9.9", None), ok(UpstreamType::Tcp, "9.9.9.9:53"));
+        assert_eq!(upstream(Some(UpstreamType::Https), "dns.google/dns-query", None), ok(UpstreamType::Https, "https://dns.google/dns-query"));
+        assert_eq!(upstream(Some(UpstreamType::Recursive), ".", None), ok(UpstreamType::Recursive, "."));
+
+        assert!(upstream(None, "dns.google", None).unwrap_err().starts_with("invalid upstream host \"dns.google\""));
+        assert!(upstream(None, "tcp://dns.google", None).unwrap_err().ends_with("tcp upstreams require an IP address"));
+        assert_eq!(upstream(None, "ftp://1.1.1.1", None).unwrap_err(), "unknown scheme \"ftp\" in upstream host \"ftp://1.1.1.1\"");
+        assert_eq!(upstream(Some(UpstreamType::Udp), "https://dns.google/dns-query", None).unwrap_err(),
+            "uptype udp conflicts with the scheme of upstream host \"https://dns.google/dns-query\"");
+        assert!(serde_json::from_str::<Upstream>(r#"{"uptype": "doh", "host": "1.1.1.1"}"#).is_err());
+    }
+
+    #[test]
+    fn test_load_with() {
+        let path = std::env::temp_dir().join(format!("zzdns-config-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"server": {"port": 53, "worker": 1, "qsize": 16},
+            "cache": {"max_size": 100, "max_ttl": 600, "min_ttl": 60}, "upstreams": [{"host": "1.1.1.1"}]}"#).unwrap();
+        let overrides = vec![Override::parse("server.port=5353").unwrap(), Override::parse("admin.listen=127.0.0.1:8053").unwrap()];
+        let conf = load_with(&path, &overrides).unwrap();
+        assert_eq!(conf.server.port, 5353);
+        assert_eq!(conf.admin.unwrap().listen, "127.0.0.1:8053");
+        let err = load_with(&path, &[Override::parse("server.port=abc").unwrap()]).unwrap_err();
+        assert!(err.to_string().ends_with("error:server.port: invalid type: string \"abc\", expected u16"), "{}", err);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_formats() {
+        let dir = std::env::temp_dir();
+        let toml = dir.join(format!("zzdns-config-{}.toml", std::process::id()));
+        std::fs::write(&toml, r#"
+            # 服务配置
+            [server]
+            port = 53
+            worker = 4
+            qsize = 1024
+
+            [cache]
+            max_size = 4096
+            max_ttl = 600
+            min_ttl = 300
+
+            [[upstreams]]
+            host = "https://dns.alidns.com/dns-query"
+
+            [[upstreams]]
+            host = "1.1.1.1"
+        "#).unwrap();
+        let yaml = dir.join(format!("zzdns-config-{}.yaml", std::process::id()));
+        std::fs::write(&yaml, "
+# 服务配置
+server:
+  port: 53
+  worker: 4
+  qsize: 1024
+cache:
+  max_size: 4096
+  max_ttl: 600
+  min_ttl: 300
+upstreams:
+  - host: https://dns.alidns.com/dns-query
+  - host: 1.1.1.1
+").unwrap();
+        for path in [&toml, &yaml] {
+            let conf = load_with(path, &[]).unwrap();
+            assert_eq!(conf.server.worker, 4);
+            assert_eq!(conf.cache.min_ttl, 300);
+            assert_eq!(conf.upstreams[0].get_host().unwrap(), "https://dns.alidns.com/dns-query");
+            assert_eq!(conf.upstreams[1].get_host().unwrap(), "1.1.1.1:53");
+        }
+
+        // 所有校验错误一次报告
+        std::fs::write(&yaml, "server: {port: 0, worker: 1, qsize: 16}\ncache: {max_size: 1, max_ttl: 60, min_ttl: 600}\nupstreams: []\n").unwrap();
+        let err = load_with(&yaml, &[]).unwrap_err().to_string();
+        assert!(err.contains("3 error(s):"), "{}", err);
+        assert!(err.contains("server.port: must be between 1 and 65535"));
+        assert!(err.contains("cache.min_ttl: must not exceed cache.max_ttl (600 > 60)"));
+        assert!(err.contains("upstreams: must not be empty"));
+        let _ = std::fs::remove_file(toml);
+        let _ = std::fs::remove_file(yaml);
+    }
 }
\ No newline at end of file
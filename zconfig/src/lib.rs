@@ -1,8 +1,25 @@
 #[macro_use]extern crate lazy_static;
 
 mod config;
+mod overrides;
+mod validate;
 
 pub use config::CONFIG;
-pub use config::Upstream;
+pub use config::{config_path, load, load_with, Config};
+pub use overrides::Override;
+pub use config::{Upstream, UpstreamType};
 pub use config::Server;
-pub use config::Cache;
\ No newline at end of file
+pub use config::RateLimit;
+pub use config::Acl;
+pub use config::Cache;
+pub use config::RedisAddr;
+pub use config::Filter;
+pub use config::FilterPolicy;
+pub use config::Local;
+pub use config::LocalRecord;
+pub use config::Zone;
+pub use config::Rewrite;
+pub use config::Dnssec;
+pub use config::Admin;
+pub use config::QueryLog;
+pub use config::Dnstap;
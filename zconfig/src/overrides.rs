@@ -0,0 +1,67 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+/// 覆盖配置文件中的单个配置项, 如 `server.port=5353`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub path: Vec<String>,
+    pub value: Value,
+}
+
+impl Override {
+
+    pub fn new(path: &str, value: Value) -> Self {
+        Self { path: path.split('.').map(|s| s.to_string()).collect(), value }
+    }
+
+    /// 解析 `path=value`, 值按JSON解析, 失败时作为字符串
+    pub fn parse(s: &str) -> Result<Self> {
+        let (path, value) = s.split_once('=').ok_or_else(|| anyhow!("expected <path>=<value>, got {:?}", s))?;
+        let path = path.trim();
+        if path.is_empty() || path.split('.').any(|p| p.is_empty()) {
+            return Err(anyhow!("invalid config path {:?}", path));
+        }
+        let value = value.trim();
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        Ok(Self::new(path, value))
+    }
+
+    /// 写入配置, 缺少的中间项创建为对象
+    pub fn apply(&self, config: &mut Value) -> Result<()> {
+        let mut node = config;
+        for (i, key) in self.path.iter().enumerate() {
+            if node.is_null() {
+                *node = Value::Object(Map::new());
+            }
+            let object = node.as_object_mut()
+                .ok_or_else(|| anyhow!("config path {:?} is not an object", self.path[..i].join(".")))?;
+            node = object.entry(key.clone()).or_insert(Value::Null);
+        }
+        *node = self.value.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Override::parse("server.port=5353").unwrap(), Override::new("server.port", json!(5353)));
+        assert_eq!(Override::parse("admin.listen=127.0.0.1:8053").unwrap().value, json!("127.0.0.1:8053"));
+        assert_eq!(Override::parse("upstreams=[{\"host\":\"1.1.1.1\"}]").unwrap().value, json!([{"host": "1.1.1.1"}]));
+        assert!(Override::parse("server.port").is_err());
+        assert!(Override::parse("server..port=1").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config = json!({"server": {"port": 53, "worker": 1}, "upstreams": []});
+        Override::parse("server.port=5353").unwrap().apply(&mut config).unwrap();
+        Override::parse("admin.listen=127.0.0.1:8053").unwrap().apply(&mut config).unwrap();
+        assert_eq!(config, json!({"server": {"port": 5353, "worker": 1}, "upstreams": [], "admin": {"listen": "127.0.0.1:8053"}}));
+        assert!(Override::parse("upstreams.host=1.1.1.1").unwrap().apply(&mut config).is_err());
+    }
+}
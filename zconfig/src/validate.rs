@@ -0,0 +1,263 @@
+use std::{net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use domain::base::{Dname, Rtype};
+use ipnet::IpNet;
+use crate::config::{Config, RedisAddr, Rewrite, Upstream};
+
+// 收集校验错误, 每条错误带配置项路径
+#[derive(Default)]
+struct Errors(Vec<String>);
+
+impl Errors {
+
+    fn check(&mut self, ok: bool, path: &str, msg: &str) {
+        if !ok {
+            self.0.push(format!("{}: {}", path, msg));
+        }
+    }
+
+    fn one_of(&mut self, value: Option<&str>, path: &str, values: &[&str]) {
+        if let Some(v) = value {
+            self.check(values.contains(&v.to_ascii_lowercase().as_str()), path,
+                &format!("unknown value {:?}, expected one of {}", v, values.join(", ")));
+        }
+    }
+}
+
+fn upstream(errors: &mut Errors, path: &str, conf: &Upstream) {
+    errors.check(conf.port != Some(0), &format!("{}.port", path), "must be between 1 and 65535");
+    if conf.host.trim().is_empty() {
+        errors.check(false, &format!("{}.host", path), "must not be empty");
+        return;
+    }
+    if let Err(e) = conf.get_type() {
+        errors.check(false, &format!("{}.host", path), &e.to_string());
+    }
+}
+
+fn is_network(s: &str) -> bool {
+    s.parse::<IpNet>().is_ok() || s.parse::<IpAddr>().is_ok()
+}
+
+fn is_dname(name: &str) -> bool {
+    Dname::<Vec<u8>>::from_str(name.trim_end_matches('.')).is_ok()
+}
+
+// 本地记录及改写应答支持的类型
+const RECORD_TYPES: [&str; 7] = ["a", "aaaa", "cname", "txt", "ptr", "srv", "mx"];
+
+// 记录值, 格式与zlocal解析的一致, rtype须已校验
+fn record(errors: &mut Errors, path: &str, rtype: &str, value: &str) {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let ok = match (rtype.to_ascii_uppercase().as_str(), fields.as_slice()) {
+        ("A", [addr]) => addr.parse::<Ipv4Addr>().is_ok(),
+        ("AAAA", [addr]) => addr.parse::<Ipv6Addr>().is_ok(),
+        ("CNAME" | "PTR", [name]) => is_dname(name),
+        ("TXT", _) => true,
+        ("MX", [preference, exchange]) => preference.parse::<u16>().is_ok() && is_dname(exchange),
+        ("SRV", [priority, weight, port, target]) =>
+            [priority, weight, port].iter().all(|v| v.parse::<u16>().is_ok()) && is_dname(target),
+        _ => false,
+    };
+    errors.check(ok, path, &format!("invalid {} record value {:?}", rtype.to_ascii_uppercase(), value));
+}
+
+fn rewrite(errors: &mut Errors, path: &str, conf: &Rewrite) {
+    errors.check(is_dname(&conf.name), &format!("{}.name", path), &format!("invalid domain name {:?}", conf.name));
+    if let Some(target) = &conf.target {
+        errors.check(is_dname(target), &format!("{}.target", path), &format!("invalid domain name {:?}", target));
+    }
+    errors.check(conf.target.is_none() || conf.answers.as_ref().is_none_or(|a| a.is_empty()), path,
+        "can not have both target and answers");
+    for (i, answer) in conf.answers.iter().flatten().enumerate() {
+        let path = format!("{}.answers[{}]", path, i);
+        match answer.trim().split_once(char::is_whitespace) {
+            Some((rtype, value)) if RECORD_TYPES.contains(&rtype.to_ascii_lowercase().as_str()) =>
+                record(errors, &path, rtype, value.trim()),
+            _ => errors.check(false, &path, &format!("invalid answer {:?}, expected \"<type> <value>\" with type one of {}",
+                answer, RECORD_TYPES.join(", "))),
+        }
+    }
+    for (i, rtype) in conf.strip.iter().flatten().enumerate() {
+        errors.check(Rtype::from_str(&rtype.to_ascii_uppercase()).is_ok(), &format!("{}.strip[{}]", path, i),
+            &format!("unknown record type {:?}", rtype));
+    }
+}
+
+/// 校验配置, 返回所有错误.
+/// 类型及结构错误(如端口为字符串)在解析时发现, 每次只报告第一个.
+pub fn validate(conf: &Config) -> Result<()> {
+    let mut errors = Errors::default();
+
+    errors.check(conf.server.port != 0, "server.port", "must be between 1 and 65535");
+    errors.check(conf.server.worker > 0, "server.worker", "must be at least 1");
+    errors.check(conf.server.qsize > 0, "server.qsize", "must be at least 1");
+    if let Some(ratelimit) = &conf.server.ratelimit {
+        errors.check(ratelimit.qps != Some(0), "server.ratelimit.qps", "must be at least 1");
+        errors.check(ratelimit.burst != Some(0), "server.ratelimit.burst", "must be at least 1");
+        errors.check(ratelimit.rrl != Some(0), "server.ratelimit.rrl", "must be at least 1");
+        errors.check(ratelimit.ipv4_prefix.unwrap_or(24) <= 32, "server.ratelimit.ipv4_prefix", "must be between 0 and 32");
+        errors.check(ratelimit.ipv6_prefix.unwrap_or(56) <= 128, "server.ratelimit.ipv6_prefix", "must be between 0 and 128");
+        errors.one_of(ratelimit.action.as_deref(), "server.ratelimit.action", &["drop", "refused", "truncate"]);
+    }
+    if let Some(acl) = &conf.server.acl {
+        for (name, nets) in [("allow", &acl.allow), ("deny", &acl.deny)] {
+            for (i, net) in nets.iter().flatten().enumerate() {
+                errors.check(is_network(net), &format!("server.acl.{}[{}]", name, i), &format!("invalid network {:?}", net));
+            }
+        }
+        errors.one_of(acl.action.as_deref(), "server.acl.action", &["refused", "drop"]);
+    }
+
+    let cache = &conf.cache;
+    errors.check(cache.max_size > 0, "cache.max_size", "must be at least 1");
+    errors.check(cache.min_ttl <= cache.max_ttl, "cache.min_ttl",
+        &format!("must not exceed cache.max_ttl ({} > {})", cache.min_ttl, cache.max_ttl));
+    errors.one_of(cache.backend.as_deref(), "cache.backend", &["memory", "lru", "redis"]);
+    if cache.backend.as_deref() == Some("redis") {
+        match &cache.redis {
+            Some(redis) => errors.check(redis.parse::<RedisAddr>().is_ok(), "cache.redis", &format!("invalid address {:?}", redis)),
+            None => errors.check(false, "cache.redis", "required by the redis backend"),
+        }
+    }
+    errors.check(cache.refresh_concurrency != Some(0), "cache.refresh_concurrency", "must be at least 1");
+    errors.check(cache.refresh_rate != Some(0), "cache.refresh_rate", "must be at least 1");
+
+    errors.check(!conf.upstreams.is_empty(), "upstreams", "must not be empty");
+    for (i, conf) in conf.upstreams.iter().enumerate() {
+        upstream(&mut errors, &format!("upstreams[{}]", i), conf);
+    }
+
+    let responses = ["nxdomain", "null", "0.0.0.0", "refused"];
+    if let Some(filter) = &conf.filter {
+        errors.one_of(filter.response.as_deref(), "filter.response", &responses);
+        for (i, policy) in filter.policies.iter().flatten().enumerate() {
+            errors.one_of(policy.response.as_deref(), &format!("filter.policies[{}].response", i), &responses);
+            errors.check(!policy.clients.is_empty(), &format!("filter.policies[{}].clients", i), "must not be empty");
+            for (j, client) in policy.clients.iter().enumerate() {
+                errors.check(is_network(client), &format!("filter.policies[{}].clients[{}]", i, j), &format!("invalid network {:?}", client));
+            }
+        }
+    }
+    if let Some(local) = &conf.local {
+        for (i, rr) in local.records.iter().flatten().enumerate() {
+            errors.check(is_dname(&rr.name), &format!("local.records[{}].name", i), &format!("invalid domain name {:?}", rr.name));
+            let rtype_path = format!("local.records[{}].rtype", i);
+            errors.one_of(Some(&rr.rtype), &rtype_path, &RECORD_TYPES);
+            if RECORD_TYPES.contains(&rr.rtype.to_ascii_lowercase().as_str()) {
+                record(&mut errors, &format!("local.records[{}].value", i), &rr.rtype, rr.value.trim());
+            }
+        }
+    }
+    for (i, conf) in conf.rewrites.iter().flatten().enumerate() {
+        rewrite(&mut errors, &format!("rewrites[{}]", i), conf);
+    }
+    if let Some(admin) = &conf.admin {
+        errors.check(admin.listen.parse::<SocketAddr>().is_ok(), "admin.listen", &format!("invalid address {:?}", admin.listen));
+    }
+    if let Some(querylog) = &conf.querylog {
+        errors.one_of(querylog.format.as_deref(), "querylog.format", &["json", "csv"]);
+    }
+    if let Some(dnstap) = &conf.dnstap {
+        errors.check(dnstap.socket.is_some() != dnstap.file.is_some(), "dnstap", "requires exactly one of socket and file");
+    }
+
+    if errors.0.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!("{} error(s):\n  {}", errors.0.len(), errors.0.join("\n  ")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(json: serde_json::Value) -> Config {
+        let mut base = serde_json::json!({
+            "server": {"port": 53, "worker": 1, "qsize": 16},
+            "cache": {"max_size": 100, "max_ttl": 600, "min_ttl": 60},
+            "upstreams": [{"host": "1.1.1.1"}, {"host": "https://dns.alidns.com/dns-query"}, {"uptype": "recursive", "host": "."}]
+        });
+        for (k, v) in json.as_object().unwrap() {
+            base[k] = v.clone();
+        }
+        serde_json::from_value(base).unwrap()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert!(validate(&config(serde_json::json!({}))).is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        let conf = config(serde_json::json!({
+            "server": {"port": 0, "worker": 1, "qsize": 16},
+            "cache": {"max_size": 100, "max_ttl": 60, "min_ttl": 600, "backend": "redis"},
+            "upstreams": [{"host": "1.1.1.1", "port": 0}, {"host": "dns.google/dns-query"}, {"host": "quic://dns.adguard-dns.com"}],
+            "admin": {"listen": "localhost"},
+            "dnstap": {"socket": "a", "file": "b"}
+        }));
+        let err = validate(&conf).unwrap_err().to_string();
+        let lines: Vec<&str> = err.lines().skip(1).map(|l| l.trim()).collect();
+        assert!(err.starts_with("8 error(s):"), "{}", err);
+        assert_eq!(lines[0], "server.port: must be between 1 and 65535");
+        assert_eq!(lines[1], "cache.min_ttl: must not exceed cache.max_ttl (600 > 60)");
+        assert_eq!(lines[2], "cache.redis: required by the redis backend");
+        assert_eq!(lines[3], "upstreams[0].port: must be between 1 and 65535");
+        assert!(lines[4].starts_with("upstreams[1].host: invalid upstream host \"dns.google/dns-query\""));
+        assert!(lines[5].ends_with("quic upstreams require an IP address"), "{}", lines[5]);
+        assert_eq!(lines[6], "admin.listen: invalid address \"localhost\"");
+        assert_eq!(lines[7], "dnstap: requires exactly one of socket and file");
+
+        let conf = config(serde_json::json!({"upstreams": []}));
+        assert_eq!(validate(&conf).unwrap_err().to_string(), "1 error(s):\n  upstreams: must not be empty");
+
+        let conf = config(serde_json::json!({
+            "server": {"port": 53, "worker": 1, "qsize": 16, "ratelimit": {"qps": 0, "ipv4_prefix": 33, "action": "slip"}}
+        }));
+        let err = validate(&conf).unwrap_err().to_string();
+        assert!(err.starts_with("3 error(s):"), "{}", err);
+        assert!(err.contains("server.ratelimit.qps: must be at least 1"));
+        assert!(err.contains("server.ratelimit.ipv4_prefix: must be between 0 and 32"));
+        assert!(err.contains("server.ratelimit.action: unknown value \"slip\", expected one of drop, refused, truncate"));
+
+        let conf = config(serde_json::json!({
+            "server": {"port": 53, "worker": 1, "qsize": 16, "acl": {"allow": ["10.0.0.0/8", "::1"], "deny": ["10.0.0.0/33"]}}
+        }));
+        assert_eq!(validate(&conf).unwrap_err().to_string(), "1 error(s):\n  server.acl.deny[0]: invalid network \"10.0.0.0/33\"");
+
+        let conf = config(serde_json::json!({
+            "filter": {"blocklists": [], "policies": [{"name": "kids", "clients": ["192.168.1.0/24", "192.168.1.300"], "blocklists": []}]},
+            "local": {"records": [
+                {"name": "nas.home.lan", "rtype": "a", "value": "10.0.0.2"},
+                {"name": "mail.home.lan", "rtype": "MX", "value": "mail.home.lan"},
+                {"name": "*.home.lan", "rtype": "NS", "value": "ns.home.lan"}
+            ]},
+            "rewrites": [
+                {"name": "example.com", "answers": ["A 10.0.0.1", "AAAA 10.0.0.1", "10.0.0.1"], "strip": ["HTTPS", "BOGUS"]},
+                {"name": "bad..name", "target": "example.net", "answers": ["A 10.0.0.1"]}
+            ]
+        }));
+        let err = validate(&conf).unwrap_err().to_string();
+        let lines: Vec<&str> = err.lines().skip(1).map(|l| l.trim()).collect();
+        assert!(err.starts_with("8 error(s):"), "{}", err);
+        assert_eq!(lines[0], "filter.policies[0].clients[1]: invalid network \"192.168.1.300\"");
+        assert_eq!(lines[1], "local.records[1].value: invalid MX record value \"mail.home.lan\"");
+        assert_eq!(lines[2], "local.records[2].rtype: unknown value \"NS\", expected one of a, aaaa, cname, txt, ptr, srv, mx");
+        assert_eq!(lines[3], "rewrites[0].answers[1]: invalid AAAA record value \"10.0.0.1\"");
+        assert!(lines[4].starts_with("rewrites[0].answers[2]: invalid answer \"10.0.0.1\""), "{}", lines[4]);
+        assert_eq!(lines[5], "rewrites[0].strip[1]: unknown record type \"BOGUS\"");
+        assert_eq!(lines[6], "rewrites[1].name: invalid domain name \"bad..name\"");
+        assert_eq!(lines[7], "rewrites[1]: can not have both target and answers");
+
+        let redis = |addr: &str| config(serde_json::json!({
+            "cache": {"max_size": 100, "max_ttl": 600, "min_ttl": 60, "backend": "redis", "redis": addr}
+        }));
+        assert!(validate(&redis("127.0.0.1:6379")).is_ok());
+        assert!(validate(&redis("redis://:secret@10.0.0.1/2")).is_ok());
+        assert_eq!(validate(&redis("redis://host/x")).unwrap_err().to_string(),
+            "1 error(s):\n  cache.redis: invalid address \"redis://host/x\"");
+    }
+}
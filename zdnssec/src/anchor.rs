@@ -0,0 +1,76 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use domain::base::Dname;
+use domain::base::iana::{DigestAlg, SecAlg};
+use domain::rdata::Ds;
+use domain::utils::base16;
+
+// 根区域KSK的DS记录, 见 https://data.iana.org/root-anchors/root-anchors.xml
+pub const ROOT_ANCHORS: [&str; 2] = [
+    ". 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ". 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+/// 信任锚, 同一区域的多条DS记录合并在一起.
+#[derive(Debug, Clone)]
+pub struct Anchor {
+    pub zone: Dname<Bytes>,
+    pub ds: Vec<Ds<Bytes>>,
+}
+
+/// 解析DS格式的信任锚, 如 `. 20326 8 2 E06D...`, 允许包含 `IN DS` 字段.
+pub fn parse_anchor(text: &str) -> Result<(Dname<Bytes>, Ds<Bytes>)> {
+    let fields: Vec<&str> = text.split_whitespace()
+        .filter(|f| !f.eq_ignore_ascii_case("IN") && !f.eq_ignore_ascii_case("DS"))
+        .collect();
+    let invalid = || anyhow!("Invalid trust anchor: {:?}", text);
+    if fields.len() < 5 {
+        return Err(invalid());
+    }
+    let zone = match fields[0].trim_end_matches('.') {
+        "" => Dname::root_bytes(),
+        name => Dname::bytes_from_str(name).map_err(|_| invalid())?,
+    };
+    let key_tag = fields[1].parse::<u16>().map_err(|_| invalid())?;
+    let algorithm = SecAlg::from_int(fields[2].parse::<u8>().map_err(|_| invalid())?);
+    let digest_type = DigestAlg::from_int(fields[3].parse::<u8>().map_err(|_| invalid())?);
+    let digest: Vec<u8> = base16::decode(&fields[4..].concat()).map_err(|_| invalid())?;
+    Ok((zone, Ds::new(key_tag, algorithm, digest_type, Bytes::from(digest))))
+}
+
+pub fn parse_anchors(list: &[String]) -> Result<Vec<Anchor>> {
+    let mut anchors: Vec<Anchor> = Vec::new();
+    for text in list {
+        let (zone, ds) = parse_anchor(text)?;
+        match anchors.iter_mut().find(|a| a.zone == zone) {
+            Some(anchor) => anchor.ds.push(ds),
+            None => anchors.push(Anchor { zone, ds: vec![ds] }),
+        }
+    }
+    Ok(anchors)
+}
+
+
+#[cfg(test)]
+mod test {
+    use domain::base::iana::{DigestAlg, SecAlg};
+    use super::{parse_anchor, parse_anchors, ROOT_ANCHORS};
+
+    #[test]
+    fn test_parse_anchor() {
+        let (zone, ds) = parse_anchor(ROOT_ANCHORS[0]).unwrap();
+        assert!(zone.is_root());
+        assert_eq!(ds.key_tag(), 20326);
+        assert_eq!(ds.algorithm(), SecAlg::RsaSha256);
+        assert_eq!(ds.digest_type(), DigestAlg::Sha256);
+        assert_eq!(ds.digest().len(), 32);
+
+        let (zone, _) = parse_anchor("example.com. IN DS 370 13 2 BE74359954660069D5C63D200C39F5603827D7DD02B56F120EE9F3A8 6764247C").unwrap();
+        assert_eq!(zone.to_string(), "example.com");
+        assert!(parse_anchor("example.com 370 13").is_err());
+
+        let anchors = parse_anchors(&ROOT_ANCHORS.iter().map(|a| a.to_string()).collect::<Vec<_>>()).unwrap();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[0].ds.len(), 2);
+    }
+}
@@ -0,0 +1,8 @@
+#[macro_use] extern crate log;
+
+mod anchor;
+mod proof;
+mod verify;
+mod validator;
+
+pub use validator::{dnssec_query, Security, ZValidator};
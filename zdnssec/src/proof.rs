@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use domain::base::{Dname, DnameBuilder, Rtype, ToDname};
+use domain::rdata::{AllRecordData, Nsec, Nsec3};
+use domain::rdata::rfc4034::RtypeBitmap;
+use domain::utils::base32;
+use crate::verify::{hash_covered, nsec3_hash, Rrset};
+
+type Name = Dname<Bytes>;
+
+// NSEC 记录及其所在区域(签名者)
+struct Nsecs<'a> {
+    owner: &'a Name,
+    zone: &'a Name,
+    nsec: &'a Nsec<Bytes, Name>,
+}
+
+// NSEC3 记录及其所在区域, 所有者名称第一个标签为哈希值
+struct Hashed<'a> {
+    hash: Vec<u8>,
+    zone: &'a Name,
+    nsec3: &'a Nsec3<Bytes>,
+}
+
+/// 否定应答及通配符应答的证明, 见RFC 4035 5.4节及RFC 5155 8节.
+/// 记录的签名须已验证. 证明成立时返回true, NSEC3 opt-out时返回false(不安全), 缺少证明时返回错误.
+pub struct Proofs<'a> {
+    nsecs: Vec<Nsecs<'a>>,
+    nsec3s: Vec<Hashed<'a>>,
+}
+
+// 父区域一侧的委派点, 其NSEC/NSEC3不能证明子区域中的名称
+fn is_delegation(types: &RtypeBitmap<Bytes>) -> bool {
+    types.contains(Rtype::Ns) && !types.contains(Rtype::Soa)
+}
+
+// `name` 存在但没有 `qtype` 类型的记录
+fn lacks(types: &RtypeBitmap<Bytes>, qtype: Rtype) -> bool {
+    !types.contains(qtype) && !types.contains(Rtype::Cname)
+}
+
+fn wildcard(name: &Name) -> Result<Name> {
+    let mut builder = DnameBuilder::new_bytes();
+    builder.append_label(b"*")?;
+    Ok(builder.append_origin(name)?)
+}
+
+// `name` 的后缀中标签数(不含根)为 `labels` 的名称
+fn suffix(name: &Name, labels: usize) -> Option<Name> {
+    name.iter_suffixes().find(|s| s.label_count() == labels + 1)
+}
+
+impl<'a> Proofs<'a> {
+
+    pub fn new(authority: &'a [Rrset]) -> Self {
+        let mut nsecs = Vec::new();
+        let mut nsec3s = Vec::new();
+        for rrset in authority {
+            let zone = match rrset.sigs.first() {
+                Some(sig) => sig.signer_name(),
+                None => continue,
+            };
+            for record in rrset.records.iter() {
+                match record.data() {
+                    AllRecordData::Nsec(nsec) => nsecs.push(Nsecs { owner: &rrset.owner, zone, nsec }),
+                    AllRecordData::Nsec3(nsec3) => {
+                        let label = rrset.owner.first().to_string().to_ascii_uppercase();
+                        if let Ok(hash) = base32::decode_hex::<Vec<u8>>(&label) {
+                            nsec3s.push(Hashed { hash, zone, nsec3 });
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Self { nsecs, nsec3s }
+    }
+
+    fn nsec_match(&self, name: &Name) -> Option<&'a Nsec<Bytes, Name>> {
+        self.nsecs.iter().find(|n| n.owner == name).map(|n| n.nsec)
+    }
+
+    // 覆盖 `name` 的NSEC, 返回其所有者及下一名称
+    fn nsec_cover(&self, name: &Name) -> Option<(&'a Name, &'a Name)> {
+        self.nsecs.iter().find(|n| {
+            let (owner, next) = (n.owner, n.nsec.next_name());
+            if !name.ends_with(n.zone) || (name.ends_with(owner) && is_delegation(n.nsec.types())) {
+                return false;
+            }
+            if owner.name_cmp(next).is_lt() {
+                owner.name_cmp(name).is_lt() && name.name_cmp(next).is_lt()
+            } else {
+                owner.name_cmp(name).is_lt() || name.name_cmp(next).is_lt()
+            }
+        }).map(|n| (n.owner, n.nsec.next_name()))
+    }
+
+    fn nsec3_match(&self, name: &Name) -> Option<&'a Nsec3<Bytes>> {
+        self.nsec3s.iter()
+            .filter(|h| name.ends_with(h.zone))
+            .find(|h| nsec3_hash(name, h.nsec3.salt().as_slice(), h.nsec3.iterations()) == h.hash)
+            .map(|h| h.nsec3)
+    }
+
+    fn nsec3_cover(&self, name: &Name) -> Option<&'a Nsec3<Bytes>> {
+        self.nsec3s.iter()
+            .filter(|h| name.ends_with(h.zone))
+            .find(|h| {
+                let hash = nsec3_hash(name, h.nsec3.salt().as_slice(), h.nsec3.iterations());
+                hash_covered(&h.hash, h.nsec3.next_owner().as_ref(), &hash)
+            })
+            .map(|h| h.nsec3)
+    }
+
+    // NSEC3 最近祖先证明: 返回最近的存在的祖先, 及覆盖其下一级名称的记录
+    fn closest_encloser(&self, name: &Name) -> Result<(Name, &'a Nsec3<Bytes>)> {
+        let mut next_closer = name.clone();
+        for ancestor in name.iter_suffixes().skip(1) {
+            if let Some(nsec3) = self.nsec3_match(&ancestor) {
+                if is_delegation(nsec3.types()) || nsec3.types().contains(Rtype::Dname) {
+                    return Err(anyhow!("NSEC3 closest encloser of {} is a delegation", name));
+                }
+                let cover = self.nsec3_cover(&next_closer)
+                    .ok_or_else(|| anyhow!("no NSEC3 covers next closer name {}", next_closer))?;
+                return Ok((ancestor, cover));
+            }
+            next_closer = ancestor;
+        }
+        Err(anyhow!("no NSEC3 closest encloser for {}", name))
+    }
+
+    /// `name` 不存在(NXDOMAIN)
+    pub fn name_error(&self, name: &Name) -> Result<bool> {
+        if let Some((owner, next)) = self.nsec_cover(name) {
+            // 最近祖先为 `name` 与NSEC两端名称的最长公共后缀
+            let encloser = [owner, next].iter()
+                .filter_map(|n| name.iter_suffixes().find(|s| n.ends_with(s)))
+                .max_by_key(|s| s.label_count())
+                .ok_or_else(|| anyhow!("no closest encloser for {}", name))?;
+            let star = wildcard(&encloser)?;
+            if self.nsec_cover(&star).is_none() {
+                return Err(anyhow!("no NSEC denies wildcard {}", star));
+            }
+            return Ok(true);
+        }
+        if !self.nsec3s.is_empty() {
+            let (encloser, cover) = self.closest_encloser(name)?;
+            let star = wildcard(&encloser)?;
+            if self.nsec3_cover(&star).is_none() {
+                return Err(anyhow!("no NSEC3 denies wildcard {}", star));
+            }
+            return Ok(!cover.opt_out());
+        }
+        Err(anyhow!("missing denial of existence for {}", name))
+    }
+
+    /// `name` 存在但没有 `qtype` 类型的记录(NODATA)
+    pub fn no_data(&self, name: &Name, qtype: Rtype) -> Result<bool> {
+        let referral = |types: &RtypeBitmap<Bytes>| qtype != Rtype::Ds && is_delegation(types);
+        if let Some(nsec) = self.nsec_match(name) {
+            if lacks(nsec.types(), qtype) && !referral(nsec.types()) {
+                return Ok(true);
+            }
+            return Err(anyhow!("NSEC of {} does not deny {}", name, qtype));
+        }
+        if let Some(nsec3) = self.nsec3_match(name) {
+            if lacks(nsec3.types(), qtype) && !referral(nsec3.types()) {
+                return Ok(true);
+            }
+            return Err(anyhow!("NSEC3 of {} does not deny {}", name, qtype));
+        }
+        // 通配符展开的NODATA: `name` 不存在, 通配符存在但没有该类型
+        if let Some((owner, next)) = self.nsec_cover(name) {
+            for encloser in [owner, next].iter().filter_map(|n| name.iter_suffixes().find(|s| n.ends_with(s))) {
+                if self.nsec_match(&wildcard(&encloser)?).is_some_and(|nsec| lacks(nsec.types(), qtype)) {
+                    return Ok(true);
+                }
+            }
+        }
+        if !self.nsec3s.is_empty() {
+            let (encloser, cover) = self.closest_encloser(name)?;
+            if qtype == Rtype::Ds && cover.opt_out() {
+                return Ok(false);
+            }
+            if self.nsec3_match(&wildcard(&encloser)?).is_some_and(|nsec3| lacks(nsec3.types(), qtype)) {
+                return Ok(true);
+            }
+        }
+        Err(anyhow!("missing proof that {} has no {} records", name, qtype))
+    }
+
+    /// `name` 的记录由通配符展开, 签名的标签数为 `labels`, 须证明 `name` 本身不存在.
+    pub fn wildcard(&self, name: &Name, labels: u8) -> Result<bool> {
+        if self.nsec_cover(name).is_some() {
+            return Ok(true);
+        }
+        let next_closer = suffix(name, usize::from(labels) + 1)
+            .ok_or_else(|| anyhow!("invalid wildcard labels for {}", name))?;
+        match self.nsec3_cover(&next_closer) {
+            Some(nsec3) => Ok(!nsec3.opt_out()),
+            None => Err(anyhow!("missing proof that {} is not an exact match", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use domain::base::{iana::{Class, Nsec3HashAlg, SecAlg}, Record, Serial};
+    use domain::rdata::{Rrsig, rfc4034::RtypeBitmapBuilder, rfc5155::{Nsec3Salt, OwnerHash}};
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        Dname::bytes_from_str(s).unwrap()
+    }
+
+    // example.test 区域的NSEC3链, 签名只用于标明区域, 不做验证
+    fn nsec3_chain(names: &[(&str, &[Rtype])], opt_out: bool) -> Vec<Rrset> {
+        let salt = Bytes::from_static(b"\xab\xcd");
+        let mut hashed: Vec<(Vec<u8>, &[Rtype])> = names.iter()
+            .map(|(n, types)| (nsec3_hash(&name(n), &salt, 1), *types))
+            .collect();
+        hashed.sort();
+        (0..hashed.len()).map(|i| {
+            let (hash, types) = &hashed[i];
+            let next = &hashed[(i + 1) % hashed.len()].0;
+            let mut bitmap = RtypeBitmapBuilder::new_vec();
+            for rtype in types.iter() {
+                bitmap.add(*rtype).unwrap();
+            }
+            let bitmap = RtypeBitmap::from_octets(Bytes::from(bitmap.finalize().as_octets().clone())).unwrap();
+            let nsec3 = Nsec3::new(Nsec3HashAlg::Sha1, u8::from(opt_out), 1,
+                Nsec3Salt::from_octets(salt.clone()).unwrap(), OwnerHash::from_octets(Bytes::from(next.clone())).unwrap(), bitmap);
+            let owner = name(&format!("{}.example.test", base32::encode_string_hex(hash)));
+            let sig = Rrsig::new(Rtype::Nsec3, SecAlg::EcdsaP256Sha256, 3, 300, Serial::from(0), Serial::from(0), 0,
+                name("example.test"), Bytes::new());
+            Rrset {
+                owner: owner.clone(),
+                rtype: Rtype::Nsec3,
+                class: Class::In,
+                ttl: 300,
+                records: vec![Record::new(owner, Class::In, 300, AllRecordData::Nsec3(nsec3))],
+                sigs: vec![sig],
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_nsec3() {
+        let zone: [(&str, &[Rtype]); 2] = [("example.test", &[Rtype::Soa, Rtype::Ns]), ("www.example.test", &[Rtype::A])];
+        let chain = nsec3_chain(&zone, false);
+        let proofs = Proofs::new(&chain);
+        assert!(proofs.name_error(&name("nope.example.test")).unwrap());
+        assert!(proofs.name_error(&name("a.b.example.test")).unwrap());
+        assert!(proofs.name_error(&name("www.example.test")).is_err());
+        assert!(proofs.no_data(&name("www.example.test"), Rtype::Aaaa).unwrap());
+        assert!(proofs.no_data(&name("www.example.test"), Rtype::A).is_err());
+        assert!(proofs.wildcard(&name("host.example.test"), 2).unwrap());
+        assert!(proofs.wildcard(&name("www.example.test"), 2).is_err());
+        // 其他区域的名称不能由该区域的记录证明
+        assert!(proofs.name_error(&name("nope.other.test")).is_err());
+
+        let chain = nsec3_chain(&zone, true);
+        assert!(!Proofs::new(&chain).name_error(&name("nope.example.test")).unwrap());
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(wildcard(&name("example.test")).unwrap(), name("*.example.test"));
+        assert_eq!(wildcard(&Dname::root_bytes()).unwrap(), name("*"));
+        assert_eq!(suffix(&name("a.b.example.test"), 2), Some(name("example.test")));
+    }
+}
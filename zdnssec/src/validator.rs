@@ -0,0 +1,686 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{Dname, Message, MessageBuilder, Question, Rtype, iana::Rcode};
+use domain::base::name::ToDname;
+use domain::rdata::{AllRecordData, Dnskey};
+use zconfig::Dnssec as DnssecConf;
+use zupstream::ZUpstream;
+use crate::anchor::{parse_anchors, Anchor, ROOT_ANCHORS};
+use crate::proof::Proofs;
+use crate::verify::{delegation, sections, verified_sig, verify_dnskeys, verify_rrset, Delegation, Rrset};
+
+// 验证链缓存的TTL范围(秒)
+const MIN_LINK_TTL: u32 = 60;
+const MAX_LINK_TTL: u32 = 3600;
+// 向上游查询时声明的EDNS UDP负载大小
+const UDP_PAYLOAD_SIZE: u16 = 1232;
+// CNAME链的最大长度
+const MAX_CHAIN: usize = 16;
+// 验证链缓存的最大条目数
+const MAX_LINKS: usize = 10000;
+
+/// 应答的验证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Security {
+    Secure,
+    Insecure,
+    Bogus(String),
+}
+
+// 验证链中一个域名的状态
+#[derive(Debug, Clone)]
+enum Link {
+    Secure(Vec<Dnskey<Bytes>>), // 委派点, 区域密钥已验证
+    NotCut,                     // 不是委派点, 沿用父区域密钥
+    Insecure,                   // 无DS的委派, 其下的记录不验证
+}
+
+type ZoneKeys = (Dname<Bytes>, Vec<Dnskey<Bytes>>);
+
+/// DNSSEC 验证器, 从信任锚逐级验证DS/DNSKEY, 再验证应答记录的签名,
+/// 否定应答及通配符应答须有覆盖查询域名的NSEC/NSEC3证明.
+#[derive(Clone)]
+pub struct ZValidator {
+    anchors: Vec<Anchor>,
+    upstream: Arc<ZUpstream>,
+    links: Arc<Mutex<HashMap<String, (Link, Instant)>>>,
+}
+
+impl ZValidator {
+
+    pub fn build(conf: Option<DnssecConf>, upstream: Arc<ZUpstream>) -> Result<Self> {
+        let anchors = match conf {
+            Some(conf) => {
+                let list = conf.trust_anchors
+                    .unwrap_or_else(|| ROOT_ANCHORS.iter().map(|a| a.to_string()).collect());
+                let anchors = parse_anchors(&list)?;
+                info!("zvalidator enabled, trust anchors: {}", list.len());
+                anchors
+            },
+            None => Vec::new(),
+        };
+        Ok(Self { anchors, upstream, links: Arc::new(Mutex::new(HashMap::new())) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.anchors.is_empty()
+    }
+
+    /// 验证上游应答, 未开启验证或客户端设置了CD位时返回 `Insecure`.
+    pub async fn check(&self, qmsg: &Message<Bytes>, rmsg: &Message<Bytes>) -> Security {
+        if !self.is_enabled() || qmsg.header().cd() {
+            return Security::Insecure;
+        }
+        match self.verify(rmsg).await {
+            Ok(true) => Security::Secure,
+            Ok(false) => Security::Insecure,
+            Err(e) => Security::Bogus(e.to_string()),
+        }
+    }
+
+    /// 验证上游应答并按客户端查询整理: 验证通过时设置AD位, 验证失败时返回SERVFAIL,
+    /// 客户端未设置DO位时移除应答中的DNSSEC记录.
+    pub async fn validate(&self, qmsg: &Message<Bytes>, rmsg: Bytes) -> Result<Bytes> {
+        let rmsg = Message::from_octets(rmsg)?;
+        let question = qmsg.sole_question()?;
+        let security = self.check(qmsg, &rmsg).await;
+        if let Security::Bogus(reason) = &security {
+            warn!("dnssec validation failed, qname: {:?}, qtype: {:?}, reason: {}",
+                question.qname().to_string(), question.qtype(), reason);
+            return Ok(MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, Rcode::ServFail)?
+                .into_message().into_octets());
+        }
+
+        let client_edns = qmsg.opt().is_some();
+        let client_do = qmsg.opt().is_some_and(|o| o.dnssec_ok());
+        let qtype = question.qtype();
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?;
+        let mut header = rmsg.header();
+        header.set_id(qmsg.header().id());
+        header.set_cd(qmsg.header().cd());
+        header.set_ad(security == Security::Secure && (client_do || qmsg.header().ad()));
+        *builder.header_mut() = header;
+        let mut question = builder.question();
+        for q in rmsg.question().flatten() {
+            question.push(q)?;
+        }
+        let builder = rmsg.copy_records(question.answer(), |rr| {
+            let rtype = rr.rtype();
+            if rtype == Rtype::Opt && !client_edns {
+                return None;
+            }
+            if !client_do && rtype != qtype && matches!(rtype, Rtype::Rrsig | Rtype::Nsec | Rtype::Nsec3) {
+                return None;
+            }
+            rr.to_record::<AllRecordData<_, _>>().ok().flatten()
+        }).map_err(|e| anyhow!("Failed to copy records, error: {:?}", e))?;
+        Ok(builder.into_message().into_octets())
+    }
+
+    // 应答全部记录验证通过且否定应答、通配符应答有证明时返回true, 位于无DS委派之下时返回false, 验证失败返回错误.
+    async fn verify(&self, rmsg: &Message<Bytes>) -> Result<bool> {
+        let rcode = rmsg.header().rcode();
+        if rcode != Rcode::NoError && rcode != Rcode::NXDomain {
+            return Ok(false);
+        }
+        let question = rmsg.sole_question()?;
+        let (qname, qtype) = (question.qname().to_dname::<Bytes>()?, question.qtype());
+        let (answer, authority) = sections(rmsg)?;
+        // authority中的NS记录在父区域一侧时不签名
+        let authority: Vec<Rrset> = authority.into_iter().filter(|s| s.rtype != Rtype::Ns).collect();
+
+        if answer.is_empty() && authority.is_empty() {
+            return match self.zone_keys(&qname).await? {
+                Some(_) => Err(anyhow!("missing denial of existence for {}", qname)),
+                None => Ok(false),
+            };
+        }
+
+        let mut secure = true;
+        let mut wildcards = Vec::new();
+        for (i, rrset) in answer.iter().chain(authority.iter()).enumerate() {
+            let signer = match rrset.sigs.first() {
+                Some(sig) => sig.signer_name().clone(),
+                None => {
+                    if self.zone_keys(&rrset.owner).await?.is_some() {
+                        return Err(anyhow!("missing signature for {} {}", rrset.owner, rrset.rtype));
+                    }
+                    secure = false;
+                    continue;
+                }
+            };
+            if !rrset.owner.ends_with(&signer) {
+                return Err(anyhow!("signer {} is not an ancestor of {}", signer, rrset.owner));
+            }
+            match self.zone_keys(&signer).await? {
+                Some((zone, keys)) if zone == signer => match verified_sig(rrset, &keys) {
+                    // 签名的标签数少于所有者名称时为通配符展开
+                    Some(sig) if i < answer.len() && usize::from(sig.labels()) + 1 < rrset.owner.label_count() =>
+                        wildcards.push((rrset.owner.clone(), sig.labels())),
+                    Some(_) => {},
+                    None => return Err(anyhow!("invalid signature for {} {}", rrset.owner, rrset.rtype)),
+                },
+                Some(_) => return Err(anyhow!("invalid signature for {} {}", rrset.owner, rrset.rtype)),
+                None => secure = false,
+            }
+        }
+        if !secure {
+            return Ok(false);
+        }
+
+        let proofs = Proofs::new(&authority);
+        for (owner, labels) in wildcards.iter() {
+            secure &= proofs.wildcard(owner, *labels)?;
+        }
+        match (answer_chain(&qname, qtype, &answer)?, rcode) {
+            (Some(name), Rcode::NXDomain) => secure &= proofs.name_error(&name)?,
+            (Some(name), _) => secure &= proofs.no_data(&name, qtype)?,
+            (None, Rcode::NXDomain) => return Err(anyhow!("NXDOMAIN with an answer for {}", qname)),
+            (None, _) => {},
+        }
+        Ok(secure)
+    }
+
+    // 从信任锚逐级向下验证到 `name`, 返回其所在安全区域及密钥, 遇到无DS的委派时返回None.
+    async fn zone_keys(&self, name: &Dname<Bytes>) -> Result<Option<ZoneKeys>> {
+        let anchor = match self.anchors.iter()
+            .filter(|a| name.ends_with(&a.zone))
+            .max_by_key(|a| a.zone.label_count()) {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        let mut zone = anchor.zone.clone();
+        let mut keys = self.anchor_keys(anchor).await?;
+        let mut names: Vec<Dname<Bytes>> = name.iter_suffixes()
+            .filter(|n| n.label_count() > zone.label_count())
+            .collect();
+        names.reverse();
+        for child in names {
+            match self.link(&zone, &keys, &child).await? {
+                Link::Secure(child_keys) => {
+                    zone = child;
+                    keys = child_keys;
+                },
+                Link::NotCut => {},
+                Link::Insecure => return Ok(None),
+            }
+        }
+        Ok(Some((zone, keys)))
+    }
+
+    async fn anchor_keys(&self, anchor: &Anchor) -> Result<Vec<Dnskey<Bytes>>> {
+        if let Some(Link::Secure(keys)) = self.cached(&anchor.zone) {
+            return Ok(keys);
+        }
+        let rmsg = self.query(&anchor.zone, Rtype::Dnskey).await?;
+        let (answer, _) = sections(&rmsg)?;
+        let rrset = answer.iter()
+            .find(|s| s.rtype == Rtype::Dnskey && s.owner == anchor.zone)
+            .ok_or_else(|| anyhow!("no DNSKEY for trust anchor {}", anchor.zone))?;
+        let keys = verify_dnskeys(rrset, &anchor.ds)
+            .ok_or_else(|| anyhow!("DNSKEY of {} does not match trust anchor", anchor.zone))?;
+        self.store(&anchor.zone, Link::Secure(keys.clone()), rrset.ttl);
+        Ok(keys)
+    }
+
+    // 用 `parent` 区域的密钥验证 `child` 的DS记录或其不存在的证明.
+    async fn link(&self, parent: &Dname<Bytes>, keys: &[Dnskey<Bytes>], child: &Dname<Bytes>) -> Result<Link> {
+        if let Some(link) = self.cached(child) {
+            return Ok(link);
+        }
+        let rmsg = self.query(child, Rtype::Ds).await?;
+        let rcode = rmsg.header().rcode();
+        if rcode != Rcode::NoError && rcode != Rcode::NXDomain {
+            return Err(anyhow!("DS query for {} failed, rcode: {}", child, rcode));
+        }
+        let (answer, authority) = sections(&rmsg)?;
+
+        let (link, ttl) = if let Some(ds) = answer.iter().find(|s| s.rtype == Rtype::Ds && s.owner == *child) {
+            if !verify_rrset(ds, keys) {
+                return Err(anyhow!("DS of {} is not signed by {}", child, parent));
+            }
+            let kmsg = self.query(child, Rtype::Dnskey).await?;
+            let (kanswer, _) = sections(&kmsg)?;
+            let dnskey = kanswer.iter()
+                .find(|s| s.rtype == Rtype::Dnskey && s.owner == *child)
+                .ok_or_else(|| anyhow!("no DNSKEY for {}", child))?;
+            let child_keys = verify_dnskeys(dnskey, &ds.ds())
+                .ok_or_else(|| anyhow!("DNSKEY of {} does not match DS", child))?;
+            (Link::Secure(child_keys), ds.ttl.min(dnskey.ttl))
+        } else if let Some(cname) = answer.iter().find(|s| s.rtype == Rtype::Cname && s.owner == *child) {
+            // CNAME 所在的名称不会是委派点
+            if !verify_rrset(cname, keys) {
+                return Err(anyhow!("CNAME of {} is not signed by {}", child, parent));
+            }
+            (Link::NotCut, cname.ttl)
+        } else {
+            let proofs: Vec<&Rrset> = authority.iter().filter(|s| s.rtype != Rtype::Ns).collect();
+            if proofs.is_empty() {
+                return Err(anyhow!("unsigned DS denial for {}", child));
+            }
+            if let Some(rrset) = proofs.iter().find(|s| !verify_rrset(s, keys)) {
+                return Err(anyhow!("invalid signature for {} {}", rrset.owner, rrset.rtype));
+            }
+            let ttl = proofs.iter().map(|s| s.ttl).min().unwrap_or(MIN_LINK_TTL);
+            match delegation(child, &proofs) {
+                Delegation::Unsigned => (Link::Insecure, ttl),
+                Delegation::None => (Link::NotCut, ttl),
+            }
+        };
+        self.store(child, link.clone(), ttl);
+        Ok(link)
+    }
+
+    async fn query(&self, name: &Dname<Bytes>, rtype: Rtype) -> Result<Message<Bytes>> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512))?;
+        let header = builder.header_mut();
+        header.set_random_id();
+        header.set_rd(true);
+        header.set_cd(true);
+        let mut builder = builder.question();
+        builder.push(Question::new_in(name.clone(), rtype))?;
+        let mut builder = builder.additional();
+        builder.opt(|opt| {
+            opt.set_udp_payload_size(UDP_PAYLOAD_SIZE);
+            opt.set_dnssec_ok(true);
+            Ok(())
+        })?;
+        let rmsg = self.upstream.query(builder.into_message().into_octets()).await?;
+        Ok(Message::from_octets(rmsg)?)
+    }
+
+    fn cached(&self, name: &Dname<Bytes>) -> Option<Link> {
+        let links = self.links.lock().ok()?;
+        links.get(&key(name))
+            .filter(|(_, expire)| *expire > Instant::now())
+            .map(|(link, _)| link.clone())
+    }
+
+    fn store(&self, name: &Dname<Bytes>, link: Link, ttl: u32) {
+        let ttl = ttl.clamp(MIN_LINK_TTL, MAX_LINK_TTL);
+        if let Ok(mut links) = self.links.lock() {
+            // 超出上限时先移除过期的条目, 仍超出时清空
+            if links.len() >= MAX_LINKS {
+                let now = Instant::now();
+                links.retain(|_, (_, expire)| *expire > now);
+                if links.len() >= MAX_LINKS {
+                    links.clear();
+                }
+            }
+            links.insert(key(name), (link, Instant::now() + Duration::from_secs(ttl.into())));
+        }
+    }
+}
+
+/// 转发给上游的查询: 保留客户端查询的ID与问题, 设置DO位取回签名, 设置CD位由本地验证.
+pub fn dnssec_query(qmsg: &Message<Bytes>) -> Result<Bytes> {
+    let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512))?;
+    let header = builder.header_mut();
+    header.set_id(qmsg.header().id());
+    header.set_rd(qmsg.header().rd());
+    header.set_cd(true);
+    let mut builder = builder.question();
+    for q in qmsg.question().flatten() {
+        builder.push(q)?;
+    }
+    let mut builder = builder.additional();
+    builder.opt(|opt| {
+        opt.set_udp_payload_size(UDP_PAYLOAD_SIZE);
+        opt.set_dnssec_ok(true);
+        Ok(())
+    })?;
+    Ok(builder.into_message().into_octets())
+}
+
+// 从查询域名沿CNAME链检查answer中的记录, 返回没有 `qtype` 记录的链尾名称, 有记录时返回None.
+fn answer_chain(qname: &Dname<Bytes>, qtype: Rtype, answer: &[Rrset]) -> Result<Option<Dname<Bytes>>> {
+    let mut chain = vec![qname.clone()];
+    let mut name = qname.clone();
+    let mut end = Some(name.clone());
+    while chain.len() <= MAX_CHAIN {
+        if answer.iter().any(|s| s.owner == name && (s.rtype == qtype || qtype == Rtype::Any)) {
+            end = None;
+            break;
+        }
+        let target = answer.iter()
+            .filter(|s| s.owner == name && s.rtype == Rtype::Cname)
+            .flat_map(|s| s.records.iter())
+            .find_map(|r| match r.data() {
+                AllRecordData::Cname(cname) => Some(cname.cname().clone()),
+                _ => None,
+            });
+        match target {
+            Some(target) if chain.contains(&target) => return Err(anyhow!("CNAME loop at {}", target)),
+            Some(target) => {
+                chain.push(target.clone());
+                name = target.clone();
+                end = Some(target);
+            },
+            None => break,
+        }
+    }
+    // 其余记录须属于CNAME链, DNAME须为链上名称的祖先
+    for rrset in answer {
+        let expected = chain.contains(&rrset.owner)
+            || (rrset.rtype == Rtype::Dname && chain.iter().any(|n| n.ends_with(&rrset.owner)));
+        if !expected {
+            return Err(anyhow!("unexpected record {} {} in answer for {}", rrset.owner, rrset.rtype, qname));
+        }
+    }
+    Ok(end)
+}
+
+fn key(name: &Dname<Bytes>) -> String {
+    name.to_string().to_ascii_lowercase()
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::{net::Ipv4Addr, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Question, Record, Rtype, Serial};
+    use domain::base::iana::{Class, Rcode};
+    use domain::base::name::ToDname;
+    use domain::rdata::{self, Dnskey, Ds, ZoneRecordData};
+    use domain::sign::{key::SigningKey, records::SortedRecords, ring::Key};
+    use ring::rand::SystemRandom;
+    use tokio::net::UdpSocket;
+    use zconfig::{Dnssec as DnssecConf, Upstream as UpstreamConf};
+    use zupstream::ZUpstream;
+    use super::{dnssec_query, Security, ZValidator};
+
+    type ZoneRecord = Record<Dname<Bytes>, ZoneRecordData<Bytes, Dname<Bytes>>>;
+    type Zones = Vec<(Dname<Bytes>, Vec<ZoneRecord>)>;
+
+    fn name(s: &str) -> Dname<Bytes> {
+        Dname::bytes_from_str(s).unwrap()
+    }
+
+    fn record(owner: &str, data: ZoneRecordData<Bytes, Dname<Bytes>>) -> ZoneRecord {
+        Record::new(name(owner), Class::In, 3600, data)
+    }
+
+    fn dnskey(key: &Key) -> Dnskey<Bytes> {
+        let k = key.dnskey().unwrap();
+        Dnskey::new(k.flags(), k.protocol(), k.algorithm(), Bytes::from(k.public_key().clone()))
+    }
+
+    fn ds(key: &Key, owner: &str) -> Ds<Bytes> {
+        let d = key.ds(name(owner)).unwrap();
+        Ds::new(d.key_tag(), d.algorithm(), d.digest_type(), Bytes::from(d.digest().clone()))
+    }
+
+    // 生成NSEC记录并签名, 返回区域的全部记录
+    fn sign_zone(key: &Key, records: Vec<ZoneRecord>) -> Vec<ZoneRecord> {
+        let mut zone = SortedRecords::new();
+        zone.extend(records);
+        let family = zone.find_soa().unwrap().family_name().cloned();
+        let nsecs = zone.nsecs::<Bytes, _>(&family, 3600);
+        zone.extend(nsecs.into_iter().map(|r| {
+            let (owner, data) = (r.owner().clone(), r.data().clone());
+            Record::new(owner, Class::In, r.ttl(), ZoneRecordData::Nsec(data))
+        }));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        let sigs = zone.sign::<Bytes, _, _>(&family, Serial::from(now + 86400), Serial::from(now - 3600), key).unwrap();
+        let mut records: Vec<ZoneRecord> = zone.families().flat_map(|f| f.records().cloned()).collect();
+        records.extend(sigs.into_iter().map(|r| {
+            let (owner, data) = (r.owner().clone(), r.data().clone());
+            Record::new(owner, Class::In, r.ttl(), ZoneRecordData::Rrsig(data))
+        }));
+        records
+    }
+
+    fn soa(apex: &str) -> ZoneRecord {
+        record(apex, ZoneRecordData::Soa(rdata::Soa::new(
+            name(&format!("ns.{}", apex)), name(&format!("hostmaster.{}", apex)),
+            Serial::from(1), 3600, 600, 86400, 300)))
+    }
+
+    fn ns(owner: &str) -> ZoneRecord {
+        record(owner, ZoneRecordData::Ns(rdata::Ns::new(name(&format!("ns.{}", owner)))))
+    }
+
+    fn a(owner: &str, addr: Ipv4Addr) -> ZoneRecord {
+        record(owner, ZoneRecordData::A(rdata::A::new(addr)))
+    }
+
+    // test. 为信任锚, 其下 example.test 为安全委派, insecure.test 为无DS的委派
+    fn fixture() -> (String, Zones) {
+        let rng = SystemRandom::new();
+        let parent_key = Key::throwaway_13(257, &rng).unwrap();
+        let child_key = Key::throwaway_13(257, &rng).unwrap();
+        let parent = sign_zone(&parent_key, vec![
+            soa("test"),
+            ns("test"),
+            record("test", ZoneRecordData::Dnskey(dnskey(&parent_key))),
+            ns("example.test"),
+            record("example.test", ZoneRecordData::Ds(ds(&child_key, "example.test"))),
+            ns("insecure.test"),
+        ]);
+        let child = sign_zone(&child_key, vec![
+            soa("example.test"),
+            ns("example.test"),
+            record("example.test", ZoneRecordData::Dnskey(dnskey(&child_key))),
+            a("www.example.test", Ipv4Addr::new(192, 0, 2, 1)),
+            a("*.wild.example.test", Ipv4Addr::new(192, 0, 2, 9)),
+        ]);
+        let d = ds(&parent_key, "test");
+        let anchor = format!("test. {} {} {} {}", d.key_tag(), d.algorithm().to_int(), d.digest_type().to_int(),
+            domain::utils::base16::encode_string(d.digest()));
+        (anchor, vec![(name("test"), parent), (name("example.test"), child)])
+    }
+
+    fn query(qname: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(name(qname), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn query_do(qname: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(name(qname), qtype)).unwrap();
+        let mut builder = builder.additional();
+        builder.opt(|opt| {
+            opt.set_dnssec_ok(true);
+            Ok(())
+        }).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn response(qmsg: &Message<Bytes>, rcode: Rcode, answer: Vec<ZoneRecord>, authority: Vec<ZoneRecord>) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024)).unwrap()
+            .start_answer(qmsg, rcode).unwrap();
+        for r in answer {
+            builder.push(r).unwrap();
+        }
+        let mut builder = builder.authority();
+        for r in authority {
+            builder.push(r).unwrap();
+        }
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn covers(r: &ZoneRecord, rtype: Rtype) -> bool {
+        match r.data() {
+            ZoneRecordData::Rrsig(sig) => sig.type_covered() == rtype,
+            _ => r.rtype() == rtype,
+        }
+    }
+
+    // 模拟上游: DS查询由父区域应答, 其余由最近的区域应答
+    fn answer(zones: &[(Dname<Bytes>, Vec<ZoneRecord>)], qmsg: &Message<Bytes>) -> Message<Bytes> {
+        let question = qmsg.sole_question().unwrap();
+        let qname = question.qname().to_dname::<Bytes>().unwrap();
+        let qtype = question.qtype();
+        let (apex, records) = zones.iter()
+            .filter(|(apex, _)| qname.ends_with(apex) && !(qtype == Rtype::Ds && qname == *apex))
+            .max_by_key(|(apex, _)| apex.label_count())
+            .unwrap();
+        let matched: Vec<ZoneRecord> = records.iter()
+            .filter(|r| *r.owner() == qname && covers(r, qtype))
+            .cloned()
+            .collect();
+        if !matched.is_empty() {
+            return response(qmsg, Rcode::NoError, matched, vec![]);
+        }
+        let authority = records.iter()
+            .filter(|r| (r.owner() == apex && covers(r, Rtype::Soa)) || (*r.owner() == qname && covers(r, Rtype::Nsec)))
+            .cloned()
+            .collect();
+        response(qmsg, Rcode::NoError, vec![], authority)
+    }
+
+    async fn validator(anchor: String, zones: Zones) -> ZValidator {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((len, src)) = socket.recv_from(&mut buf).await {
+                let qmsg = Message::from_octets(Bytes::copy_from_slice(&buf[..len])).unwrap();
+                let _ = socket.send_to(answer(&zones, &qmsg).as_slice(), src).await;
+            }
+        });
+        let upstream = ZUpstream::build(vec![UpstreamConf { uptype: None, host: "127.0.0.1".to_string(), port: Some(port) }]).await.unwrap();
+        ZValidator::build(Some(DnssecConf { trust_anchors: Some(vec![anchor]) }), Arc::new(upstream)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let (anchor, zones) = fixture();
+        let signed = answer(&zones, &query("www.example.test", Rtype::A));
+        let validator = validator(anchor, zones.clone()).await;
+        assert!(validator.is_enabled());
+
+        let qmsg = query("www.example.test", Rtype::A);
+        assert_eq!(validator.check(&qmsg, &signed).await, Security::Secure);
+
+        // 篡改地址后签名无效
+        let records = signed.answer().unwrap().limit_to::<ZoneRecordData<_, _>>()
+            .map(|r| r.unwrap())
+            .map(|r| {
+                let owner = r.owner().to_dname::<Bytes>().unwrap();
+                let data = match r.data() {
+                    ZoneRecordData::A(_) => ZoneRecordData::A(rdata::A::new(Ipv4Addr::new(192, 0, 2, 66))),
+                    data => data.clone().flatten_into().unwrap(),
+                };
+                Record::new(owner, Class::In, r.ttl(), data)
+            })
+            .collect::<Vec<ZoneRecord>>();
+        let forged = response(&qmsg, Rcode::NoError, records.clone(), vec![]);
+        assert!(matches!(validator.check(&qmsg, &forged).await, Security::Bogus(_)));
+        let rmsg = Message::from_octets(validator.validate(&qmsg, forged.into_octets()).await.unwrap()).unwrap();
+        assert_eq!(rmsg.header().rcode(), Rcode::ServFail);
+
+        // 安全区域内缺少签名
+        let stripped = response(&qmsg, Rcode::NoError, records.into_iter().filter(|r| r.rtype() == Rtype::A).collect(), vec![]);
+        assert!(matches!(validator.check(&qmsg, &stripped).await, Security::Bogus(_)));
+
+        // 无DS委派之下的记录不验证
+        let qmsg = query("host.insecure.test", Rtype::A);
+        let unsigned = response(&qmsg, Rcode::NoError, vec![a("host.insecure.test", Ipv4Addr::new(198, 51, 100, 1))], vec![]);
+        assert_eq!(validator.check(&qmsg, &unsigned).await, Security::Insecure);
+
+        // 验证通过时设置AD位, 客户端未设置DO位时移除RRSIG
+        let qmsg = query_do("www.example.test", Rtype::A);
+        let rmsg = Message::from_octets(validator.validate(&qmsg, signed.clone().into_octets()).await.unwrap()).unwrap();
+        assert!(rmsg.header().ad());
+        assert_eq!(rmsg.header_counts().ancount(), 2);
+
+        let qmsg = query("www.example.test", Rtype::A);
+        let rmsg = Message::from_octets(validator.validate(&qmsg, signed.into_octets()).await.unwrap()).unwrap();
+        assert!(!rmsg.header().ad());
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+    }
+
+    // 最近的区域中所有者为 `owner` 的 `rtype` 记录及其签名
+    fn rrset(zones: &Zones, owner: &str, rtype: Rtype) -> Vec<ZoneRecord> {
+        let owner = name(owner);
+        let (_, records) = zones.iter()
+            .filter(|(apex, _)| owner.ends_with(apex))
+            .max_by_key(|(apex, _)| apex.label_count())
+            .unwrap();
+        records.iter()
+            .filter(|r| *r.owner() == owner && covers(r, rtype))
+            .cloned()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_denial() {
+        let (anchor, zones) = fixture();
+        let validator = validator(anchor, zones.clone()).await;
+        let soa = rrset(&zones, "example.test", Rtype::Soa);
+
+        // NODATA: www.example.test 的NSEC中没有AAAA
+        let qmsg = query("www.example.test", Rtype::Aaaa);
+        let nodata = answer(&zones, &qmsg);
+        assert_eq!(validator.check(&qmsg, &nodata).await, Security::Secure);
+        let qmsg = query("www.example.test", Rtype::A);
+        let forged = response(&qmsg, Rcode::NoError, vec![], nodata.authority().unwrap().limit_to::<ZoneRecordData<_, _>>()
+            .map(|r| r.unwrap())
+            .map(|r| Record::new(r.owner().to_dname::<Bytes>().unwrap(), Class::In, r.ttl(), r.data().clone().flatten_into().unwrap()))
+            .collect());
+        assert!(matches!(validator.check(&qmsg, &forged).await, Security::Bogus(_)));
+
+        // NXDOMAIN: example.test 的NSEC覆盖 nope.example.test 及 *.example.test
+        let proof: Vec<ZoneRecord> = soa.iter().cloned().chain(rrset(&zones, "example.test", Rtype::Nsec)).collect();
+        let qmsg = query("nope.example.test", Rtype::A);
+        let nxdomain = response(&qmsg, Rcode::NXDomain, vec![], proof.clone());
+        assert_eq!(validator.check(&qmsg, &nxdomain).await, Security::Secure);
+
+        // 重放到存在的名称, 或只有SOA时验证失败
+        let qmsg = query("www.example.test", Rtype::A);
+        let replayed = response(&qmsg, Rcode::NXDomain, vec![], proof);
+        assert!(matches!(validator.check(&qmsg, &replayed).await, Security::Bogus(_)));
+        let replayed = response(&qmsg, Rcode::NXDomain, vec![], soa.clone());
+        assert!(matches!(validator.check(&qmsg, &replayed).await, Security::Bogus(_)));
+
+        // answer中的记录须属于查询域名
+        let qmsg = query("other.example.test", Rtype::A);
+        let unrelated = response(&qmsg, Rcode::NoError, rrset(&zones, "www.example.test", Rtype::A), vec![]);
+        assert!(matches!(validator.check(&qmsg, &unrelated).await, Security::Bogus(_)));
+    }
+
+    #[tokio::test]
+    async fn test_wildcard() {
+        let (anchor, zones) = fixture();
+        let validator = validator(anchor, zones.clone()).await;
+        let qmsg = query("host.wild.example.test", Rtype::A);
+        let expanded: Vec<ZoneRecord> = rrset(&zones, "*.wild.example.test", Rtype::A).into_iter()
+            .map(|r| Record::new(name("host.wild.example.test"), r.class(), r.ttl(), r.data().clone()))
+            .collect();
+
+        // 缺少 host.wild.example.test 不存在的证明
+        let rmsg = response(&qmsg, Rcode::NoError, expanded.clone(), vec![]);
+        assert!(matches!(validator.check(&qmsg, &rmsg).await, Security::Bogus(_)));
+
+        let rmsg = response(&qmsg, Rcode::NoError, expanded, rrset(&zones, "*.wild.example.test", Rtype::Nsec));
+        assert_eq!(validator.check(&qmsg, &rmsg).await, Security::Secure);
+    }
+
+    #[test]
+    fn test_dnssec_query() {
+        let qmsg = query("www.example.test", Rtype::Aaaa);
+        let dmsg = Message::from_octets(dnssec_query(&qmsg).unwrap()).unwrap();
+        assert_eq!(dmsg.header().id(), qmsg.header().id());
+        assert!(dmsg.header().cd());
+        assert!(dmsg.opt().unwrap().dnssec_ok());
+        assert_eq!(dmsg.sole_question().unwrap().qtype(), Rtype::Aaaa);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let upstream = ZUpstream::build(vec![]).await.unwrap();
+        let validator = ZValidator::build(None, Arc::new(upstream)).unwrap();
+        assert!(!validator.is_enabled());
+        let qmsg = query("www.example.test", Rtype::A);
+        let rmsg = response(&qmsg, Rcode::NoError, vec![a("www.example.test", Ipv4Addr::new(192, 0, 2, 1))], vec![]);
+        assert_eq!(validator.check(&qmsg, &rmsg).await, Security::Insecure);
+    }
+}
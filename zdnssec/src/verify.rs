@@ -0,0 +1,246 @@
+use anyhow::Result;
+use bytes::Bytes;
+use domain::base::{Dname, Message, ParsedDname, Record, RecordSection, Rtype, Serial, ToDname};
+use domain::base::iana::Class;
+use domain::base::octets::{Compose, ShortBuf};
+use domain::rdata::{AllRecordData, Dnskey, Ds, Rrsig};
+use domain::utils::base32;
+use domain::validate::{DnskeyExt, RrsigExt};
+use ring::digest;
+
+pub type Rr = Record<Dname<Bytes>, AllRecordData<Bytes, Dname<Bytes>>>;
+
+// DNSKEY flags中的Zone Key位
+const ZONE_KEY: u16 = 0x0100;
+
+/// 同名同类型的记录集合及覆盖它的签名
+#[derive(Debug, Clone)]
+pub struct Rrset {
+    pub owner: Dname<Bytes>,
+    pub rtype: Rtype,
+    pub class: Class,
+    pub ttl: u32,
+    pub records: Vec<Rr>,
+    pub sigs: Vec<Rrsig<Bytes, Dname<Bytes>>>,
+}
+
+impl Rrset {
+
+    pub fn ds(&self) -> Vec<Ds<Bytes>> {
+        self.records.iter().filter_map(|r| match r.data() {
+            AllRecordData::Ds(ds) => Some(ds.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn dnskeys(&self) -> Vec<Dnskey<Bytes>> {
+        self.records.iter().filter_map(|r| match r.data() {
+            AllRecordData::Dnskey(key) => Some(key.clone()),
+            _ => None,
+        }).collect()
+    }
+}
+
+/// 解析应答中answer与authority两段的记录集合, RRSIG记录归入其覆盖的集合.
+pub fn sections(msg: &Message<Bytes>) -> Result<(Vec<Rrset>, Vec<Rrset>)> {
+    let answer = group(parse_section(msg.answer()?)?);
+    let authority = group(parse_section(msg.authority()?)?);
+    Ok((answer, authority))
+}
+
+fn parse_section(section: RecordSection<&Bytes>) -> Result<Vec<Rr>> {
+    let mut records = Vec::new();
+    for rr in section {
+        let rr = rr?;
+        if rr.rtype() == Rtype::Opt {
+            continue;
+        }
+        if let Some(record) = rr.to_record::<AllRecordData<Bytes, ParsedDname<&Bytes>>>()? {
+            let owner = record.owner().to_dname::<Bytes>()?;
+            let (class, ttl) = (record.class(), record.ttl());
+            records.push(Record::new(owner, class, ttl, record.into_data().flatten_into()?));
+        }
+    }
+    Ok(records)
+}
+
+fn group(records: Vec<Rr>) -> Vec<Rrset> {
+    let mut rrsets: Vec<Rrset> = Vec::new();
+    let mut sigs = Vec::new();
+    for record in records {
+        if let AllRecordData::Rrsig(sig) = record.data() {
+            sigs.push((record.owner().clone(), sig.clone()));
+            continue;
+        }
+        match rrsets.iter_mut().find(|s| s.owner == *record.owner() && s.rtype == record.rtype()) {
+            Some(rrset) => {
+                rrset.ttl = rrset.ttl.min(record.ttl());
+                rrset.records.push(record);
+            },
+            None => rrsets.push(Rrset {
+                owner: record.owner().clone(),
+                rtype: record.rtype(),
+                class: record.class(),
+                ttl: record.ttl(),
+                records: vec![record],
+                sigs: Vec::new(),
+            }),
+        }
+    }
+    for (owner, sig) in sigs {
+        if let Some(rrset) = rrsets.iter_mut().find(|s| s.owner == owner && s.rtype == sig.type_covered()) {
+            rrset.sigs.push(sig);
+        }
+    }
+    rrsets
+}
+
+// 按RFC 4035 5.3.2构造签名数据, 通配符展开的记录还原为 `*.` 开头的名称.
+fn signed_data(sig: &Rrsig<Bytes, Dname<Bytes>>, rrset: &Rrset) -> Result<Vec<u8>, ShortBuf> {
+    let mut buf = Vec::new();
+    sig.type_covered().compose(&mut buf)?;
+    sig.algorithm().compose(&mut buf)?;
+    sig.labels().compose(&mut buf)?;
+    sig.original_ttl().compose(&mut buf)?;
+    sig.expiration().compose(&mut buf)?;
+    sig.inception().compose(&mut buf)?;
+    sig.key_tag().compose(&mut buf)?;
+    sig.signer_name().compose_canonical(&mut buf)?;
+
+    let mut owner = Vec::new();
+    let labels = rrset.owner.label_count() - 1;
+    let sig_labels = usize::from(sig.labels());
+    if sig_labels > labels {
+        return Err(ShortBuf);
+    }
+    if sig_labels < labels {
+        owner.extend_from_slice(b"\x01*");
+        if let Some(suffix) = rrset.owner.iter_suffixes().nth(labels - sig_labels) {
+            suffix.compose_canonical(&mut owner)?;
+        }
+    } else {
+        rrset.owner.compose_canonical(&mut owner)?;
+    }
+
+    let mut rdatas = Vec::new();
+    for record in rrset.records.iter() {
+        let mut rdata = Vec::new();
+        record.data().compose_canonical(&mut rdata)?;
+        rdatas.push(rdata);
+    }
+    rdatas.sort();
+    rdatas.dedup();
+    for rdata in rdatas {
+        buf.extend_from_slice(&owner);
+        rrset.rtype.compose(&mut buf)?;
+        rrset.class.compose(&mut buf)?;
+        sig.original_ttl().compose(&mut buf)?;
+        (rdata.len() as u16).compose(&mut buf)?;
+        buf.extend_from_slice(&rdata);
+    }
+    Ok(buf)
+}
+
+/// 任一有效期内的签名可由 `keys` 中的密钥验证时返回true.
+pub fn verify_rrset(rrset: &Rrset, keys: &[Dnskey<Bytes>]) -> bool {
+    verified_sig(rrset, keys).is_some()
+}
+
+/// 返回第一个有效期内且可由 `keys` 中的密钥验证的签名.
+pub fn verified_sig<'a>(rrset: &'a Rrset, keys: &[Dnskey<Bytes>]) -> Option<&'a Rrsig<Bytes, Dname<Bytes>>> {
+    let now = Serial::now();
+    rrset.sigs.iter().find(|sig| {
+        if now < sig.inception() || now > sig.expiration() {
+            return false;
+        }
+        let data = match signed_data(sig, rrset) {
+            Ok(d) => d,
+            Err(_) => return false,
+        };
+        keys.iter()
+            .filter(|k| k.key_tag() == sig.key_tag() && k.algorithm() == sig.algorithm())
+            .filter(|k| k.protocol() == 3 && k.flags() & ZONE_KEY != 0 && !k.is_revoked())
+            .any(|k| sig.verify_signed_data(k, &data).is_ok())
+    })
+}
+
+/// 用DS记录验证DNSKEY集合, 成功时返回区域的全部密钥.
+pub fn verify_dnskeys(rrset: &Rrset, ds_list: &[Ds<Bytes>]) -> Option<Vec<Dnskey<Bytes>>> {
+    let keys = rrset.dnskeys();
+    let trusted: Vec<Dnskey<Bytes>> = keys.iter()
+        .filter(|k| ds_list.iter().any(|ds| {
+            ds.key_tag() == k.key_tag()
+                && ds.algorithm() == k.algorithm()
+                && k.digest(&rrset.owner, ds.digest_type()).is_ok_and(|d| d.as_ref() == ds.digest().as_ref())
+        }))
+        .cloned()
+        .collect();
+    if !trusted.is_empty() && verify_rrset(rrset, &trusted) {
+        Some(keys)
+    } else {
+        None
+    }
+}
+
+/// NSEC3 哈希, 见RFC 5155 5节.
+pub fn nsec3_hash(name: &Dname<Bytes>, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let _ = name.compose_canonical(&mut buf);
+    buf.extend_from_slice(salt);
+    let mut hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &buf);
+    for _ in 0..iterations {
+        let mut buf = hash.as_ref().to_vec();
+        buf.extend_from_slice(salt);
+        hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &buf);
+    }
+    hash.as_ref().to_vec()
+}
+
+/// NSEC3 记录 `owner` 到 `next` 之间(不含两端)是否包含 `hash`, 区域中最后一条记录回绕到第一条.
+pub fn hash_covered(owner: &[u8], next: &[u8], hash: &[u8]) -> bool {
+    if owner < next {
+        owner < hash && hash < next
+    } else {
+        owner < hash || hash < next
+    }
+}
+
+/// 否定应答中关于 `name` 的委派证明
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delegation {
+    Unsigned, // 无DS的委派点, 或NSEC3 opt-out覆盖
+    None,     // 不是委派点
+}
+
+/// 从已验证签名的NSEC/NSEC3记录判断 `name` 是否是无DS的委派点.
+pub fn delegation(name: &Dname<Bytes>, proofs: &[&Rrset]) -> Delegation {
+    let unsigned = |has: &dyn Fn(Rtype) -> bool| {
+        if has(Rtype::Ns) && !has(Rtype::Ds) && !has(Rtype::Soa) { Delegation::Unsigned } else { Delegation::None }
+    };
+    for rrset in proofs.iter().filter(|s| s.rtype == Rtype::Nsec && s.owner == *name) {
+        for record in rrset.records.iter() {
+            if let AllRecordData::Nsec(nsec) = record.data() {
+                return unsigned(&|rtype| nsec.types().contains(rtype));
+            }
+        }
+    }
+    for rrset in proofs.iter().filter(|s| s.rtype == Rtype::Nsec3) {
+        let label = rrset.owner.first().to_string().to_ascii_uppercase();
+        let owner_hash = match base32::decode_hex::<Vec<u8>>(&label) {
+            Ok(h) => h,
+            Err(_) => continue,
+        };
+        for record in rrset.records.iter() {
+            if let AllRecordData::Nsec3(nsec3) = record.data() {
+                let hash = nsec3_hash(name, nsec3.salt().as_slice(), nsec3.iterations());
+                if hash == owner_hash {
+                    return unsigned(&|rtype| nsec3.types().contains(rtype));
+                }
+                if hash_covered(&owner_hash, nsec3.next_owner().as_ref(), &hash) && nsec3.opt_out() {
+                    return Delegation::Unsigned;
+                }
+            }
+        }
+    }
+    Delegation::None
+}
@@ -0,0 +1,284 @@
+use std::{net::SocketAddr, path::PathBuf, sync::{Mutex, OnceLock}, time::{Duration, SystemTime}};
+
+use anyhow::{anyhow, Result};
+use async_channel::{bounded, Receiver, Sender};
+use lazy_static::lazy_static;
+use tokio::task::JoinHandle;
+use zconfig::Dnstap as DnstapConf;
+use crate::framestream::Output;
+use crate::proto::{Message, MessageType, Protocol};
+
+// 等待输出的消息数, 超过后丢弃新消息
+const QUEUE_SIZE: usize = 8192;
+// socket断开后重连的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    pub static ref DNSTAP: ZDnstap = ZDnstap::default();
+}
+
+/// 上游的地址及协议, 地址未知(如DoH)时为None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub protocol: Protocol,
+    pub addr: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    File(PathBuf),
+    Socket(PathBuf),
+}
+
+struct Tap {
+    sender: Sender<Vec<u8>>,
+    identity: Vec<u8>,
+    handle: Mutex<Option<JoinHandle<()>>>, // 后台输出任务
+}
+
+/// dnstap输出, 未配置时各方法不做任何事.
+#[derive(Default)]
+pub struct ZDnstap {
+    tap: OnceLock<Tap>,
+}
+
+impl ZDnstap {
+
+    /// 按配置启动后台输出任务
+    pub fn start(&self, conf: Option<DnstapConf>) -> Result<()> {
+        let conf = match conf {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let target = match (conf.socket, conf.file) {
+            (Some(socket), None) => Target::Socket(PathBuf::from(socket)),
+            (None, Some(file)) => Target::File(PathBuf::from(file)),
+            _ => return Err(anyhow!("dnstap requires exactly one of socket and file")),
+        };
+        let (sender, receiver) = bounded(QUEUE_SIZE);
+        let identity = conf.identity.unwrap_or_else(|| "zzdns".to_string()).into_bytes();
+        if self.tap.set(Tap { sender, identity, handle: Mutex::new(None) }).is_err() {
+            return Err(anyhow!("dnstap is already started"));
+        }
+        let handle = tokio::spawn(async move {
+            serve(target, receiver).await;
+        });
+        if let Some(tap) = self.tap.get() {
+            *tap.handle.lock().unwrap() = Some(handle);
+        }
+        Ok(())
+    }
+
+    /// 停止接收消息, 等待写完队列中的消息并结束输出
+    pub async fn stop(&self) {
+        if let Some(tap) = self.tap.get() {
+            tap.sender.close();
+            let handle = tap.handle.lock().unwrap().take();
+            if let Some(handle) = handle {
+                let _ = handle.await;
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tap.get().is_some()
+    }
+
+    fn emit(&self, message: Message) {
+        if let Some(tap) = self.tap.get() {
+            let frame = message.encode(&tap.identity, concat!("zzdns ", env!("CARGO_PKG_VERSION")).as_bytes());
+            if tap.sender.try_send(frame).is_err() {
+                debug!("dnstap queue is full, message dropped");
+            }
+        }
+    }
+
+    /// 收到客户端查询
+    pub fn client_query(&self, client: SocketAddr, server: SocketAddr, query_time: SystemTime, msg: &[u8]) {
+        self.emit(Message {
+            mtype: MessageType::ClientQuery,
+            protocol: Protocol::Udp,
+            query_address: Some(client),
+            response_address: Some(server),
+            query_time,
+            response_time: None,
+            message: msg,
+        });
+    }
+
+    /// 向客户端发送应答, `query_time` 为收到该查询的时间
+    pub fn client_response(&self, client: SocketAddr, server: SocketAddr, query_time: SystemTime, msg: &[u8]) {
+        self.emit(Message {
+            mtype: MessageType::ClientResponse,
+            protocol: Protocol::Udp,
+            query_address: Some(client),
+            response_address: Some(server),
+            query_time,
+            response_time: Some(SystemTime::now()),
+            message: msg,
+        });
+    }
+
+    /// 向上游转发查询
+    pub fn forwarder_query(&self, peer: Peer, query_time: SystemTime, msg: &[u8]) {
+        self.emit(Message {
+            mtype: MessageType::ForwarderQuery,
+            protocol: peer.protocol,
+            query_address: None,
+            response_address: peer.addr,
+            query_time,
+            response_time: None,
+            message: msg,
+        });
+    }
+
+    /// 收到上游应答
+    pub fn forwarder_response(&self, peer: Peer, query_time: SystemTime, msg: &[u8]) {
+        self.emit(Message {
+            mtype: MessageType::ForwarderResponse,
+            protocol: peer.protocol,
+            query_address: None,
+            response_address: peer.addr,
+            query_time,
+            response_time: Some(SystemTime::now()),
+            message: msg,
+        });
+    }
+}
+
+async fn open(target: &Target) -> Result<Output> {
+    match target {
+        Target::File(path) => Output::file(path).await,
+        Target::Socket(path) => Output::socket(path).await,
+    }
+}
+
+// 写入队列中的消息, socket断开后重连, 断开期间的消息被丢弃. 队列关闭后写完剩余消息并结束输出.
+async fn serve(target: Target, receiver: Receiver<Vec<u8>>) {
+    let mut output: Option<Output> = None;
+    loop {
+        if output.is_none() {
+            match open(&target).await {
+                Ok(o) => {
+                    info!("dnstap output opened: {:?}", target);
+                    output = Some(o);
+                },
+                Err(e) => {
+                    warn!("Failed to open dnstap output {:?}, error:{:?}", target, e);
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                    while receiver.try_recv().is_ok() {}
+                    if receiver.is_closed() {
+                        return;
+                    }
+                    continue;
+                },
+            }
+        }
+        let frame = match receiver.recv().await {
+            Ok(f) => f,
+            Err(_) => break,
+        };
+        if let Some(o) = output.as_mut() {
+            let mut res = o.write(&frame).await;
+            // 取完队列中的消息后再刷新
+            while let (true, Ok(frame)) = (res.is_ok(), receiver.try_recv()) {
+                res = o.write(&frame).await;
+            }
+            if let Err(e) = res.and(o.flush().await) {
+                warn!("Failed to write dnstap output {:?}, error:{:?}", target, e);
+                output = None;
+            }
+        }
+    }
+    if let Some(o) = output {
+        if let Err(e) = o.stop().await {
+            debug!("Failed to stop dnstap output, error:{:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+    use crate::framestream::{self, ACCEPT, FINISH, READY, START, STOP};
+    use super::*;
+
+    fn peer() -> Peer {
+        Peer { protocol: Protocol::Udp, addr: Some("192.0.2.53:53".parse().unwrap()) }
+    }
+
+    #[tokio::test]
+    async fn test_file() {
+        let path = std::env::temp_dir().join(format!("zzdns-dnstap-{}.fstrm", std::process::id()));
+        let (sender, receiver) = bounded(16);
+        let tap = ZDnstap::default();
+        let _ = tap.tap.set(Tap { sender: sender.clone(), identity: b"test".to_vec(), handle: Mutex::new(None) });
+        tap.forwarder_query(peer(), SystemTime::now(), b"\x00\x01");
+        tap.forwarder_response(peer(), SystemTime::now(), b"\x00\x02");
+        sender.close();
+        serve(Target::File(path.clone()), receiver).await;
+
+        let data = std::fs::read(&path).unwrap();
+        let mut reader = &data[..];
+        assert_eq!(framestream::read_control(&mut reader).await.unwrap(), START);
+        for _ in 0..2 {
+            let len = reader.read_u32().await.unwrap() as usize;
+            assert!(len > 0);
+            reader = &reader[len..];
+        }
+        assert_eq!(framestream::read_control(&mut reader).await.unwrap(), STOP);
+        assert!(reader.is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_socket() {
+        let path = std::env::temp_dir().join(format!("zzdns-dnstap-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // 模拟接收端, 返回收到的数据帧
+        let collector = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            assert_eq!(framestream::read_control(&mut stream).await.unwrap(), READY);
+            stream.write_all(&framestream::control(ACCEPT, true)).await.unwrap();
+            assert_eq!(framestream::read_control(&mut stream).await.unwrap(), START);
+            let mut frames = Vec::new();
+            loop {
+                let len = stream.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    let len = stream.read_u32().await.unwrap() as usize;
+                    let mut body = vec![0; len];
+                    stream.read_exact(&mut body).await.unwrap();
+                    assert_eq!(u32::from_be_bytes([body[0], body[1], body[2], body[3]]), STOP);
+                    stream.write_all(&framestream::control(FINISH, false)).await.unwrap();
+                    return frames;
+                }
+                let mut frame = vec![0; len];
+                stream.read_exact(&mut frame).await.unwrap();
+                frames.push(frame);
+            }
+        });
+
+        let (sender, receiver) = bounded(16);
+        let tap = ZDnstap::default();
+        let _ = tap.tap.set(Tap { sender: sender.clone(), identity: b"test".to_vec(), handle: Mutex::new(None) });
+        tap.client_query("192.0.2.1:5300".parse().unwrap(), "192.0.2.53:53".parse().unwrap(), SystemTime::now(), b"\x00\x01");
+        sender.close();
+        serve(Target::Socket(path.clone()), receiver).await;
+
+        let frames = collector.await.unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].starts_with(b"\x0a\x04test"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_start() {
+        let tap = ZDnstap::default();
+        assert!(tap.start(None).is_ok());
+        assert!(!tap.is_enabled());
+        let conf = DnstapConf { socket: Some("a".to_string()), file: Some("b".to_string()), identity: None };
+        assert!(tap.start(Some(conf)).is_err());
+    }
+}
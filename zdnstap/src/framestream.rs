@@ -0,0 +1,126 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use tokio::{fs::File, io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufWriter}, net::UnixStream};
+
+/// Frame Streams 的内容类型
+pub const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+
+// 控制帧类型
+pub const ACCEPT: u32 = 0x01;
+pub const START: u32 = 0x02;
+pub const STOP: u32 = 0x03;
+pub const READY: u32 = 0x04;
+pub const FINISH: u32 = 0x05;
+
+const FIELD_CONTENT_TYPE: u32 = 0x01;
+// 控制帧的最大长度
+const MAX_CONTROL: usize = 512;
+// 双向握手的超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 编码控制帧, 以长度为0的转义开头
+pub fn control(ctype: u32, content_type: bool) -> Vec<u8> {
+    let mut body = ctype.to_be_bytes().to_vec();
+    if content_type {
+        body.extend_from_slice(&FIELD_CONTENT_TYPE.to_be_bytes());
+        body.extend_from_slice(&(CONTENT_TYPE.len() as u32).to_be_bytes());
+        body.extend_from_slice(CONTENT_TYPE);
+    }
+    let mut frame = vec![0; 4];
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// 读取一个控制帧, 返回控制帧类型
+pub async fn read_control<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u32> {
+    if reader.read_u32().await? != 0 {
+        return Err(anyhow!("expected a control frame"));
+    }
+    let len = reader.read_u32().await? as usize;
+    if !(4..=MAX_CONTROL).contains(&len) {
+        return Err(anyhow!("invalid control frame length: {}", len));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).await?;
+    Ok(u32::from_be_bytes([body[0], body[1], body[2], body[3]]))
+}
+
+async fn expect<R: AsyncRead + Unpin>(reader: &mut R, ctype: u32) -> Result<()> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_control(reader)).await? {
+        Ok(c) if c == ctype => Ok(()),
+        Ok(c) => Err(anyhow!("unexpected control frame: {:#x}", c)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Frame Streams输出, 文件为单向, Unix socket需先与接收端握手.
+pub enum Output {
+    File(BufWriter<File>),
+    Socket(BufWriter<UnixStream>),
+}
+
+impl Output {
+
+    pub async fn file(path: &Path) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path).await?);
+        writer.write_all(&control(START, true)).await?;
+        Ok(Output::File(writer))
+    }
+
+    pub async fn socket(path: &Path) -> Result<Self> {
+        let mut stream = UnixStream::connect(path).await?;
+        stream.write_all(&control(READY, true)).await?;
+        expect(&mut stream, ACCEPT).await?;
+        stream.write_all(&control(START, true)).await?;
+        Ok(Output::Socket(BufWriter::new(stream)))
+    }
+
+    pub async fn write(&mut self, frame: &[u8]) -> Result<()> {
+        let len = (frame.len() as u32).to_be_bytes();
+        match self {
+            Output::File(w) => { w.write_all(&len).await?; w.write_all(frame).await?; },
+            Output::Socket(w) => { w.write_all(&len).await?; w.write_all(frame).await?; },
+        }
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<()> {
+        match self {
+            Output::File(w) => w.flush().await?,
+            Output::Socket(w) => w.flush().await?,
+        }
+        Ok(())
+    }
+
+    /// 发送STOP, socket等待接收端的FINISH
+    pub async fn stop(mut self) -> Result<()> {
+        let stop = control(STOP, false);
+        match &mut self {
+            Output::File(w) => { w.write_all(&stop).await?; w.flush().await?; },
+            Output::Socket(w) => {
+                w.write_all(&stop).await?;
+                w.flush().await?;
+                expect(w.get_mut(), FINISH).await?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control() {
+        let frame = control(START, true);
+        assert_eq!(&frame[..12], &[0, 0, 0, 0, 0, 0, 0, 34, 0, 0, 0, 2]);
+        assert!(frame.ends_with(CONTENT_TYPE));
+        let mut reader = &frame[..];
+        assert_eq!(read_control(&mut reader).await.unwrap(), START);
+        let mut reader = &[0u8, 0, 0, 5, 1, 2, 3, 4, 5][..];
+        assert!(read_control(&mut reader).await.is_err());
+    }
+}
@@ -0,0 +1,8 @@
+#[macro_use] extern crate log;
+
+mod proto;
+mod framestream;
+mod dnstap;
+
+pub use proto::{MessageType, Protocol};
+pub use dnstap::{Peer, ZDnstap, DNSTAP};
@@ -0,0 +1,182 @@
+use std::{net::{IpAddr, SocketAddr}, time::{SystemTime, UNIX_EPOCH}};
+
+// dnstap.proto 中 Dnstap.Type::MESSAGE
+const DNSTAP_MESSAGE: u64 = 1;
+
+/// dnstap.proto 中的 Message.Type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    ClientQuery = 5,
+    ClientResponse = 6,
+    ForwarderQuery = 7,
+    ForwarderResponse = 8,
+}
+
+impl MessageType {
+    fn is_query(&self) -> bool {
+        matches!(self, Self::ClientQuery | Self::ForwarderQuery)
+    }
+}
+
+/// dnstap.proto 中的 SocketProtocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Udp = 1,
+    Tcp = 2,
+    Dot = 3,
+    Doh = 4,
+    Doq = 7,
+}
+
+/// 一条dnstap消息, 地址均为None时不输出socket相关字段.
+#[derive(Debug, Clone)]
+pub struct Message<'a> {
+    pub mtype: MessageType,
+    pub protocol: Protocol,
+    pub query_address: Option<SocketAddr>,
+    pub response_address: Option<SocketAddr>,
+    pub query_time: SystemTime,
+    pub response_time: Option<SystemTime>,
+    pub message: &'a [u8], // DNS报文, 按类型写入query_message或response_message
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn key(buf: &mut Vec<u8>, field: u32, wire: u8) {
+    varint(buf, ((field as u64) << 3) | wire as u64);
+}
+
+fn uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    key(buf, field, 0);
+    varint(buf, value);
+}
+
+fn fixed32(buf: &mut Vec<u8>, field: u32, value: u32) {
+    key(buf, field, 5);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn bytes(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    key(buf, field, 2);
+    varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn ip_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+fn time(buf: &mut Vec<u8>, sec_field: u32, time: SystemTime) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    uint(buf, sec_field, since.as_secs());
+    fixed32(buf, sec_field + 1, since.subsec_nanos());
+}
+
+impl Message<'_> {
+
+    fn encode_message(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.message.len() + 64);
+        uint(&mut buf, 1, self.mtype as u64);
+        let family = self.query_address.or(self.response_address).map(|a| if a.is_ipv4() { 1 } else { 2 });
+        if let Some(family) = family {
+            uint(&mut buf, 2, family);
+        }
+        uint(&mut buf, 3, self.protocol as u64);
+        if let Some(addr) = self.query_address {
+            bytes(&mut buf, 4, &ip_bytes(addr.ip()));
+        }
+        if let Some(addr) = self.response_address {
+            bytes(&mut buf, 5, &ip_bytes(addr.ip()));
+        }
+        if let Some(addr) = self.query_address {
+            uint(&mut buf, 6, addr.port() as u64);
+        }
+        if let Some(addr) = self.response_address {
+            uint(&mut buf, 7, addr.port() as u64);
+        }
+        time(&mut buf, 8, self.query_time);
+        if self.mtype.is_query() {
+            bytes(&mut buf, 10, self.message);
+        } else {
+            time(&mut buf, 12, self.response_time.unwrap_or_else(SystemTime::now));
+            bytes(&mut buf, 14, self.message);
+        }
+        buf
+    }
+
+    /// 编码为 Dnstap 帧内容
+    pub fn encode(&self, identity: &[u8], version: &[u8]) -> Vec<u8> {
+        let message = self.encode_message();
+        let mut buf = Vec::with_capacity(message.len() + identity.len() + version.len() + 8);
+        if !identity.is_empty() {
+            bytes(&mut buf, 1, identity);
+        }
+        if !version.is_empty() {
+            bytes(&mut buf, 2, version);
+        }
+        bytes(&mut buf, 14, &message);
+        uint(&mut buf, 15, DNSTAP_MESSAGE);
+        buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        let mut buf = Vec::new();
+        varint(&mut buf, 1);
+        varint(&mut buf, 300);
+        assert_eq!(buf, vec![0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn test_encode() {
+        let message = Message {
+            mtype: MessageType::ClientQuery,
+            protocol: Protocol::Udp,
+            query_address: Some("192.0.2.1:5300".parse().unwrap()),
+            response_address: Some("192.0.2.53:53".parse().unwrap()),
+            query_time: UNIX_EPOCH + Duration::new(1, 2),
+            response_time: None,
+            message: b"\xab\xcd",
+        };
+        let inner = vec![
+            0x08, 0x05, // type CLIENT_QUERY
+            0x10, 0x01, // socket_family INET
+            0x18, 0x01, // socket_protocol UDP
+            0x22, 0x04, 192, 0, 2, 1, // query_address
+            0x2a, 0x04, 192, 0, 2, 53, // response_address
+            0x30, 0xb4, 0x29, // query_port 5300
+            0x38, 0x35, // response_port 53
+            0x40, 0x01, // query_time_sec
+            0x4d, 0x02, 0x00, 0x00, 0x00, // query_time_nsec
+            0x52, 0x02, 0xab, 0xcd, // query_message
+        ];
+        let mut expected = vec![0x0a, 0x05];
+        expected.extend_from_slice(b"zzdns");
+        expected.extend_from_slice(&[0x72, inner.len() as u8]);
+        expected.extend_from_slice(&inner);
+        expected.extend_from_slice(&[0x78, 0x01]);
+        assert_eq!(message.encode(b"zzdns", b""), expected);
+
+        let response = Message { mtype: MessageType::ForwarderResponse, query_address: None, response_address: None,
+            response_time: Some(UNIX_EPOCH + Duration::new(2, 0)), ..message };
+        let encoded = response.encode(b"", b"");
+        // 无地址时不输出socket_family, 应答报文为字段14
+        assert_eq!(&encoded[2..6], &[0x08, 0x08, 0x18, 0x01]);
+        assert!(encoded.ends_with(&[0x72, 0x02, 0xab, 0xcd, 0x78, 0x01]));
+    }
+}
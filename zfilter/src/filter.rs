@@ -0,0 +1,261 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{Message, MessageBuilder, Rtype, iana::{Class, Rcode}};
+use domain::rdata;
+use ipnet::IpNet;
+use tokio::fs;
+use zconfig::Filter as FilterConf;
+use crate::{parser::parse_line, trie::DomainTrie};
+
+// 拦截响应中 0.0.0.0/:: 记录的TTL
+const BLOCK_TTL: u32 = 60;
+
+/// 被拦截域名的应答方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockResponse {
+    NxDomain,
+    Null,
+    Refused,
+}
+
+impl From<&str> for BlockResponse {
+    fn from(s: &str) -> Self {
+        match s.to_ascii_lowercase().as_str() {
+            "null" | "0.0.0.0" => Self::Null,
+            "refused" => Self::Refused,
+            _ => Self::NxDomain,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Policy {
+    name: String,
+    blocklist: DomainTrie,
+    allowlist: DomainTrie,
+    response: BlockResponse,
+}
+
+impl Policy {
+
+    async fn load(name: String, blocklists: &[String], allowlists: &[String], response: Option<&str>) -> Result<Self> {
+        let mut blocklist = DomainTrie::new();
+        let mut allowlist = DomainTrie::new();
+        for filename in blocklists.iter() {
+            load_list(filename, &mut blocklist, &mut allowlist).await?;
+        }
+        for filename in allowlists.iter() {
+            load_list(filename, &mut allowlist, &mut DomainTrie::new()).await?;
+        }
+        let response = BlockResponse::from(response.unwrap_or("nxdomain"));
+        info!("zfilter policy<{}> loaded, block rules: {}, allow rules: {}, response: {:?}",
+            name, blocklist.len(), allowlist.len(), response);
+        Ok(Self { name, blocklist, allowlist, response })
+    }
+
+    fn is_blocked(&self, qname: &str) -> bool {
+        !self.allowlist.matches(qname) && self.blocklist.matches(qname)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ZFilter {
+    default: Policy,
+    policies: Vec<(Vec<IpNet>, Policy)>,
+}
+
+impl ZFilter {
+
+    pub async fn build(conf: Option<FilterConf>) -> Result<Self> {
+        let conf = match conf {
+            Some(c) => c,
+            None => return Ok(Self {
+                default: Policy {
+                    name: "default".to_string(),
+                    blocklist: DomainTrie::new(),
+                    allowlist: DomainTrie::new(),
+                    response: BlockResponse::NxDomain,
+                },
+                policies: Vec::new(),
+            }),
+        };
+        let default = Policy::load("default".to_string(), &conf.blocklists,
+            &conf.allowlists.unwrap_or_default(), conf.response.as_deref()).await?;
+
+        let mut policies = Vec::new();
+        for pconf in conf.policies.unwrap_or_default() {
+            let mut nets = Vec::new();
+            for client in pconf.clients.iter() {
+                nets.push(parse_net(client)?);
+            }
+            let policy = Policy::load(pconf.name, &pconf.blocklists,
+                &pconf.allowlists.unwrap_or_default(), pconf.response.as_deref()).await?;
+            policies.push((nets, policy));
+        }
+        Ok(Self { default, policies })
+    }
+
+    pub fn is_blocked(&self, src: IpAddr, qname: &str) -> bool {
+        self.select(src).is_blocked(qname)
+    }
+
+    /// 查询的域名被拦截时返回应答报文, 否则返回 `None`.
+    pub fn filter(&self, src: &SocketAddr, qmsg: &Message<Bytes>) -> Result<Option<Bytes>> {
+        let question = qmsg.sole_question()?;
+        let policy = self.select(src.ip());
+        if !policy.is_blocked(&question.qname().to_string()) {
+            return Ok(None);
+        }
+        debug!("query blocked, client: {:?}, policy: {}, qname: {:?}, qtype: {:?}",
+            src, policy.name, question.qname().to_string(), question.qtype());
+        Ok(Some(block_answer(qmsg, policy.response)?))
+    }
+
+    // 选择网段前缀最长的策略, 未匹配时使用默认策略.
+    fn select(&self, src: IpAddr) -> &Policy {
+        let src = src.to_canonical();
+        let mut selected: Option<(u8, &Policy)> = None;
+        for (nets, policy) in self.policies.iter() {
+            for net in nets.iter().filter(|n| n.contains(&src)) {
+                if selected.is_none_or(|(len, _)| net.prefix_len() > len) {
+                    selected = Some((net.prefix_len(), policy));
+                }
+            }
+        }
+        selected.map_or(&self.default, |(_, p)| p)
+    }
+}
+
+fn parse_net(s: &str) -> Result<IpNet> {
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| anyhow!("Invalid client network: {:?}", s))
+}
+
+// 读取规则列表, Adblock例外规则(`@@||example.com^`)加入放行列表. 文件无法读取时返回错误.
+async fn load_list(filename: &str, trie: &mut DomainTrie, exceptions: &mut DomainTrie) -> Result<()> {
+    let lines = fs::read_to_string(filename).await
+        .map_err(|e| anyhow!("Failed to read filter list({:?}), error: {}", filename, e))?;
+    let mut invalid = 0;
+    for line in lines.lines() {
+        let (line, target) = match line.trim_start().strip_prefix("@@") {
+            Some(l) => (l, &mut *exceptions),
+            None => (line, &mut *trie),
+        };
+        match parse_line(line) {
+            Ok(rules) => {
+                for (domain, kind) in rules {
+                    target.insert(&domain, kind);
+                }
+            },
+            Err(_) => invalid += 1,
+        }
+    }
+    if invalid > 0 {
+        warn!("Skipped {} invalid lines in filter list({:?})", invalid, filename);
+    }
+    Ok(())
+}
+
+fn block_answer(qmsg: &Message<Bytes>, response: BlockResponse) -> Result<Bytes> {
+    let rcode = match response {
+        BlockResponse::NxDomain => Rcode::NXDomain,
+        BlockResponse::Refused => Rcode::Refused,
+        BlockResponse::Null => Rcode::NoError,
+    };
+    let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+            .start_answer(qmsg, rcode)?;
+    rmsg.header_mut().set_ra(true);
+
+    if response == BlockResponse::Null {
+        let question = qmsg.sole_question()?;
+        match question.qtype() {
+            Rtype::A => rmsg.push((question.qname(), Class::In, BLOCK_TTL, rdata::A::new(Ipv4Addr::UNSPECIFIED)))?,
+            Rtype::Aaaa => rmsg.push((question.qname(), Class::In, BLOCK_TTL, rdata::Aaaa::new(Ipv6Addr::UNSPECIFIED)))?,
+            _ => {},
+        }
+    }
+    Ok(rmsg.into_message().into_octets())
+}
+
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+    use domain::base::{Dname, Message, MessageBuilder, Question, Rtype, iana::Rcode};
+    use crate::trie::{DomainTrie, MatchKind};
+    use super::{block_answer, parse_net, BlockResponse, Policy, ZFilter};
+
+    fn query(name: &str, qtype: Rtype) -> Message<bytes::Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(Dname::bytes_from_str(name).unwrap(), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    #[test]
+    fn test_block_answer() {
+        let qmsg = query("ads.example.com", Rtype::A);
+
+        let rmsg = Message::from_octets(block_answer(&qmsg, BlockResponse::NxDomain).unwrap()).unwrap();
+        assert_eq!(rmsg.header().rcode(), Rcode::NXDomain);
+        assert_eq!(rmsg.header().id(), qmsg.header().id());
+
+        let rmsg = Message::from_octets(block_answer(&qmsg, BlockResponse::Null).unwrap()).unwrap();
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let qmsg = query("ads.example.com", Rtype::Mx);
+        let rmsg = Message::from_octets(block_answer(&qmsg, BlockResponse::Null).unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+    }
+
+    fn policy(name: &str, blocked: &[&str], allowed: &[&str]) -> Policy {
+        let mut blocklist = DomainTrie::new();
+        let mut allowlist = DomainTrie::new();
+        blocked.iter().for_each(|d| blocklist.insert(d, MatchKind::Suffix));
+        allowed.iter().for_each(|d| allowlist.insert(d, MatchKind::Suffix));
+        Policy { name: name.to_string(), blocklist, allowlist, response: BlockResponse::NxDomain }
+    }
+
+    #[test]
+    fn test_policy_select() {
+        let zfilter = ZFilter {
+            default: policy("default", &["ads.com"], &["ok.ads.com"]),
+            policies: vec![
+                (vec![parse_net("192.168.0.0/16").unwrap()], policy("lan", &["games.com"], &[])),
+                (vec![parse_net("192.168.20.0/24").unwrap(), parse_net("10.0.0.1").unwrap()], policy("kids", &["games.com", "video.com"], &[])),
+            ],
+        };
+        let default = "127.0.0.1".parse().unwrap();
+        assert!(zfilter.is_blocked(default, "x.ads.com"));
+        assert!(!zfilter.is_blocked(default, "ok.ads.com"));
+        assert!(!zfilter.is_blocked(default, "games.com"));
+
+        let lan = "192.168.1.2".parse().unwrap();
+        assert!(zfilter.is_blocked(lan, "games.com"));
+        assert!(!zfilter.is_blocked(lan, "video.com"));
+
+        let kids = "192.168.20.9".parse().unwrap();
+        assert!(zfilter.is_blocked(kids, "video.com"));
+        assert!(zfilter.is_blocked("10.0.0.1".parse().unwrap(), "video.com"));
+        assert!(zfilter.is_blocked("::ffff:192.168.20.9".parse().unwrap(), "video.com"));
+        assert!(parse_net("not-a-net").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build() {
+        let path = std::env::temp_dir().join(format!("zzdns-filter-{}.txt", std::process::id()));
+        std::fs::write(&path, "||ads.example.com^\n@@||ok.ads.example.com^\n").unwrap();
+        let conf = zconfig::Filter { blocklists: vec![path.to_string_lossy().to_string()], allowlists: None, response: None, policies: None };
+        let zfilter = ZFilter::build(Some(conf.clone())).await.unwrap();
+        let src = "127.0.0.1".parse().unwrap();
+        assert!(zfilter.is_blocked(src, "x.ads.example.com"));
+        assert!(!zfilter.is_blocked(src, "ok.ads.example.com"));
+        let _ = std::fs::remove_file(&path);
+
+        let err = ZFilter::build(Some(conf)).await.unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read filter list"), "{}", err);
+    }
+}
@@ -0,0 +1,8 @@
+#[macro_use] extern crate log;
+
+mod filter;
+mod parser;
+mod trie;
+
+pub use filter::{ZFilter, BlockResponse};
+pub use trie::{DomainTrie, MatchKind};
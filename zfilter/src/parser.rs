@@ -0,0 +1,118 @@
+use std::net::IpAddr;
+
+use crate::trie::MatchKind;
+
+// hosts文件中常见的本地条目, 不作为拦截规则.
+const HOSTS_SKIP: [&str; 8] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-localnet",
+    "ip6-allnodes",
+];
+
+// Adblock元素隐藏及脚本注入规则的分隔符
+const COSMETIC: [&str; 4] = ["##", "#@#", "#?#", "#$#"];
+
+/// 解析规则列表中的一行, 支持以下格式:
+/// - hosts: `0.0.0.0 ads.example.com tracker.example.com`
+/// - 域名列表: `example.com` 或 `*.example.com`
+/// - Adblock: `||example.com^`
+///
+/// 空行及注释返回 `Ok(vec![])`, 无法识别的行返回 `Err`.
+pub fn parse_line(line: &str) -> Result<Vec<(String, MatchKind)>, ()> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+        return Ok(vec![]);
+    }
+    // Adblock元素隐藏规则只作用于页面, 不拦截域名
+    if COSMETIC.iter().any(|m| line.contains(m)) {
+        return Ok(vec![]);
+    }
+    // `#` 在行首或空白之后时为注释
+    let line = match line.char_indices().find(|(i, c)| *c == '#' && (*i == 0 || line[..*i].ends_with(char::is_whitespace))) {
+        Some((i, _)) => line[..i].trim(),
+        None => line,
+    };
+    if line.is_empty() {
+        return Ok(vec![]);
+    }
+
+    if let Some(rule) = line.strip_prefix("||") {
+        let rule = rule.split('$').next().unwrap_or_default();
+        let domain = rule.strip_suffix('^').ok_or(())?;
+        return check_domain(domain).map(|d| vec![(d, MatchKind::Suffix)]);
+    }
+
+    let mut tokens = line.split_whitespace();
+    let first = tokens.next().ok_or(())?;
+    if first.parse::<IpAddr>().is_ok() {
+        let mut rules = Vec::new();
+        for name in tokens {
+            if HOSTS_SKIP.contains(&name) {
+                continue;
+            }
+            rules.push((check_domain(name)?, MatchKind::Exact));
+        }
+        return Ok(rules);
+    }
+
+    if tokens.next().is_some() {
+        return Err(());
+    }
+    match first.strip_prefix("*.") {
+        Some(domain) => check_domain(domain).map(|d| vec![(d, MatchKind::Wildcard)]),
+        None => check_domain(first).map(|d| vec![(d, MatchKind::Suffix)]),
+    }
+}
+
+fn check_domain(domain: &str) -> Result<String, ()> {
+    let domain = domain.trim_end_matches('.');
+    if domain.is_empty() || domain.len() > 253 {
+        return Err(());
+    }
+    let valid = domain.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    });
+    if valid {
+        Ok(domain.to_ascii_lowercase())
+    } else {
+        Err(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::parse_line;
+    use crate::trie::MatchKind;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("# comment"), Ok(vec![]));
+        assert_eq!(parse_line("! adblock comment"), Ok(vec![]));
+        assert_eq!(parse_line("   "), Ok(vec![]));
+        assert_eq!(parse_line("0.0.0.0 localhost"), Ok(vec![]));
+        assert_eq!(
+            parse_line("0.0.0.0 Ads.Example.com t.example.com # trackers"),
+            Ok(vec![("ads.example.com".to_string(), MatchKind::Exact), ("t.example.com".to_string(), MatchKind::Exact)])
+        );
+        assert_eq!(parse_line("||doubleclick.net^"), Ok(vec![("doubleclick.net".to_string(), MatchKind::Suffix)]));
+        assert_eq!(parse_line("||doubleclick.net^$important"), Ok(vec![("doubleclick.net".to_string(), MatchKind::Suffix)]));
+        assert_eq!(parse_line("*.cdn.org"), Ok(vec![("cdn.org".to_string(), MatchKind::Wildcard)]));
+        assert_eq!(parse_line("tracker.net"), Ok(vec![("tracker.net".to_string(), MatchKind::Suffix)]));
+        assert!(parse_line("||/banner/*").is_err());
+        assert!(parse_line("two words").is_err());
+        // 元素隐藏规则不拦截整个域名
+        assert_eq!(parse_line("example.com##.ad-banner"), Ok(vec![]));
+        assert_eq!(parse_line("example.com#@#.ad-banner"), Ok(vec![]));
+        assert_eq!(parse_line("example.com#?#div:has(> .ad)"), Ok(vec![]));
+        assert_eq!(parse_line("example.com#$#body { overflow: auto; }"), Ok(vec![]));
+        assert!(parse_line("example.com#anchor").is_err());
+        assert_eq!(parse_line("tracker.net\t# comment"), Ok(vec![("tracker.net".to_string(), MatchKind::Suffix)]));
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// 规则匹配范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Exact,     // 仅匹配域名本身, 如hosts文件条目
+    Wildcard,  // 仅匹配子域名, 如 *.example.com
+    Suffix,    // 匹配域名本身及其子域名, 如 ||example.com^
+}
+
+#[derive(Debug, Default, Clone)]
+struct Node {
+    children: HashMap<Box<str>, Node>,
+    exact: bool,
+    subdomains: bool,
+}
+
+/// 按标签逆序存储域名的前缀树, example.com 存储为 com -> example.
+#[derive(Debug, Default, Clone)]
+pub struct DomainTrie {
+    root: Node,
+    len: usize,
+}
+
+impl DomainTrie {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, domain: &str, kind: MatchKind) {
+        let mut node = &mut self.root;
+        for label in labels(domain) {
+            node = node.children.entry(label.into()).or_default();
+        }
+        let is_new = !node.exact && !node.subdomains;
+        match kind {
+            MatchKind::Exact => node.exact = true,
+            MatchKind::Wildcard => node.subdomains = true,
+            MatchKind::Suffix => {
+                node.exact = true;
+                node.subdomains = true;
+            },
+        }
+        if is_new {
+            self.len += 1;
+        }
+    }
+
+    pub fn matches(&self, domain: &str) -> bool {
+        let mut node = &self.root;
+        for label in labels(domain) {
+            if node.subdomains {
+                return true;
+            }
+            node = match node.children.get(label.as_str()) {
+                Some(n) => n,
+                None => return false,
+            };
+        }
+        node.exact
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+fn labels(domain: &str) -> impl Iterator<Item = String> + '_ {
+    domain.trim_end_matches('.')
+        .rsplit('.')
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_ascii_lowercase())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{DomainTrie, MatchKind};
+
+    #[test]
+    fn test_trie_matches() {
+        let mut trie = DomainTrie::new();
+        trie.insert("ads.example.com", MatchKind::Exact);
+        trie.insert("tracker.net", MatchKind::Suffix);
+        trie.insert("cdn.org", MatchKind::Wildcard);
+
+        assert!(trie.matches("ads.example.com"));
+        assert!(trie.matches("ADS.example.com."));
+        assert!(!trie.matches("x.ads.example.com"));
+        assert!(!trie.matches("example.com"));
+
+        assert!(trie.matches("tracker.net"));
+        assert!(trie.matches("a.b.tracker.net"));
+        assert!(!trie.matches("nottracker.net"));
+
+        assert!(!trie.matches("cdn.org"));
+        assert!(trie.matches("img.cdn.org"));
+        assert_eq!(trie.len(), 3);
+    }
+}
@@ -0,0 +1,7 @@
+#[macro_use] extern crate log;
+
+mod local;
+mod record;
+
+pub use local::ZLocal;
+pub use record::LocalData;
@@ -0,0 +1,221 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use domain::base::{Message, MessageBuilder, Rtype, iana::Rcode};
+use tokio::fs;
+use zconfig::Local as LocalConf;
+use crate::record::{parse_dname, reverse_name, LocalData};
+
+// 本地记录默认TTL
+const LOCAL_TTL: u32 = 60;
+// CNAME链最大长度
+const MAX_CNAME_CHAIN: usize = 8;
+
+#[derive(Debug, Clone)]
+struct LocalRecord {
+    data: LocalData,
+    ttl: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ZLocal {
+    records: HashMap<String, Vec<LocalRecord>>,
+    wildcards: HashMap<String, Vec<LocalRecord>>, // *.home.lan 以 home.lan 为键
+}
+
+impl ZLocal {
+
+    pub async fn build(conf: Option<LocalConf>) -> Result<Self> {
+        let mut zlocal = Self::default();
+        let conf = match conf {
+            Some(c) => c,
+            None => return Ok(zlocal),
+        };
+        let default_ttl = conf.ttl.unwrap_or(LOCAL_TTL);
+        // 按加载顺序记录地址, 用于合成PTR记录
+        let mut addrs = Vec::new();
+
+        for record in conf.records.unwrap_or_default() {
+            let data = LocalData::parse(&record.rtype, &record.value)?;
+            if let LocalData::A(addr) = data {
+                addrs.push((IpAddr::V4(addr), record.name.clone()));
+            }
+            if let LocalData::Aaaa(addr) = data {
+                addrs.push((IpAddr::V6(addr), record.name.clone()));
+            }
+            zlocal.insert(&record.name, data, record.ttl.unwrap_or(default_ttl))?;
+        }
+
+        for filename in conf.hosts_files.unwrap_or_default() {
+            let lines = match fs::read_to_string(&filename).await {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Failed to read hosts file({:?}), error: {:?}", filename, e);
+                    continue;
+                }
+            };
+            for line in lines.lines() {
+                let line = line.split('#').next().unwrap_or_default();
+                let mut tokens = line.split_whitespace();
+                let addr = match tokens.next().map(|t| t.parse::<IpAddr>()) {
+                    Some(Ok(addr)) => addr,
+                    _ => continue,
+                };
+                for name in tokens {
+                    addrs.push((addr, name.to_string()));
+                    zlocal.insert(name, LocalData::from_addr(addr), default_ttl)?;
+                }
+            }
+        }
+
+        zlocal.synthesize_ptr(addrs, default_ttl)?;
+        info!("zlocal loaded, names: {}, wildcards: {}", zlocal.records.len(), zlocal.wildcards.len());
+        Ok(zlocal)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.wildcards.is_empty()
+    }
+
+    fn insert(&mut self, name: &str, data: LocalData, ttl: u32) -> Result<()> {
+        let name = normalize(name);
+        let (map, name) = match name.strip_prefix("*.") {
+            Some(n) => (&mut self.wildcards, n.to_string()),
+            None => (&mut self.records, name),
+        };
+        parse_dname(&name)?;
+        map.entry(name).or_default().push(LocalRecord { data, ttl });
+        Ok(())
+    }
+
+    // 为A/AAAA记录合成PTR记录, 同一地址以最先出现的域名为准, 不覆盖显式配置的PTR记录.
+    fn synthesize_ptr(&mut self, addrs: Vec<(IpAddr, String)>, ttl: u32) -> Result<()> {
+        for (addr, name) in addrs {
+            if addr.is_unspecified() || name.starts_with("*.") {
+                continue;
+            }
+            let rname = reverse_name(addr);
+            if self.records.contains_key(&rname) {
+                continue;
+            }
+            let data = LocalData::Ptr(parse_dname(&name)?);
+            self.records.insert(rname, vec![LocalRecord { data, ttl }]);
+        }
+        Ok(())
+    }
+
+    // 精确匹配优先, 其次匹配最近的通配符记录.
+    fn lookup(&self, name: &str) -> Option<&Vec<LocalRecord>> {
+        if let Some(records) = self.records.get(name) {
+            return Some(records);
+        }
+        let mut parent = name;
+        while let Some((_, p)) = parent.split_once('.') {
+            if let Some(records) = self.wildcards.get(p) {
+                return Some(records);
+            }
+            parent = p;
+        }
+        None
+    }
+
+    /// 查询的域名存在本地记录时返回权威应答, 否则返回 `None`.
+    pub fn answer(&self, qmsg: &Message<Bytes>) -> Result<Option<Bytes>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let question = qmsg.sole_question()?;
+        let qtype = question.qtype();
+        let mut name = normalize(&question.qname().to_string());
+        let mut records = match self.lookup(&name) {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, Rcode::NoError)?;
+        let header = rmsg.header_mut();
+        header.set_aa(true);
+        header.set_ra(true);
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            let owner = parse_dname(&name)?;
+            let matched: Vec<&LocalRecord> = records.iter()
+                .filter(|r| qtype == Rtype::Any || r.data.rtype() == qtype)
+                .collect();
+            if !matched.is_empty() {
+                for record in matched {
+                    record.data.push(&mut rmsg, &owner, record.ttl)?;
+                }
+                break;
+            }
+            // 没有对应类型的记录时跟随CNAME, 目标不在本地时只返回CNAME.
+            let cname = records.iter().find(|r| r.data.rtype() == Rtype::Cname);
+            let (target, record) = match cname {
+                Some(r @ LocalRecord { data: LocalData::Cname(target), .. }) => (target, r),
+                _ => break,
+            };
+            record.data.push(&mut rmsg, &owner, record.ttl)?;
+            name = normalize(&target.to_string());
+            records = match self.lookup(&name) {
+                Some(r) => r,
+                None => break,
+            };
+        }
+        Ok(Some(rmsg.into_message().into_octets()))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+
+#[cfg(test)]
+mod test {
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Question, Rtype, iana::Rcode};
+    use crate::record::LocalData;
+    use super::ZLocal;
+
+    fn query(name: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(Dname::bytes_from_str(name).unwrap(), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn zlocal() -> ZLocal {
+        let mut zlocal = ZLocal::default();
+        zlocal.insert("nas.home.lan", LocalData::parse("A", "192.168.1.10").unwrap(), 60).unwrap();
+        zlocal.insert("www.home.lan", LocalData::parse("CNAME", "nas.home.lan").unwrap(), 60).unwrap();
+        zlocal.insert("*.dev.home.lan", LocalData::parse("A", "192.168.1.20").unwrap(), 60).unwrap();
+        zlocal.synthesize_ptr(vec![("192.168.1.10".parse().unwrap(), "nas.home.lan".to_string())], 60).unwrap();
+        zlocal
+    }
+
+    #[test]
+    fn test_answer() {
+        let zlocal = zlocal();
+
+        let rmsg = Message::from_octets(zlocal.answer(&query("NAS.home.lan", Rtype::A)).unwrap().unwrap()).unwrap();
+        assert!(rmsg.header().aa());
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let rmsg = Message::from_octets(zlocal.answer(&query("www.home.lan", Rtype::A)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 2);
+
+        let rmsg = Message::from_octets(zlocal.answer(&query("app.dev.home.lan", Rtype::A)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let rmsg = Message::from_octets(zlocal.answer(&query("nas.home.lan", Rtype::Aaaa)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+
+        let rmsg = Message::from_octets(zlocal.answer(&query("10.1.168.192.in-addr.arpa", Rtype::Ptr)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        assert!(zlocal.answer(&query("dev.home.lan", Rtype::A)).unwrap().is_none());
+        assert!(zlocal.answer(&query("example.com", Rtype::A)).unwrap().is_none());
+    }
+}
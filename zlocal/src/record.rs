@@ -0,0 +1,138 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{Dname, Rtype, iana::Class};
+use domain::base::message_builder::AnswerBuilder;
+use domain::rdata;
+
+/// 本地记录数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(Dname<Bytes>),
+    Txt(String),
+    Ptr(Dname<Bytes>),
+    Srv { priority: u16, weight: u16, port: u16, target: Dname<Bytes> },
+    Mx { preference: u16, exchange: Dname<Bytes> },
+}
+
+impl LocalData {
+
+    /// 按记录类型解析记录值, 格式同zone文件, 如MX为 `10 mail.example.com`,
+    /// SRV为 `0 5 5060 sip.example.com`.
+    pub fn parse(rtype: &str, value: &str) -> Result<Self> {
+        let value = value.trim();
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let data = match (rtype.to_ascii_uppercase().as_str(), fields.as_slice()) {
+            ("A", [addr]) => Self::A(addr.parse()?),
+            ("AAAA", [addr]) => Self::Aaaa(addr.parse()?),
+            ("CNAME", [name]) => Self::Cname(parse_dname(name)?),
+            ("PTR", [name]) => Self::Ptr(parse_dname(name)?),
+            ("TXT", _) => Self::Txt(value.trim_matches('"').to_string()),
+            ("MX", [preference, exchange]) => Self::Mx {
+                preference: preference.parse()?,
+                exchange: parse_dname(exchange)?,
+            },
+            ("SRV", [priority, weight, port, target]) => Self::Srv {
+                priority: priority.parse()?,
+                weight: weight.parse()?,
+                port: port.parse()?,
+                target: parse_dname(target)?,
+            },
+            _ => return Err(anyhow!("Invalid local record, type: {:?}, value: {:?}", rtype, value)),
+        };
+        Ok(data)
+    }
+
+    pub fn from_addr(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(addr) => Self::A(addr),
+            IpAddr::V6(addr) => Self::Aaaa(addr),
+        }
+    }
+
+    pub fn rtype(&self) -> Rtype {
+        match self {
+            Self::A(_) => Rtype::A,
+            Self::Aaaa(_) => Rtype::Aaaa,
+            Self::Cname(_) => Rtype::Cname,
+            Self::Txt(_) => Rtype::Txt,
+            Self::Ptr(_) => Rtype::Ptr,
+            Self::Srv { .. } => Rtype::Srv,
+            Self::Mx { .. } => Rtype::Mx,
+        }
+    }
+
+    pub fn push(&self, rmsg: &mut AnswerBuilder<BytesMut>, owner: &Dname<Bytes>, ttl: u32) -> Result<()> {
+        let owner = owner.clone();
+        match self {
+            Self::A(addr) => rmsg.push((owner, Class::In, ttl, rdata::A::new(*addr)))?,
+            Self::Aaaa(addr) => rmsg.push((owner, Class::In, ttl, rdata::Aaaa::new(*addr)))?,
+            Self::Cname(name) => rmsg.push((owner, Class::In, ttl, rdata::Cname::new(name.clone())))?,
+            Self::Ptr(name) => rmsg.push((owner, Class::In, ttl, rdata::Ptr::new(name.clone())))?,
+            Self::Txt(text) => {
+                let txt = rdata::Txt::<Bytes>::from_slice(text.as_bytes())?;
+                rmsg.push((owner, Class::In, ttl, txt))?
+            },
+            Self::Srv { priority, weight, port, target } => {
+                rmsg.push((owner, Class::In, ttl, rdata::Srv::new(*priority, *weight, *port, target.clone())))?
+            },
+            Self::Mx { preference, exchange } => {
+                rmsg.push((owner, Class::In, ttl, rdata::Mx::new(*preference, exchange.clone())))?
+            },
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_dname(name: &str) -> Result<Dname<Bytes>> {
+    Dname::bytes_from_str(name.trim_end_matches('.'))
+        .map_err(|e| anyhow!("Invalid domain name {:?}, error: {}", name, e))
+}
+
+/// IP地址对应的反向解析域名, 如 `1.0.168.192.in-addr.arpa`.
+pub fn reverse_name(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => {
+            let o = addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        },
+        IpAddr::V6(addr) => {
+            let mut name = String::with_capacity(72);
+            for octet in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", octet & 0x0f, octet >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use domain::base::Rtype;
+    use super::{reverse_name, LocalData};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(LocalData::parse("a", "192.168.1.10").unwrap(), LocalData::A("192.168.1.10".parse().unwrap()));
+        assert_eq!(LocalData::parse("TXT", "\"v=spf1 -all\"").unwrap(), LocalData::Txt("v=spf1 -all".to_string()));
+        assert_eq!(LocalData::parse("MX", "10 mail.home.lan").unwrap().rtype(), Rtype::Mx);
+        assert_eq!(LocalData::parse("SRV", "0 5 5060 sip.home.lan.").unwrap().rtype(), Rtype::Srv);
+        assert!(LocalData::parse("A", "::1").is_err());
+        assert!(LocalData::parse("MX", "mail.home.lan").is_err());
+        assert!(LocalData::parse("HINFO", "x86 linux").is_err());
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        assert_eq!(reverse_name("192.168.1.10".parse().unwrap()), "10.1.168.192.in-addr.arpa");
+        assert_eq!(
+            reverse_name("2001:db8::1".parse().unwrap()),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+}
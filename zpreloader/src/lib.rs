@@ -1,5 +1,7 @@
 #[macro_use] extern crate log;
 
 mod preloader;
+mod popularity;
 
-pub use preloader::ZPreloader;
\ No newline at end of file
+pub use preloader::ZPreloader;
+pub use popularity::ZPopularity;
\ No newline at end of file
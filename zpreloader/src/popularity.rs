@@ -0,0 +1,215 @@
+use std::{collections::{hash_map::RandomState, HashMap}, hash::BuildHasher, sync::{Arc, Mutex}, time::Duration};
+
+use anyhow::Result;
+use tokio::fs;
+use zshutdown::SHUTDOWN;
+use zcacher::ZCacher;
+
+// 默认保留的热门域名数量
+const POPULAR_SIZE: usize = 1000;
+// 检查热门域名缓存是否即将过期的间隔
+const REFRESH_INTERVAL: u64 = 30;
+// 保存热门列表并衰减计数的间隔
+const SAVE_INTERVAL: u64 = 300;
+// 统计的域名数超过热门数量的倍数时裁剪
+const TRACK_FACTOR: usize = 10;
+// 计数的分片数, 减少查询路径上的锁竞争
+const SHARDS: usize = 16;
+
+/// 按查询次数统计热门域名, 持久化热门列表用于启动时预加载, 并在缓存过期前主动刷新.
+pub struct ZPopularity {
+    file: Option<String>,
+    size: usize,
+    hasher: RandomState,
+    shards: Vec<Mutex<HashMap<String, u64>>>,
+}
+
+impl ZPopularity {
+
+    pub fn new(file: Option<String>, size: Option<u16>) -> Self {
+        let size = size.map(usize::from).unwrap_or(POPULAR_SIZE).max(1);
+        Self { file, size, hasher: RandomState::new(), shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect() }
+    }
+
+    fn shard(&self, domain: &str) -> &Mutex<HashMap<String, u64>> {
+        &self.shards[self.hasher.hash_one(domain) as usize % SHARDS]
+    }
+
+    // 单个分片超过该数量时裁剪, 保留分片内的前 `size` 个不影响整体的热门列表
+    fn shard_limit(&self) -> usize {
+        (self.size * TRACK_FACTOR / SHARDS).max(self.size * 2)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// 记录一次查询, 域名不区分大小写
+    pub fn hit(&self, domain: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let domain = normalize(domain);
+        let mut counts = self.shard(&domain).lock().unwrap();
+        *counts.entry(domain).or_insert(0) += 1;
+        if counts.len() > self.shard_limit() {
+            let top = top(counts.iter(), self.size);
+            *counts = top.into_iter().collect();
+        }
+    }
+
+    /// 查询次数最多的域名, 按次数降序.
+    pub fn top(&self) -> Vec<(String, u64)> {
+        let list: Vec<(String, u64)> = self.shards.iter().flat_map(|s| top(s.lock().unwrap().iter(), self.size)).collect();
+        top(list.iter().map(|(k, v)| (k, v)), self.size)
+    }
+
+    /// 读取热门列表并恢复计数, 返回其中的域名. 每行为 `域名 次数`, 次数可省略.
+    pub async fn load(&self) -> Result<Vec<String>> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(Vec::new()),
+        };
+        let content = match fs::read_to_string(path).await {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut domains = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let domain = match fields.next() {
+                Some(d) => normalize(d),
+                None => continue,
+            };
+            let count = fields.next().and_then(|c| c.parse::<u64>().ok()).unwrap_or(1);
+            *self.shard(&domain).lock().unwrap().entry(domain.clone()).or_insert(0) += count;
+            domains.push(domain);
+        }
+        domains.truncate(self.size);
+        Ok(domains)
+    }
+
+    /// 写入热门列表, 之后将计数减半, 使列表随查询变化而更新.
+    pub async fn save(&self) -> Result<()> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content: String = self.top().iter().map(|(domain, count)| format!("{} {}\n", domain, count)).collect();
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, content).await?;
+        fs::rename(&tmp, path).await?;
+        self.decay();
+        Ok(())
+    }
+
+    fn decay(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+        }
+    }
+
+    /// 定期刷新即将过期的热门域名并保存热门列表, 退出时保存.
+    pub async fn serve(&self, cacher: Arc<ZCacher>) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        info!("popularity tracking running...");
+        let mut refresh = tokio::time::interval(Duration::from_secs(REFRESH_INTERVAL));
+        let period = Duration::from_secs(SAVE_INTERVAL);
+        let mut save = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = refresh.tick() => {
+                    let mut count = 0;
+                    for (domain, _) in self.top() {
+                        if cacher.expiring(&domain).await && cacher.push(domain).await.is_ok() {
+                            count += 1;
+                        }
+                    }
+                    if count > 0 {
+                        debug!("Refresh popular domains, count:{:?}", count);
+                    }
+                }
+                _ = save.tick() => {
+                    if let Err(e) = self.save().await {
+                        error!("Failed to save popular domains, error:{:?}", e);
+                    }
+                }
+                _ = SHUTDOWN.wait() => {
+                    return self.save().await;
+                }
+            }
+        }
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn top<'a>(counts: impl Iterator<Item = (&'a String, &'a u64)>, size: usize) -> Vec<(String, u64)> {
+    let mut list: Vec<(String, u64)> = counts.map(|(k, v)| (k.clone(), *v)).collect();
+    list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    list.truncate(size);
+    list
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_top() {
+        let popularity = ZPopularity::new(Some("unused".to_string()), Some(2));
+        for domain in ["a.com", "b.com", "B.com", "c.com", "C.COM", "c.com."] {
+            popularity.hit(domain);
+        }
+        assert_eq!(popularity.top(), vec![("c.com".to_string(), 3), ("b.com".to_string(), 2)]);
+
+        let disabled = ZPopularity::new(None, None);
+        disabled.hit("a.com");
+        assert!(disabled.top().is_empty());
+    }
+
+    #[test]
+    fn test_prune() {
+        let popularity = ZPopularity::new(Some("unused".to_string()), Some(1));
+        popularity.hit("a.com");
+        popularity.hit("a.com");
+        for i in 0..1000 {
+            popularity.hit(&format!("{}.com", i));
+        }
+        // 每个分片裁剪后保留的域名数有上限, 最热门的域名不会被裁剪
+        for shard in popularity.shards.iter() {
+            assert!(shard.lock().unwrap().len() <= popularity.shard_limit());
+        }
+        assert_eq!(popularity.top(), vec![("a.com".to_string(), 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_save_load() {
+        let path = std::env::temp_dir().join(format!("zzdns-popular-{}.txt", std::process::id()));
+        let file = Some(path.to_string_lossy().to_string());
+        let popularity = ZPopularity::new(file.clone(), None);
+        for domain in ["a.com", "b.com", "b.com", "b.com", "b.com"] {
+            popularity.hit(domain);
+        }
+        popularity.save().await.unwrap();
+        // 保存后计数减半
+        assert_eq!(popularity.top(), vec![("b.com".to_string(), 2)]);
+
+        let loaded = ZPopularity::new(file, None);
+        assert_eq!(loaded.load().await.unwrap(), vec!["b.com".to_string(), "a.com".to_string()]);
+        assert_eq!(loaded.top(), vec![("b.com".to_string(), 4), ("a.com".to_string(), 1)]);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,31 +1,341 @@
-use std::{sync::Arc, env, path::PathBuf};
+use std::{collections::{hash_map::DefaultHasher, HashMap, HashSet}, hash::{Hash, Hasher}, path::{Path, PathBuf}, str::FromStr, sync::{Arc, Mutex, RwLock}, time::{Duration, SystemTime}};
 
+use anyhow::Result;
+use bytes::Bytes;
+use domain::base::{Dname, Rtype};
+use reqwest::{header, Client, StatusCode};
 use tokio::fs;
+use zshutdown::SHUTDOWN;
 use zcacher::ZCacher;
 
-pub struct ZPreloader;
+// 检查预加载文件是否变化的间隔
+const RELOAD_INTERVAL: u64 = 60;
+// 下载远程预加载列表的超时时间
+const FETCH_TIMEOUT: u64 = 30;
+
+/// 预加载文件的解析结果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Preload {
+    pub entries: Vec<(String, Rtype)>,
+    pub invalid: Vec<(usize, String)>, // 行号及内容
+}
+
+/// 解析预加载文件. 每行为域名及可选的记录类型(默认A), 如 `example.com AAAA MX`,
+/// 支持 `#` 注释, 忽略空行及重复的记录.
+pub fn parse(content: &str) -> Preload {
+    let mut preload = Preload::default();
+    let mut seen = HashSet::new();
+    for (i, line) in content.lines().enumerate() {
+        let text = line.split('#').next().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+        let mut fields = text.split_whitespace();
+        let domain = fields.next().unwrap_or("").trim_end_matches('.').to_ascii_lowercase();
+        if domain.is_empty() || domain.contains('*') || Dname::<Bytes>::from_str(&domain).is_err() {
+            preload.invalid.push((i + 1, line.to_string()));
+            continue;
+        }
+        let mut rtypes = Vec::new();
+        for field in fields {
+            match Rtype::from_str(&field.to_ascii_uppercase()) {
+                Ok(rtype) => rtypes.push(rtype),
+                Err(_) => {
+                    rtypes.clear();
+                    break;
+                },
+            }
+        }
+        if rtypes.is_empty() {
+            if text.split_whitespace().count() > 1 {
+                preload.invalid.push((i + 1, line.to_string()));
+                continue;
+            }
+            rtypes.push(Rtype::A);
+        }
+        for rtype in rtypes {
+            if seen.insert((domain.clone(), rtype)) {
+                preload.entries.push((domain.clone(), rtype));
+            }
+        }
+    }
+    preload
+}
+
+// 文件名中支持 `*` 与 `?` 通配符, 目录部分按原样匹配.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..])),
+        (Some(b'?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+async fn expand(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) if n.contains(['*', '?']) => n,
+        _ => return vec![path.to_path_buf()],
+    };
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut files = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(&dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let file_name = entry.file_name();
+            if matches(name.as_bytes(), file_name.to_string_lossy().as_bytes()) {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_remote(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn digest(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+// 远程列表上次下载的校验信息
+#[derive(Debug, Clone)]
+struct Remote {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    digest: u64,
+}
+
+/// 从预加载文件或 http(s) 地址读取域名加入缓存队列, 内容变化时重新加载.
+pub struct ZPreloader {
+    files: RwLock<Vec<String>>,
+    cacher: Arc<ZCacher>,
+    mtimes: Mutex<HashMap<PathBuf, SystemTime>>,
+    remotes: Mutex<HashMap<String, Remote>>,
+    client: Client,
+}
 
 impl ZPreloader {
 
-    pub async fn load(domain_filename: String, cacher: Arc<ZCacher>) {
-        let dir = match env::current_dir(){
-            Ok(path) => path,
-            Err(_err) => PathBuf::from("."),
-        };
-        let filepath = dir.join(domain_filename).into_os_string();
-        match fs::read_to_string(filepath).await {
-            Ok(domain_lines) => {
-                let mut count = 0;
-                for line in domain_lines.lines() {
-                    if cacher.push(line.to_string()).await.is_ok() {
-                        count += 1;
-                    }
+    pub fn new(files: Vec<String>, cacher: Arc<ZCacher>) -> Self {
+        let client = Client::builder().timeout(Duration::from_secs(FETCH_TIMEOUT)).build().unwrap_or_default();
+        Self { files: RwLock::new(files), cacher, mtimes: Mutex::new(HashMap::new()),
+            remotes: Mutex::new(HashMap::new()), client }
+    }
+
+    /// 替换预加载文件列表, 新文件在下次加载时读取.
+    pub fn set_files(&self, files: Vec<String>) {
+        let mut current = self.files.write().unwrap();
+        if *current != files {
+            *current = files;
+            self.mtimes.lock().unwrap().clear();
+            self.remotes.lock().unwrap().clear();
+        }
+    }
+
+    // 下载远程列表, 未修改(304或内容相同)时返回None
+    async fn fetch(&self, url: &str) -> Result<Option<String>> {
+        let prev = self.remotes.lock().unwrap().get(url).cloned();
+        let mut req = self.client.get(url);
+        if let Some(prev) = prev.as_ref() {
+            if let Some(etag) = prev.etag.as_ref() {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = prev.last_modified.as_ref() {
+                req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let res = req.send().await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let res = res.error_for_status()?;
+        let value = |name| res.headers().get(name).and_then(|v: &header::HeaderValue| v.to_str().ok()).map(String::from);
+        let (etag, last_modified) = (value(header::ETAG), value(header::LAST_MODIFIED));
+        let content = res.text().await?;
+        let digest = digest(&content);
+        let unchanged = prev.is_some_and(|p| p.digest == digest);
+        self.remotes.lock().unwrap().insert(url.to_string(), Remote { etag, last_modified, digest });
+        Ok(if unchanged { None } else { Some(content) })
+    }
+
+    /// 加载新增或修改过的文件及远程列表, 返回加入缓存队列的记录数.
+    pub async fn load(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut count = 0;
+        let files = self.files.read().unwrap().clone();
+        for pattern in files.iter() {
+            if is_remote(pattern) {
+                match self.fetch(pattern).await {
+                    Ok(Some(content)) => count += self.push(pattern, &content, &mut seen).await,
+                    Ok(None) => {},
+                    Err(e) => error!("Failed to fetch domain list {:?}, error: {:?}", pattern, e),
                 }
-                info!("Pushed domain name list to cache queue, count:{:?}", count);
-            },
-            Err(e) => {
-                error!("Failed to read domain file, error: {:?}", e);
-            },
+                continue;
+            }
+            let paths = expand(pattern).await;
+            if paths.is_empty() {
+                warn!("No preload file matches {:?}", pattern);
+            }
+            for path in paths {
+                let modified = match fs::metadata(&path).await.and_then(|m| m.modified()) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        error!("Failed to read domain file {:?}, error: {:?}", path, e);
+                        continue;
+                    },
+                };
+                if self.mtimes.lock().unwrap().get(&path) == Some(&modified) {
+                    continue;
+                }
+                let content = match fs::read_to_string(&path).await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        error!("Failed to read domain file {:?}, error: {:?}", path, e);
+                        continue;
+                    },
+                };
+                self.mtimes.lock().unwrap().insert(path.clone(), modified);
+                count += self.push(&path.to_string_lossy(), &content, &mut seen).await;
+            }
+        }
+        count
+    }
+
+    // 解析列表内容并加入缓存队列, 跳过本次加载中已加入的记录
+    async fn push(&self, source: &str, content: &str, seen: &mut HashSet<(String, Rtype)>) -> usize {
+        let preload = parse(content);
+        for (line, text) in preload.invalid.iter() {
+            warn!("Invalid preload line, file: {:?}, line: {}, content: {:?}", source, line, text);
+        }
+        let mut pushed = 0;
+        for (domain, rtype) in preload.entries {
+            if !seen.insert((domain.clone(), rtype)) {
+                continue;
+            }
+            if self.cacher.push_record(&domain, rtype).await.is_ok() {
+                pushed += 1;
+            }
+        }
+        info!("Pushed domain name list to cache queue, file: {:?}, count: {:?}, invalid: {:?}",
+            source, pushed, preload.invalid.len());
+        pushed
+    }
+
+    /// 重新加载全部文件及远程列表, 不论是否修改过.
+    pub async fn reload(&self) -> usize {
+        self.mtimes.lock().unwrap().clear();
+        self.remotes.lock().unwrap().clear();
+        self.load().await
+    }
+
+    pub async fn serve(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(RELOAD_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.load().await;
+                }
+                _ = SHUTDOWN.wait() => {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "# comment\n\n  example.com  \nExample.com. A\nipv6.example.com AAAA MX # inline\nbad..name\nfoo.com NOPE\n*.example.com\n";
+        let preload = parse(content);
+        assert_eq!(preload.entries, vec![
+            ("example.com".to_string(), Rtype::A),
+            ("ipv6.example.com".to_string(), Rtype::Aaaa),
+            ("ipv6.example.com".to_string(), Rtype::Mx),
+        ]);
+        assert_eq!(preload.invalid, vec![
+            (6, "bad..name".to_string()),
+            (7, "foo.com NOPE".to_string()),
+            (8, "*.example.com".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(b"*.txt", b"domain.txt"));
+        assert!(matches(b"domain-?.txt", b"domain-1.txt"));
+        assert!(!matches(b"*.txt", b"domain.conf"));
+        assert!(matches(b"*", b""));
+    }
+
+    // 返回固定内容的HTTP服务, 请求带有相同ETag时应答304
+    fn serve_http(body: Arc<Mutex<String>>) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let req = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                let body = body.lock().unwrap().clone();
+                let etag = format!("\"{}\"", digest(&body));
+                let res = match req.contains(&format!("if-none-match: {}", etag)) {
+                    true => "HTTP/1.1 304 Not Modified\r\nconnection: close\r\n\r\n".to_string(),
+                    false => format!("HTTP/1.1 200 OK\r\netag: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        etag, body.len(), body),
+                };
+                let _ = stream.write_all(res.as_bytes());
+            }
+        });
+        format!("http://{}/domains.txt", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let conf = serde_json::from_str(r#"{"max_size": 100, "max_ttl": 600, "min_ttl": 60}"#).unwrap();
+        let upstream = Arc::new(zupstream::ZUpstream::build(Vec::new()).await.unwrap());
+        let validator = Arc::new(zdnssec::ZValidator::build(None, upstream.clone()).unwrap());
+        let cacher = Arc::new(ZCacher::new(conf, upstream, validator).unwrap());
+
+        let body = Arc::new(Mutex::new("example.com\nexample.org AAAA\n".to_string()));
+        let url = serve_http(body.clone());
+        let preloader = ZPreloader::new(vec![url.clone()], cacher);
+        assert_eq!(preloader.load().await, 2);
+        // 未修改时不重复加入
+        assert_eq!(preloader.load().await, 0);
+        *body.lock().unwrap() = "example.net\n".to_string();
+        assert_eq!(preloader.load().await, 1);
+        assert_eq!(preloader.reload().await, 1);
+
+        preloader.set_files(vec!["http://127.0.0.1:1/domains.txt".to_string()]);
+        assert_eq!(preloader.load().await, 0);
+        assert!(is_remote(&url) && !is_remote("config/domain.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_expand() {
+        let dir = std::env::temp_dir().join(format!("zzdns-preload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.conf"] {
+            std::fs::write(dir.join(name), "example.com\n").unwrap();
         }
+        let files = expand(&dir.join("*.txt").to_string_lossy()).await;
+        assert_eq!(files, vec![dir.join("a.txt"), dir.join("b.txt")]);
+        let files = expand(&dir.join("c.conf").to_string_lossy()).await;
+        assert_eq!(files, vec![dir.join("c.conf")]);
+        let _ = std::fs::remove_dir_all(dir);
     }
-}
\ No newline at end of file
+}
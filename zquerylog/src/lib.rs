@@ -0,0 +1,8 @@
+#[macro_use] extern crate log;
+
+mod record;
+mod rotate;
+mod querylog;
+
+pub use record::{summarize, Format, Record};
+pub use querylog::ZQueryLog;
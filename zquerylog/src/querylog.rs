@@ -0,0 +1,147 @@
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
+use zconfig::QueryLog as QueryLogConf;
+use crate::record::{Format, Record, CSV_HEADER};
+use crate::rotate::Rotator;
+
+// 等待写入的记录数, 超过后丢弃新记录
+const QUEUE_SIZE: usize = 4096;
+const MAX_SIZE: u64 = 100 * 1024 * 1024;
+const MAX_FILES: usize = 7;
+
+struct Writer {
+    rotator: Rotator,
+    format: Format,
+    anonymize: bool,
+}
+
+/// 查询日志, 由后台任务写入文件, 不阻塞查询.
+pub struct ZQueryLog {
+    sender: Sender<Record>,
+    receiver: Receiver<Record>,
+    writer: Option<Mutex<Writer>>,
+}
+
+impl ZQueryLog {
+
+    pub fn build(conf: Option<QueryLogConf>) -> Result<Self> {
+        let (sender, receiver) = bounded(QUEUE_SIZE);
+        let writer = match conf {
+            Some(conf) => {
+                let format = match conf.format.as_deref().unwrap_or("json") {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    f => return Err(anyhow!("unknown query log format: {}", f)),
+                };
+                let header = (format == Format::Csv).then(|| CSV_HEADER.to_string());
+                let interval = conf.rotate_interval.filter(|i| *i > 0).map(Duration::from_secs);
+                let rotator = Rotator::new(PathBuf::from(conf.file), conf.max_size.unwrap_or(MAX_SIZE), interval,
+                    conf.max_files.unwrap_or(MAX_FILES), header);
+                Some(Mutex::new(Writer { rotator, format, anonymize: conf.anonymize.unwrap_or(false) }))
+            },
+            None => None,
+        };
+        Ok(Self { sender, receiver, writer })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// 加入写入队列, 队列已满时丢弃.
+    pub fn log(&self, record: Record) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(record) {
+            debug!("Query log queue is full, record dropped");
+        }
+    }
+
+    /// 停止接收记录, 后台任务写完队列中的记录后退出
+    pub fn close(&self) {
+        self.sender.close();
+    }
+
+    fn write(&self, record: &Record) {
+        if let Some(writer) = &self.writer {
+            let mut writer = writer.lock().unwrap();
+            let line = record.format(writer.format, writer.anonymize);
+            if let Err(e) = writer.rotator.write_line(&line) {
+                error!("Failed to write query log, error:{:?}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(writer) = &self.writer {
+            if let Err(e) = writer.lock().unwrap().rotator.flush() {
+                error!("Failed to flush query log, error:{:?}", e);
+            }
+        }
+    }
+
+    /// 写入队列中的记录, 队列关闭后写完剩余记录并退出
+    pub async fn serve(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        while let Ok(record) = self.receiver.recv().await {
+            self.write(&record);
+            // 取完队列中的记录后再刷新
+            while let Ok(record) = self.receiver.try_recv() {
+                self.write(&record);
+            }
+            self.flush();
+        }
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+    use super::*;
+
+    fn conf(file: &str, format: &str) -> QueryLogConf {
+        QueryLogConf {
+            file: file.to_string(),
+            format: Some(format.to_string()),
+            max_size: None,
+            rotate_interval: None,
+            max_files: None,
+            anonymize: Some(true),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_querylog() {
+        let path = std::env::temp_dir().join(format!("zzdns-querylog-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let querylog = std::sync::Arc::new(ZQueryLog::build(Some(conf(&path.to_string_lossy(), "csv"))).unwrap());
+        let writer = querylog.clone();
+        let handle = tokio::spawn(async move { writer.serve().await });
+        querylog.log(Record {
+            time: SystemTime::UNIX_EPOCH,
+            client: "10.0.0.8".parse().unwrap(),
+            qname: "example.com".to_string(),
+            qtype: "AAAA".to_string(),
+            rcode: "NOERROR".to_string(),
+            answers: Vec::new(),
+            cache: Some(true),
+            upstream: None,
+            latency: Duration::from_millis(1),
+            error: None,
+        });
+        querylog.close();
+        handle.await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n1970-01-01T00:00:00.000Z,10.0.0.0,example.com,AAAA,NOERROR,,hit,,1,\n", CSV_HEADER));
+        let _ = std::fs::remove_file(path);
+
+        assert!(ZQueryLog::build(Some(conf("query.log", "xml"))).is_err());
+        assert!(!ZQueryLog::build(None).unwrap().is_enabled());
+    }
+}
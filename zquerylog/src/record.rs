@@ -0,0 +1,190 @@
+use std::{net::IpAddr, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use bytes::Bytes;
+use domain::base::Message;
+use domain::rdata::AllRecordData;
+use serde_json::json;
+
+// CSV格式的列
+pub const CSV_HEADER: &str = "time,client,qname,qtype,rcode,answers,cache,upstream,latency_ms,error";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+/// 单次查询的日志记录
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub time: SystemTime,
+    pub client: IpAddr,
+    pub qname: String,
+    pub qtype: String,
+    pub rcode: String,
+    pub answers: Vec<String>, // 应答记录摘要, 如 "A 93.184.216.34"
+    pub cache: Option<bool>, // 是否命中缓存, 未查询缓存时为None
+    pub upstream: Option<String>, // 应答的上游
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+/// 应答记录的摘要
+pub fn summarize(msg: &Message<Bytes>) -> Vec<String> {
+    let answer = match msg.answer() {
+        Ok(a) => a,
+        Err(_) => return Vec::new(),
+    };
+    answer.limit_to::<AllRecordData<_, _>>().flatten()
+        .map(|rr| format!("{} {}", rr.rtype(), rr.data()))
+        .collect()
+}
+
+/// 隐藏地址的后段, IPv4只保留/24, IPv6只保留/48.
+pub fn anonymize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::from([a, b, c, 0])
+        },
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            IpAddr::from([s[0], s[1], s[2], 0, 0, 0, 0, 0])
+        },
+    }
+}
+
+/// RFC 3339格式的UTC时间, 精确到毫秒
+pub fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // 由天数计算公历日期
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60, since.subsec_millis())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Record {
+
+    fn client(&self, anonymized: bool) -> IpAddr {
+        if anonymized { anonymize(self.client) } else { self.client }
+    }
+
+    fn latency_ms(&self) -> f64 {
+        (self.latency.as_secs_f64() * 1_000_000.0).round() / 1000.0
+    }
+
+    /// 格式化为一行日志, 不含换行符
+    pub fn format(&self, format: Format, anonymized: bool) -> String {
+        match format {
+            Format::Json => json!({
+                "time": timestamp(self.time),
+                "client": self.client(anonymized).to_string(),
+                "qname": self.qname,
+                "qtype": self.qtype,
+                "rcode": self.rcode,
+                "answers": self.answers,
+                "cache": self.cache.map(|hit| if hit { "hit" } else { "miss" }),
+                "upstream": self.upstream,
+                "latency_ms": self.latency_ms(),
+                "error": self.error,
+            }).to_string(),
+            Format::Csv => [
+                timestamp(self.time),
+                self.client(anonymized).to_string(),
+                self.qname.clone(),
+                self.qtype.clone(),
+                self.rcode.clone(),
+                self.answers.join("; "),
+                self.cache.map(|hit| if hit { "hit" } else { "miss" }).unwrap_or("").to_string(),
+                self.upstream.clone().unwrap_or_default(),
+                self.latency_ms().to_string(),
+                self.error.clone().unwrap_or_default(),
+            ].iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::BytesMut;
+    use domain::base::{Dname, MessageBuilder, Rtype, iana::{Class, Rcode}};
+    use domain::rdata::rfc1035::A;
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            client: "192.168.1.23".parse().unwrap(),
+            qname: "example.com".to_string(),
+            qtype: "A".to_string(),
+            rcode: "NOERROR".to_string(),
+            answers: vec!["A 93.184.216.34".to_string(), "A 93.184.216.35".to_string()],
+            cache: Some(false),
+            upstream: Some("1.1.1.1:53".to_string()),
+            latency: Duration::from_micros(12345),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)), "2023-11-14T22:13:20.123Z");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_format() {
+        let record = record();
+        let line: serde_json::Value = serde_json::from_str(&record.format(Format::Json, false)).unwrap();
+        assert_eq!(line, json!({
+            "time": "2023-11-14T22:13:20.123Z", "client": "192.168.1.23", "qname": "example.com",
+            "qtype": "A", "rcode": "NOERROR", "answers": ["A 93.184.216.34", "A 93.184.216.35"],
+            "cache": "miss", "upstream": "1.1.1.1:53", "latency_ms": 12.345, "error": null,
+        }));
+        assert_eq!(record.format(Format::Csv, true),
+            "2023-11-14T22:13:20.123Z,192.168.1.0,example.com,A,NOERROR,A 93.184.216.34; A 93.184.216.35,miss,1.1.1.1:53,12.345,");
+
+        let mut record = record;
+        record.error = Some("bad \"reply\", retry".to_string());
+        assert!(record.format(Format::Csv, false).ends_with(",\"bad \"\"reply\"\", retry\""));
+    }
+
+    #[test]
+    fn test_anonymize() {
+        assert_eq!(anonymize("10.1.2.3".parse().unwrap()), "10.1.2.0".parse::<IpAddr>().unwrap());
+        assert_eq!(anonymize("2001:db8:1:2::5".parse().unwrap()), "2001:db8:1::".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let name = Dname::<Bytes>::from_str("example.com").unwrap();
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push((&name, Rtype::A)).unwrap();
+        let qmsg = builder.into_message();
+        let mut answer = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&qmsg, Rcode::NoError).unwrap();
+        answer.push((&name, Class::In, 60, A::from_octets(93, 184, 216, 34))).unwrap();
+        let msg = Message::from_octets(answer.into_message().into_octets()).unwrap();
+        assert_eq!(summarize(&msg), vec!["A 93.184.216.34".to_string()]);
+    }
+}
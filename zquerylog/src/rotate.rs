@@ -0,0 +1,144 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, BufWriter, Write}, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+/// 按大小或时间轮转的日志文件, 历史文件依次命名为 `file.1`, `file.2` ...
+pub struct Rotator {
+    path: PathBuf,
+    max_size: u64, // 0为不按大小轮转
+    interval: Option<Duration>,
+    max_files: usize,
+    header: Option<String>, // 每个新文件的首行, 如CSV的列名
+    file: Option<BufWriter<File>>,
+    size: u64,
+    opened: Instant,
+}
+
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+impl Rotator {
+
+    pub fn new(path: PathBuf, max_size: u64, interval: Option<Duration>, max_files: usize, header: Option<String>) -> Self {
+        Self { path, max_size, interval, max_files, header, file: None, size: 0, opened: Instant::now() }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.opened = Instant::now();
+        let mut file = BufWriter::new(file);
+        if self.size == 0 {
+            if let Some(header) = &self.header {
+                writeln!(file, "{}", header)?;
+                self.size += header.len() as u64 + 1;
+            }
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn should_rotate(&self, len: u64) -> bool {
+        let body = self.size.saturating_sub(self.header.as_ref().map_or(0, |h| h.len() as u64 + 1));
+        let full = self.max_size > 0 && body > 0 && self.size + len > self.max_size;
+        let expired = self.interval.is_some_and(|i| self.opened.elapsed() >= i);
+        full || expired
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(rotated(&self.path, self.max_files));
+        for i in (1..self.max_files).rev() {
+            let from = rotated(&self.path, i);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, i + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+
+    /// 写入一行, 需要时先轮转文件
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        let len = line.len() as u64 + 1;
+        if self.should_rotate(len) {
+            self.rotate()?;
+            self.open()?;
+        }
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", line)?;
+            self.size += len;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zzdns-querylog-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rotate_size() {
+        let dir = temp_dir("size");
+        let path = dir.join("query.csv");
+        let mut rotator = Rotator::new(path.clone(), 20, None, 2, Some("h".to_string()));
+        for line in ["line-1", "line-2", "line-3", "line-4", "line-5", "line-6"] {
+            rotator.write_line(line).unwrap();
+        }
+        rotator.flush().unwrap();
+        // 每个文件为列名加两行, 只保留两个历史文件
+        assert_eq!(fs::read_to_string(&path).unwrap(), "h\nline-5\nline-6\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "h\nline-3\nline-4\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "h\nline-1\nline-2\n");
+        assert!(!rotated(&path, 3).exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_short_existing() {
+        let dir = temp_dir("short");
+        let path = dir.join("query.csv");
+        // 已有文件比列名短时不会下溢
+        fs::write(&path, "x\n").unwrap();
+        let mut rotator = Rotator::new(path.clone(), 10, None, 1, Some("qname,qtype".to_string()));
+        rotator.write_line("example.com,A").unwrap();
+        rotator.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x\nexample.com,A\n");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_rotate_interval() {
+        let dir = temp_dir("interval");
+        let path = dir.join("query.log");
+        fs::write(&path, "old\n").unwrap();
+        let mut rotator = Rotator::new(path.clone(), 0, Some(Duration::ZERO), 1, None);
+        rotator.write_line("new").unwrap();
+        rotator.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "old\n");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
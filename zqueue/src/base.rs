@@ -1,17 +1,27 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::SystemTime};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::Bytes;
 
+/// 队列中的消息: 客户端地址, DNS报文, 收到查询的时间
+pub type Packet = (SocketAddr, Bytes, SystemTime);
 
 #[async_trait]
 pub trait ZQueueHander: Send + Sync + 'static {
     
-    async fn send(&self, msg: (SocketAddr, Bytes))-> Result<()>;
+    async fn send(&self, msg: Packet)-> Result<()>;
 
-    async fn recv(&self) -> Result<(SocketAddr, Bytes)>;
+    async fn recv(&self) -> Result<Packet>;
 
     fn close(&self) -> bool;
+
+    /// 队列中等待处理的消息数
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool;
+
+    /// 队列容量
+    fn capacity(&self) -> usize;
     
 }
\ No newline at end of file
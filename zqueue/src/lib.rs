@@ -2,6 +2,6 @@ mod base;
 mod request;
 mod response;
 
-pub use base::ZQueueHander;
+pub use base::{Packet, ZQueueHander};
 pub use request::ZRequestQueue;
 pub use response::ZResponseQueue;
\ No newline at end of file
@@ -1,23 +1,21 @@
 use anyhow::{anyhow, Result};
-use std::net::SocketAddr;
 use async_trait::async_trait;
-use bytes::Bytes;
-use crate::base::ZQueueHander;
+use crate::base::{Packet, ZQueueHander};
 use async_channel::{bounded, Sender, Receiver};
 
 
 #[derive(Debug, Clone)]
 pub struct ZRequestQueue
 {
-    sender: Sender<(SocketAddr, Bytes)>,
-    receiver: Receiver<(SocketAddr, Bytes)>,
+    sender: Sender<Packet>,
+    receiver: Receiver<Packet>,
 }
 
 impl ZRequestQueue {
 
     pub fn new(cap: usize) -> Self {
 
-        let (s, r) = bounded::<(SocketAddr, Bytes)>(cap);
+        let (s, r) = bounded::<Packet>(cap);
         
         Self { 
             sender: s, 
@@ -30,14 +28,14 @@ impl ZRequestQueue {
 #[async_trait]
 impl ZQueueHander for ZRequestQueue {
     
-    async fn send(&self, msg: (SocketAddr, Bytes)) -> Result<()> {
+    async fn send(&self, msg: Packet) -> Result<()> {
         match self.sender.send(msg).await {
             Ok(_) => Ok(()),
             Err(err) => Err(anyhow!("Send error: {}", err)),
         }
     }
 
-    async fn recv(&self) -> Result<(SocketAddr, Bytes)> {
+    async fn recv(&self) -> Result<Packet> {
         match self.receiver.recv().await {
             Ok(msg) => Ok(msg),
             Err(err) => Err(anyhow!("Recv error: {}", err)),
@@ -47,6 +45,18 @@ impl ZQueueHander for ZRequestQueue {
     fn close(&self) -> bool {
         self.receiver.close()
     }
+
+    fn len(&self) -> usize {
+        self.receiver.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.receiver.is_empty()
+    }
+
+    fn capacity(&self) -> usize {
+        self.receiver.capacity().unwrap_or(usize::MAX)
+    }
 }
 
 
@@ -54,10 +64,12 @@ impl ZQueueHander for ZRequestQueue {
     async fn test_zrequest_queue() {
         let qsize = 1024;
         let q = ZRequestQueue::new(qsize);
-        let src = "127.0.0.1:10500".parse::<SocketAddr>().unwrap();
+        let src = "127.0.0.1:10500".parse::<std::net::SocketAddr>().unwrap();
         let msg = bytes::Bytes::from_static(b"hello");
-        let _ = q.send((src, msg)).await;
-        let (recv_src, recv_msg) = q.recv().await.unwrap();
+        let received = std::time::SystemTime::now();
+        let _ = q.send((src, msg, received)).await;
+        let (recv_src, recv_msg, recv_time) = q.recv().await.unwrap();
         assert_eq!(src, recv_src);
+        assert_eq!(received, recv_time);
         assert_eq!(recv_msg, recv_msg);
 }
\ No newline at end of file
@@ -0,0 +1,5 @@
+#[macro_use] extern crate log;
+
+mod reload;
+
+pub use reload::ZReloader;
@@ -0,0 +1,224 @@
+use std::{path::PathBuf, sync::{Arc, Mutex}, time::{Duration, SystemTime}};
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::signal::unix::{signal as unix_signal, SignalKind};
+use zshutdown::SHUTDOWN;
+use zcacher::ZCacher;
+use zconfig::{Config, Override, Server};
+use zfilter::ZFilter;
+use zlocal::ZLocal;
+use zpreloader::ZPreloader;
+use zresolver::{Policies, ZResolver};
+use zrewrite::ZRewrite;
+use zserver::ZAccess;
+use zupstream::ZUpstream;
+use zzone::ZAuthority;
+
+// 检查配置文件是否变化的间隔
+const WATCH_INTERVAL: u64 = 5;
+
+/// 收到SIGHUP或配置文件变化时重新加载配置, 保留缓存内容及监听的socket.
+pub struct ZReloader {
+    path: PathBuf,
+    overrides: Vec<Override>, // 命令行及环境变量的覆盖项, 重新加载后仍生效
+    current: Mutex<Config>,
+    mtime: Mutex<Option<SystemTime>>,
+    upstream: Arc<ZUpstream>,
+    resolver: Arc<ZResolver>,
+    cacher: Arc<ZCacher>,
+    preloader: Arc<ZPreloader>,
+    access: Option<Arc<ZAccess>>, // 服务器的访问控制及限速
+}
+
+fn json<T: serde::Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+// 修改后需重启才生效的配置项, server.acl及server.ratelimit可重新加载
+fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let server = |s: &Server| json(&Server { acl: None, ratelimit: None, ..s.clone() });
+    if server(&old.server) != server(&new.server) {
+        changed.push("server");
+    }
+    if json(&old.admin) != json(&new.admin) {
+        changed.push("admin");
+    }
+    if json(&old.dnssec) != json(&new.dnssec) {
+        changed.push("dnssec");
+    }
+    if json(&old.querylog) != json(&new.querylog) {
+        changed.push("querylog");
+    }
+    if json(&old.dnstap) != json(&new.dnstap) {
+        changed.push("dnstap");
+    }
+    changed
+}
+
+impl ZReloader {
+
+    pub fn new(path: PathBuf, conf: Config, upstream: Arc<ZUpstream>, resolver: Arc<ZResolver>,
+        cacher: Arc<ZCacher>, preloader: Arc<ZPreloader>) -> Self {
+        let mtime = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Self { path, overrides: Vec::new(), current: Mutex::new(conf), mtime: Mutex::new(mtime), upstream, resolver, cacher, preloader, access: None }
+    }
+
+    pub fn with_overrides(mut self, overrides: Vec<Override>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// 重新加载时替换服务器的访问控制及限速.
+    pub fn with_access(mut self, access: Arc<ZAccess>) -> Self {
+        self.access = Some(access);
+        self
+    }
+
+    /// 读取配置文件并替换上游、策略、访问控制、限速及缓存限制, 任一部分失败时保留原配置.
+    pub async fn reload(&self) -> Result<()> {
+        let conf = self.load()?;
+        self.apply(conf).await.map(|_| ())
+    }
+
+    /// 读取并校验配置文件, 不影响当前配置.
+    pub fn load(&self) -> Result<Config> {
+        zconfig::load_with(&self.path, &self.overrides)
+    }
+
+    /// 应用已校验的配置, 返回修改后需重启才生效的配置项.
+    pub async fn apply(&self, conf: Config) -> Result<Vec<&'static str>> {
+        // 先建立新的策略, 上游替换成功后再一并生效
+        let policies = Policies {
+            zfilter: Arc::new(ZFilter::build(conf.filter.clone()).await?),
+            zlocal: Arc::new(ZLocal::build(conf.local.clone()).await?),
+            zauthority: Arc::new(ZAuthority::build(conf.zones.clone()).await?),
+            zrewrite: Arc::new(ZRewrite::new(conf.rewrites.clone())?),
+        };
+        let access = ZAccess::build(&conf.server)?;
+        let old = self.current.lock().unwrap().clone();
+        if json(&old.upstreams) != json(&conf.upstreams) {
+            self.upstream.reload(conf.upstreams.clone()).await?;
+        }
+        self.resolver.set_policies(policies);
+        if let Some(current) = &self.access {
+            current.reload(access);
+        }
+        self.cacher.reload(conf.cache.clone());
+        self.preloader.set_files(conf.cache.preload_files());
+
+        let changed = restart_required(&old, &conf);
+        if !changed.is_empty() {
+            warn!("Config sections {:?} changed, they take effect after restart", changed);
+        }
+        *self.current.lock().unwrap() = conf;
+        info!("Config reloaded from {:?}", self.path);
+        Ok(changed)
+    }
+
+    // 配置文件的修改时间变化时返回true
+    fn modified(&self) -> bool {
+        let mtime = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let mut last = self.mtime.lock().unwrap();
+        if mtime.is_none() || *last == mtime {
+            return false;
+        }
+        *last = mtime;
+        true
+    }
+
+    pub async fn serve(&self) -> Result<()> {
+        let mut hangup = unix_signal(SignalKind::hangup())?;
+        let mut interval = tokio::time::interval(Duration::from_secs(WATCH_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("Received SIGHUP, reloading config");
+                    self.modified();
+                }
+                _ = interval.tick() => {
+                    if !self.modified() {
+                        continue;
+                    }
+                    info!("Config file {:?} changed, reloading config", self.path);
+                }
+                _ = SHUTDOWN.wait() => {
+                    return Ok(());
+                }
+            }
+            if let Err(e) = self.reload().await {
+                error!("Failed to reload config, keeping the current one, error:{:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Rtype};
+    use zdnssec::ZValidator;
+    use super::*;
+
+    const BASE: &str = r#"{
+        "server": {"port": 53, "worker": 1, "qsize": 16},
+        "cache": {"max_size": 100, "max_ttl": 600, "min_ttl": 60},
+        "upstreams": [{"host": "127.0.0.1", "port": 5300}]
+    }"#;
+
+    fn query(name: &str) -> Bytes {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push((Dname::<Bytes>::from_str(name).unwrap(), Rtype::A)).unwrap();
+        builder.into_message().into_octets()
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let path = std::env::temp_dir().join(format!("zzdns-reload-{}.json", std::process::id()));
+        std::fs::write(&path, BASE).unwrap();
+        let conf = zconfig::load(&path).unwrap();
+        let upstream = Arc::new(ZUpstream::build(Vec::new()).await.unwrap());
+        let validator = Arc::new(ZValidator::build(None, upstream.clone()).unwrap());
+        let cacher = Arc::new(ZCacher::new(conf.cache.clone(), upstream.clone(), validator.clone()).unwrap());
+        let resolver = Arc::new(ZResolver::new(upstream.clone(), cacher.clone(),
+            Arc::new(ZFilter::build(None).await.unwrap()), Arc::new(ZLocal::build(None).await.unwrap()),
+            Arc::new(ZAuthority::build(None).await.unwrap()), Arc::new(ZRewrite::new(None).unwrap()), validator));
+        let preloader = Arc::new(ZPreloader::new(Vec::new(), cacher.clone()));
+        let reloader = ZReloader::new(path.clone(), conf, upstream, resolver.clone(), cacher, preloader);
+
+        let mut conf: Value = serde_json::from_str(BASE).unwrap();
+        conf["local"] = serde_json::json!({"records": [{"name": "nas.home.lan", "rtype": "A", "value": "10.0.0.2"}]});
+        std::fs::write(&path, conf.to_string()).unwrap();
+        reloader.reload().await.unwrap();
+        let res = Message::from_octets(resolver.resolve("127.0.0.1:5353".parse().unwrap(), query("nas.home.lan")).await.unwrap()).unwrap();
+        assert_eq!(res.header_counts().ancount(), 1);
+
+        // 无效的配置不影响当前配置
+        std::fs::write(&path, "{").unwrap();
+        assert!(reloader.reload().await.is_err());
+        conf["server"]["port"] = Value::from(0);
+        std::fs::write(&path, conf.to_string()).unwrap();
+        assert!(reloader.load().unwrap_err().to_string().contains("server.port: must be between 1 and 65535"));
+        conf["server"]["port"] = Value::from(5353);
+        assert_eq!(reloader.apply(serde_json::from_value(conf).unwrap()).await.unwrap(), vec!["server"]);
+        let res = Message::from_octets(resolver.resolve("127.0.0.1:5353".parse().unwrap(), query("nas.home.lan")).await.unwrap()).unwrap();
+        assert_eq!(res.header_counts().ancount(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_restart_required() {
+        let old: Config = serde_json::from_str(BASE).unwrap();
+        let mut new = old.clone();
+        new.cache.max_ttl = 300;
+        assert!(restart_required(&old, &new).is_empty());
+        new.server.acl = serde_json::from_str(r#"{"deny": ["10.0.0.0/8"]}"#).unwrap();
+        assert!(restart_required(&old, &new).is_empty());
+        new.server.ratelimit = serde_json::from_str(r#"{"qps": 100}"#).unwrap();
+        assert!(restart_required(&old, &new).is_empty());
+        new.server.port = 5353;
+        assert_eq!(restart_required(&old, &new), vec!["server"]);
+    }
+}
@@ -1,4 +1,5 @@
 #[macro_use] extern crate log;
 
 mod resolver;
-pub use resolver::ZResolver;
\ No newline at end of file
+mod singleflight;
+pub use resolver::{Policies, ZResolver};
\ No newline at end of file
@@ -1,95 +1,271 @@
-use std::sync::Arc;
+use std::{cell::RefCell, net::SocketAddr, sync::{Arc, RwLock}, time::{Instant, SystemTime}};
 
 use anyhow::Result;
 use bytes::{Bytes, BytesMut};
 use domain::base::{Message, MessageBuilder, iana::Rcode};
+use domain::rdata::AllRecordData;
 use zcacher::ZCacher;
+use zdnssec::{dnssec_query, Security, ZValidator};
+use zfilter::ZFilter;
+use zlocal::ZLocal;
+use zpreloader::ZPopularity;
+use zquerylog::{summarize, Record, ZQueryLog};
+use zrewrite::ZRewrite;
 use zupstream::ZUpstream;
+use zstats::STATS;
+use zzone::ZAuthority;
+use crate::singleflight::{self, SingleFlight};
+
+// 上游应答及应答的上游名称
+type Answer = (Bytes, Option<String>);
+
+// 单次查询的处理过程, 用于查询日志
+#[derive(Default)]
+struct Trace {
+    cache: Option<bool>,
+    upstream: Option<String>,
+    error: Option<String>,
+}
+
+tokio::task_local! {
+    static TRACE: RefCell<Trace>;
+}
+
+fn trace(f: impl FnOnce(&mut Trace)) {
+    let _ = TRACE.try_with(|t| f(&mut t.borrow_mut()));
+}
+
+/// 过滤、本地记录、权威区域及改写规则, 重新加载配置时整体替换.
+pub struct Policies {
+    pub zfilter: Arc<ZFilter>,
+    pub zlocal: Arc<ZLocal>,
+    pub zauthority: Arc<ZAuthority>,
+    pub zrewrite: Arc<ZRewrite>,
+}
 
 #[derive(Clone)]
 pub struct ZResolver {
     zupstream: Arc<ZUpstream>,
     cacher: Arc<ZCacher>,
+    policies: Arc<RwLock<Arc<Policies>>>,
+    zvalidator: Arc<ZValidator>,
+    popularity: Option<Arc<ZPopularity>>,
+    querylog: Option<Arc<ZQueryLog>>,
+    inflight: Arc<SingleFlight<Answer>>,
 }
 
 
 impl  ZResolver {
-    pub fn new(zupstream: Arc<ZUpstream>, cacher: Arc<ZCacher>) -> Self {
-        Self { zupstream, cacher }
+    pub fn new(zupstream: Arc<ZUpstream>, cacher: Arc<ZCacher>, zfilter: Arc<ZFilter>, zlocal: Arc<ZLocal>,
+        zauthority: Arc<ZAuthority>, zrewrite: Arc<ZRewrite>, zvalidator: Arc<ZValidator>) -> Self {
+        let policies = Arc::new(RwLock::new(Arc::new(Policies { zfilter, zlocal, zauthority, zrewrite })));
+        Self { zupstream, cacher, policies, zvalidator, popularity: None,
+            querylog: None, inflight: Arc::new(SingleFlight::default()) }
+    }
+
+    /// 替换策略, 进行中的查询仍使用原来的策略.
+    pub fn set_policies(&self, policies: Policies) {
+        *self.policies.write().unwrap() = Arc::new(policies);
+    }
+
+    /// 统计A记录查询次数, 用于热门域名预加载.
+    pub fn with_popularity(mut self, popularity: Arc<ZPopularity>) -> Self {
+        self.popularity = Some(popularity);
+        self
     }
 
-    pub async fn resolve(&self, qmsg: Bytes) -> Result<Bytes> {
+    pub fn with_querylog(mut self, querylog: Arc<ZQueryLog>) -> Self {
+        self.querylog = Some(querylog);
+        self
+    }
+
+    pub async fn resolve(&self, src: SocketAddr, qmsg: Bytes) -> Result<Bytes> {
 
+        let started = Instant::now();
+        STATS.query();
         let qmsg = Message::from_octets(qmsg)?;
+        let (res, trace) = TRACE.scope(RefCell::new(Trace::default()), async {
+            let res = self.respond(src, &qmsg).await;
+            (res, TRACE.with(|t| t.take()))
+        }).await;
+        let res = res?;
+        let latency = started.elapsed();
+        let rmsg = Message::from_octets(res.clone()).ok();
+        let qtype = qmsg.first_question().map(|q| q.qtype().to_string()).unwrap_or_else(|| "NONE".to_string());
+        let rcode = rmsg.as_ref().map(|m| m.header().rcode().to_string()).unwrap_or_default();
+        STATS.response(&qtype, &rcode, latency);
+
+        if let (Some(querylog), Some(question)) = (self.querylog.as_ref().filter(|q| q.is_enabled()), qmsg.first_question()) {
+            querylog.log(Record {
+                time: SystemTime::now(),
+                client: src.ip(),
+                qname: question.qname().to_string(),
+                qtype,
+                rcode,
+                answers: rmsg.as_ref().map(summarize).unwrap_or_default(),
+                cache: trace.cache,
+                upstream: trace.upstream,
+                latency,
+                error: trace.error,
+            });
+        }
+        Ok(res)
+    }
+
+    async fn respond(&self, src: SocketAddr, qmsg: &Message<Bytes>) -> Result<Bytes> {
+
+        let policies = self.policies.read().unwrap().clone();
 
         Ok(match qmsg.sole_question() {
             Ok(_) => {
-                match self.matching(qmsg.clone()).await {
-                    Ok(r) => {
-                        let question = qmsg.sole_question()?;
-                        info!("query success, qname: {:?}, qtype: {:?}", question.qname().to_string(), question.qtype());
-                        r
-                    },
+                if let Some(r) = policies.zfilter.filter(&src, qmsg)? {
+                    return Ok(r);
+                }
+                match self.matching(&policies, qmsg.clone()).await {
+                    Ok(r) => r,
                     Err(err) => {
                         let question = qmsg.sole_question()?;
-                        info!("query error, qname: {:?}, qtype: {:?}, error:{:?}", question.qname().to_string(), question.qtype(), err);
+                        debug!("query error, qname: {:?}, qtype: {:?}, error:{:?}", question.qname().to_string(), question.qtype(), err);
+                        trace(|t| t.error = Some(err.to_string()));
                         MessageBuilder::from_target(BytesMut::with_capacity(1024))?
-                                .start_answer(&qmsg, Rcode::ServFail)?
+                                .start_answer(qmsg, Rcode::ServFail)?
                                 .into_message().into_octets()
                     }
                 }
             },
             Err(_) => {
                 MessageBuilder::from_target(BytesMut::with_capacity(1024))?
-            .start_answer(&qmsg, Rcode::ServFail)?
+            .start_answer(qmsg, Rcode::ServFail)?
             .into_message().into_octets()
             },
         })
     }
 
-    async fn matching(&self, qmsg: Message<Bytes>) -> Result<Bytes>{
+    async fn matching(&self, policies: &Policies, qmsg: Message<Bytes>) -> Result<Bytes>{
+
+        let rule = match policies.zrewrite.find(&qmsg)? {
+            Some(r) => r,
+            None => return self.lookup(policies, qmsg).await,
+        };
+
+        if let Some(r) = rule.answer(&qmsg)? {
+            return Ok(r);
+        }
+
+        let res = match rule.rewrite_query(&qmsg)? {
+            Some(rqmsg) => {
+                let res = self.lookup(policies, rqmsg).await?;
+                rule.flatten(&qmsg, res)?
+            },
+            None => self.lookup(policies, qmsg).await?,
+        };
+        rule.strip(res)
+    }
+
+    async fn lookup(&self, policies: &Policies, qmsg: Message<Bytes>) -> Result<Bytes> {
+
+        if let Some(r) = policies.zlocal.answer(&qmsg)? {
+            return Ok(r);
+        }
+
+        if let Some(r) = policies.zauthority.answer(&qmsg)? {
+            return Ok(r);
+        }
 
         let question = qmsg.sole_question()?;
 
         let qtype = question.qtype();
+        // 开启验证时, 设置DO位的客户端需要完整的签名记录, 不走A记录缓存.
+        let dnssec_ok = self.zvalidator.is_enabled() && qmsg.opt().is_some_and(|o| o.dnssec_ok());
 
         let res = match qtype {
-            domain::base::Rtype::A => self.resolve_a(qmsg).await?,
+            domain::base::Rtype::A if !dnssec_ok => self.resolve_a(qmsg).await?,
+            _ if !dnssec_ok => match self.cached_record(&qmsg).await? {
+                Some(r) => r,
+                None => self.resolve_other(qmsg).await?,
+            },
             _ => self.resolve_other(qmsg).await?,
         };
         Ok(res)
     }
 
+    // 预加载的其它类型记录, 预加载的域名均为小写
+    async fn cached_record(&self, qmsg: &Message<Bytes>) -> Result<Option<Bytes>> {
+        let question = qmsg.sole_question()?;
+        let qname = question.qname().to_string().to_ascii_lowercase();
+        let cached = self.cacher.get_record(&qname, question.qtype()).await;
+        trace(|t| t.cache = Some(cached.is_some()));
+        let (bytes, ttl) = match cached {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let msg = Message::from_octets(bytes)?;
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, Rcode::NoError)?;
+        rmsg.header_mut().set_ra(true);
+        rmsg.header_mut().set_ad(msg.header().ad() && qmsg.header().ad());
+        for rr in msg.answer()?.limit_to::<AllRecordData<_, _>>() {
+            let mut record = rr?;
+            record.set_ttl(ttl);
+            rmsg.push(record)?;
+        }
+        Ok(Some(rmsg.into_message().into_octets()))
+    }
+
+    // 相同的查询同时只向上游发送一次
+    async fn query_upstream(&self, qmsg: &Message<Bytes>) -> Result<Bytes> {
+        let key = singleflight::key(qmsg)?;
+        let (res, upstream) = self.inflight.run(key, async {
+            match self.zvalidator.is_enabled() {
+                true => self.zupstream.query_named(dnssec_query(qmsg)?).await,
+                false => self.zupstream.query_named(qmsg.clone().into_octets()).await,
+            }
+        }).await?;
+        trace(|t| t.upstream = upstream);
+        Ok(singleflight::for_query(res, qmsg))
+    }
+
     async fn resolve_other(&self, qmsg: Message<Bytes>) -> Result<Bytes> {
-        let res = self.zupstream.query(qmsg.into_octets()).await?;
+        let res = self.query_upstream(&qmsg).await?;
+        if !self.zvalidator.is_enabled() {
+            return Ok(res);
+        }
 
-        Ok(res)
+        self.zvalidator.validate(&qmsg, res).await
     }
 
     
+    #[allow(clippy::unnecessary_unwrap)]
     async fn resolve_a(&self, qmsg: Message<Bytes>) -> Result<Bytes> {
         let question = qmsg.sole_question()?;
-        let qname = question.qname().to_string();
+        let qname = question.qname().to_string().to_ascii_lowercase();
+        if let Some(popularity) = &self.popularity {
+            popularity.hit(&qname);
+        }
         let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
                 .start_answer(&qmsg, Rcode::NoError)?;
         let header = rmsg.header_mut();
         header.set_ra(true);
 
-        if let Some((bytes, ttl)) = self.cacher.get(qname.to_string()).await {
+        let cached = self.cacher.get(qname.to_string()).await;
+        trace(|t| t.cache = Some(cached.is_some()));
+        if let Some((bytes, ttl)) = cached {
             let msg = Message::from_octets(bytes)?;
+            // 客户端设置AD位时返回缓存应答的验证结果
+            rmsg.header_mut().set_ad(msg.header().ad() && qmsg.header().ad());
             let (_, answer, _, _) = msg.sections()?;
             for rr in answer.flatten() {
                 if let Ok(record) = rr.to_record::<domain::rdata::rfc1035::A>() {
                     if record.is_some() {
                         let mut record = record.unwrap();
-                        record.set_ttl(ttl as u32);
+                        record.set_ttl(ttl);
                         rmsg.push(record)?;
                     }
                 }
                 if let Ok(record) = rr.to_record::<domain::rdata::rfc1035::Cname<_>>() {
                     if record.is_some() {
                         let mut record = record.unwrap();
-                        record.set_ttl(ttl as u32);
+                        record.set_ttl(ttl);
                         rmsg.push(record)?;
                     } 
                 }
@@ -97,10 +273,20 @@ impl  ZResolver {
             return Ok(rmsg.into_message().into_octets());
         }
 
-        let up_bytes = self.zupstream.query(qmsg.clone().into_octets()).await?;
+        let up_bytes = self.query_upstream(&qmsg).await?;
         let mut has_a = false;
 
         let up_msg = Message::from_octets(up_bytes)?;
+        match self.zvalidator.check(&qmsg, &up_msg).await {
+            Security::Secure => rmsg.header_mut().set_ad(qmsg.header().ad()),
+            Security::Insecure => {},
+            Security::Bogus(reason) => {
+                warn!("dnssec validation failed, qname: {:?}, reason: {}", qname, reason);
+                return Ok(MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                        .start_answer(&qmsg, Rcode::ServFail)?
+                        .into_message().into_octets());
+            },
+        }
         let (_, ans,_,_) = up_msg.sections()?;
         let ttl = 10;
 
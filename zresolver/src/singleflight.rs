@@ -0,0 +1,204 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{iana::Class, Message, Rtype};
+use tokio::sync::broadcast;
+
+/// 合并相同查询的键: (qname, qtype, class, DO位, CD位)
+pub type Key = (String, Rtype, Class, bool, bool);
+
+type Shared<T> = Result<T, String>;
+
+pub fn key(qmsg: &Message<Bytes>) -> Result<Key> {
+    let question = qmsg.sole_question()?;
+    let dnssec_ok = qmsg.opt().is_some_and(|o| o.dnssec_ok());
+    Ok((question.qname().to_string().to_ascii_lowercase(), question.qtype(), question.qclass(), dnssec_ok,
+        qmsg.header().cd()))
+}
+
+// 报文中第一个问题(域名及类型、类)的长度, 问题中的域名不会被压缩
+fn question_len(msg: &[u8]) -> Option<usize> {
+    let mut pos = 12;
+    loop {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 {
+            break;
+        }
+        if len > 63 {
+            return None;
+        }
+        pos += len + 1;
+    }
+    let end = pos + 1 + 4;
+    (end <= msg.len()).then_some(end - 12)
+}
+
+/// 按客户端的查询改写共享的应答: ID、RD及CD位, 以及问题中域名的大小写(0x20编码)
+pub fn for_query(res: Bytes, qmsg: &Message<Bytes>) -> Bytes {
+    let query = qmsg.as_slice();
+    if res.len() < 12 || query.len() < 12 {
+        return res;
+    }
+    let mut buf = BytesMut::from(&res[..]);
+    buf[..2].copy_from_slice(&query[..2]);
+    buf[2] = (buf[2] & !0x01) | (query[2] & 0x01);
+    buf[3] = (buf[3] & !0x10) | (query[3] & 0x10);
+    if res[4..6] == [0, 1] && query[4..6] == [0, 1] {
+        if let (Some(len), Some(qlen)) = (question_len(&res), question_len(query)) {
+            if len == qlen && res[12..12 + len].eq_ignore_ascii_case(&query[12..12 + len]) {
+                buf[12..12 + len].copy_from_slice(&query[12..12 + len]);
+            }
+        }
+    }
+    buf.freeze()
+}
+
+/// 并发的相同查询只向上游解析一次, 其余请求等待并共享结果.
+pub struct SingleFlight<T> {
+    calls: Mutex<HashMap<Key, broadcast::Sender<Shared<T>>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self { calls: Mutex::new(HashMap::new()) }
+    }
+}
+
+// 解析结束或被取消时移除, 等待者收到Closed后自行解析
+struct CallGuard<'a, T> {
+    flight: &'a SingleFlight<T>,
+    key: Option<Key>,
+}
+
+impl<T> Drop for CallGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.flight.calls.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+
+    pub async fn run<F>(&self, key: Key, f: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let receiver = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    let (sender, _) = broadcast::channel(1);
+                    calls.insert(key.clone(), sender);
+                    None
+                },
+            }
+        };
+        if let Some(mut receiver) = receiver {
+            return match receiver.recv().await {
+                Ok(res) => res.map_err(|e| anyhow!(e)),
+                Err(_) => f.await,
+            };
+        }
+
+        let mut guard = CallGuard { flight: self, key: Some(key) };
+        let res = f.await;
+        if let Some(key) = guard.key.take() {
+            if let Some(sender) = self.calls.lock().unwrap().remove(&key) {
+                let shared = match &res {
+                    Ok(r) => Ok(r.clone()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let _ = sender.send(shared);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    use std::time::Duration;
+    use domain::base::{iana::Rcode, Dname, MessageBuilder};
+    use domain::rdata::A;
+    use super::*;
+
+    fn test_key(name: &str) -> Key {
+        (name.to_string(), Rtype::A, Class::In, false, false)
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let flight = Arc::new(SingleFlight::default());
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut tasks = Vec::new();
+        for _ in 0..10 {
+            let flight = flight.clone();
+            let count = count.clone();
+            tasks.push(tokio::spawn(async move {
+                flight.run(test_key("example.com"), async {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Ok(Bytes::from_static(b"\x00\x01answer"))
+                }).await
+            }));
+        }
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), Bytes::from_static(b"\x00\x01answer"));
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(flight.calls.lock().unwrap().is_empty());
+
+        // 失败结果同样共享
+        let (a, b) = tokio::join!(
+            flight.run(test_key("fail.com"), async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(anyhow!("timeout"))
+            }),
+            flight.run(test_key("fail.com"), async { Ok(Bytes::new()) }),
+        );
+        assert!(a.is_err());
+        assert_eq!(b.unwrap_err().to_string(), "timeout");
+    }
+
+    fn query(id: u16, name: &str, rd: bool, cd: bool) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap();
+        builder.header_mut().set_id(id);
+        builder.header_mut().set_rd(rd);
+        builder.header_mut().set_cd(cd);
+        let mut builder = builder.question();
+        builder.push((Dname::<Bytes>::from_str(name).unwrap(), Rtype::A)).unwrap();
+        builder.into_message()
+    }
+
+    #[test]
+    fn test_for_query() {
+        let leader = query(0x1234, "ExAmPle.com", true, false);
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&leader, Rcode::NoError).unwrap();
+        builder.header_mut().set_ra(true);
+        let mut builder = builder.answer();
+        builder.push((Dname::<Bytes>::from_str("example.com").unwrap(), Class::In, 60, A::from_str("1.2.3.4").unwrap())).unwrap();
+        let res = builder.into_message().into_octets();
+
+        let follower = query(0xabcd, "eXaMPLE.COM", false, false);
+        let msg = Message::from_octets(for_query(res.clone(), &follower)).unwrap();
+        assert_eq!(msg.header().id(), 0xabcd);
+        assert!(!msg.header().rd() && msg.header().ra() && msg.header().qr());
+        assert_eq!(msg.sole_question().unwrap().qname().to_string(), "eXaMPLE.COM");
+        assert_eq!(msg.header_counts().ancount(), 1);
+        assert_eq!(key(&follower).unwrap(), key(&leader).unwrap());
+
+        let msg = Message::from_octets(for_query(res.clone(), &query(1, "example.com", true, true))).unwrap();
+        assert!(msg.header().rd() && msg.header().cd());
+        assert_ne!(key(&query(1, "example.com", true, true)).unwrap(), key(&leader).unwrap());
+        // 问题不同时只改写头部
+        let msg = Message::from_octets(for_query(res, &query(2, "example.org", true, false))).unwrap();
+        assert_eq!(msg.sole_question().unwrap().qname().to_string(), "ExAmPle.com");
+        assert_eq!(for_query(Bytes::from_static(b"\x12\x34"), &leader), Bytes::from_static(b"\x12\x34"));
+    }
+}
@@ -0,0 +1,5 @@
+#[macro_use] extern crate log;
+
+mod rewrite;
+
+pub use rewrite::{ZRewrite, RewriteRule};
@@ -0,0 +1,323 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{Dname, Message, MessageBuilder, Question, Record, Rtype, iana::Rcode};
+use domain::rdata::AllRecordData;
+use zconfig::Rewrite as RewriteConf;
+use zlocal::LocalData;
+
+// 强制应答记录的默认TTL
+const REWRITE_TTL: u32 = 60;
+
+/// 改写规则, 按配置顺序匹配第一条规则.
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    name: String,
+    wildcard: bool,
+    target: Option<String>,
+    answers: Vec<LocalData>,
+    strip: Vec<Rtype>,
+    ttl: u32,
+}
+
+impl RewriteRule {
+
+    pub fn new(conf: RewriteConf) -> Result<Self> {
+        let name = normalize(&conf.name);
+        let (name, wildcard) = match name.strip_prefix("*.") {
+            Some(n) => (n.to_string(), true),
+            None => (name, false),
+        };
+        let mut answers = Vec::new();
+        for answer in conf.answers.unwrap_or_default() {
+            let (rtype, value) = answer.trim().split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("Invalid rewrite answer: {:?}", answer))?;
+            answers.push(LocalData::parse(rtype, value)?);
+        }
+        let mut strip = Vec::new();
+        for rtype in conf.strip.unwrap_or_default() {
+            strip.push(Rtype::from_str(&rtype.to_ascii_uppercase())
+                .map_err(|_| anyhow!("Invalid record type to strip: {:?}", rtype))?);
+        }
+        let target = conf.target.map(|t| normalize(&t));
+        if target.is_some() && !answers.is_empty() {
+            return Err(anyhow!("Rewrite rule {:?} can not have both target and answers", conf.name));
+        }
+        Ok(Self { name, wildcard, target, answers, strip, ttl: conf.ttl.unwrap_or(REWRITE_TTL) })
+    }
+
+    fn matches(&self, qname: &str) -> bool {
+        if self.wildcard {
+            qname.len() > self.name.len() + 1
+                && qname.ends_with(&self.name)
+                && qname.as_bytes()[qname.len() - self.name.len() - 1] == b'.'
+        } else {
+            qname == self.name
+        }
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// 上游解析前的应答: 强制应答记录, 或查询类型被移除时返回NODATA.
+    pub fn answer(&self, qmsg: &Message<Bytes>) -> Result<Option<Bytes>> {
+        let question = qmsg.sole_question()?;
+        let qtype = question.qtype();
+        if self.answers.is_empty() && !self.strip.contains(&qtype) {
+            return Ok(None);
+        }
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, Rcode::NoError)?;
+        rmsg.header_mut().set_ra(true);
+        if !self.strip.contains(&qtype) {
+            let owner = Dname::bytes_from_str(&question.qname().to_string())?;
+            for data in self.answers.iter().filter(|d| qtype == Rtype::Any || d.rtype() == qtype) {
+                data.push(&mut rmsg, &owner, self.ttl)?;
+            }
+        }
+        Ok(Some(rmsg.into_message().into_octets()))
+    }
+
+    /// 将查询改写为目标域名, 保留原查询的ID与类型.
+    pub fn rewrite_query(&self, qmsg: &Message<Bytes>) -> Result<Option<Message<Bytes>>> {
+        let target = match self.target.as_deref() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let question = qmsg.sole_question()?;
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?;
+        builder.header_mut().set_id(qmsg.header().id());
+        builder.header_mut().set_rd(qmsg.header().rd());
+        builder.header_mut().set_cd(qmsg.header().cd());
+        let mut builder = builder.question();
+        builder.push(Question::new(Dname::bytes_from_str(target)?, question.qtype(), question.qclass()))?;
+        // 保留客户端的EDNS选项, 上游应答才会带回OPT及DNSSEC记录
+        let mut builder = builder.additional();
+        if let Some(opt) = qmsg.opt() {
+            builder.opt(|o| {
+                o.set_udp_payload_size(opt.udp_payload_size());
+                o.set_dnssec_ok(opt.dnssec_ok());
+                Ok(())
+            })?;
+        }
+        Ok(Some(Message::from_octets(builder.into_message().into_octets())?))
+    }
+
+    /// 将目标域名的应答展平为原域名的应答: 去掉CNAME链, 记录名称替换为原域名,
+    /// 授权及附加部分(SOA、OPT等)原样保留. 签名及NSEC/NSEC3无法对应改名后的记录, 从应答部分去掉.
+    pub fn flatten(&self, qmsg: &Message<Bytes>, rmsg: Bytes) -> Result<Bytes> {
+        let rmsg = Message::from_octets(rmsg)?;
+        let question = qmsg.sole_question()?;
+        let owner = Dname::bytes_from_str(&question.qname().to_string())?;
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, rmsg.header().rcode())?;
+        builder.header_mut().set_ra(true);
+        for rr in rmsg.answer()?.limit_to::<AllRecordData<_, _>>() {
+            let rr = rr?;
+            if matches!(rr.rtype(), Rtype::Cname | Rtype::Dname | Rtype::Rrsig | Rtype::Nsec | Rtype::Nsec3) {
+                continue;
+            }
+            builder.push(Record::new(owner.clone(), rr.class(), rr.ttl(), rr.data().clone()))?;
+        }
+        let mut builder = builder.authority();
+        for rr in rmsg.authority()?.limit_to::<AllRecordData<_, _>>() {
+            builder.push(rr?)?;
+        }
+        let mut builder = builder.additional();
+        for rr in rmsg.additional()?.limit_to::<AllRecordData<_, _>>() {
+            builder.push(rr?)?;
+        }
+        Ok(builder.into_message().into_octets())
+    }
+
+    /// 上游解析后移除应答中指定类型的记录.
+    pub fn strip(&self, rmsg: Bytes) -> Result<Bytes> {
+        if self.strip.is_empty() {
+            return Ok(rmsg);
+        }
+        let rmsg = Message::from_octets(rmsg)?;
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(1024))?;
+        *builder.header_mut() = rmsg.header();
+        let mut question = builder.question();
+        for q in rmsg.question().flatten() {
+            question.push(q)?;
+        }
+        let builder = rmsg.copy_records(question.answer(), |rr| {
+            if self.strip.contains(&rr.rtype()) {
+                return None;
+            }
+            rr.to_record::<AllRecordData<_, _>>().ok().flatten()
+        }).map_err(|e| anyhow!("Failed to strip records, error: {:?}", e))?;
+        Ok(builder.into_message().into_octets())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ZRewrite {
+    rules: Vec<RewriteRule>,
+}
+
+impl ZRewrite {
+
+    pub fn new(conf: Option<Vec<RewriteConf>>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rconf in conf.unwrap_or_default() {
+            rules.push(RewriteRule::new(rconf)?);
+        }
+        if !rules.is_empty() {
+            info!("zrewrite loaded, rules: {}", rules.len());
+        }
+        Ok(Self { rules })
+    }
+
+    /// 查找匹配查询域名的改写规则.
+    pub fn find(&self, qmsg: &Message<Bytes>) -> Result<Option<&RewriteRule>> {
+        if self.rules.is_empty() {
+            return Ok(None);
+        }
+        let qname = normalize(&qmsg.sole_question()?.qname().to_string());
+        Ok(self.rules.iter().find(|r| r.matches(&qname)))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Question, Rtype, Serial, iana::{Class, SecAlg}};
+    use domain::rdata;
+    use zconfig::Rewrite as RewriteConf;
+    use super::{RewriteRule, ZRewrite};
+
+    fn query(name: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(Dname::bytes_from_str(name).unwrap(), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn rule(name: &str, target: Option<&str>, answers: &[&str], strip: &[&str]) -> RewriteRule {
+        RewriteRule::new(RewriteConf {
+            name: name.to_string(),
+            target: target.map(|t| t.to_string()),
+            answers: Some(answers.iter().map(|a| a.to_string()).collect()),
+            strip: Some(strip.iter().map(|s| s.to_string()).collect()),
+            ttl: None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_find() {
+        let zrewrite = ZRewrite { rules: vec![rule("*.example.com", None, &[], &["AAAA"]), rule("example.com", None, &["A 10.0.0.1"], &[])] };
+        assert!(zrewrite.find(&query("www.example.com", Rtype::A)).unwrap().unwrap().wildcard);
+        assert!(!zrewrite.find(&query("example.com", Rtype::A)).unwrap().unwrap().wildcard);
+        assert!(zrewrite.find(&query("example.org", Rtype::A)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_answer_and_strip() {
+        let forced = rule("example.com", None, &["A 10.0.0.1", "TXT hello"], &[]);
+        let rmsg = Message::from_octets(forced.answer(&query("example.com", Rtype::A)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let no_aaaa = rule("example.com", None, &[], &["AAAA"]);
+        let rmsg = Message::from_octets(no_aaaa.answer(&query("example.com", Rtype::Aaaa)).unwrap().unwrap()).unwrap();
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+        assert!(no_aaaa.answer(&query("example.com", Rtype::A)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_flatten() {
+        let rewrite = rule("app.example.com", Some("cdn.example.net"), &[], &[]);
+        let qmsg = query("app.example.com", Rtype::A);
+        let tmsg = rewrite.rewrite_query(&qmsg).unwrap().unwrap();
+        assert_eq!(tmsg.header().id(), qmsg.header().id());
+        assert_eq!(tmsg.sole_question().unwrap().qname().to_string(), "cdn.example.net");
+
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&tmsg, domain::base::iana::Rcode::NoError).unwrap();
+        let cname = Dname::bytes_from_str("edge.example.net").unwrap();
+        rmsg.push((Dname::bytes_from_str("cdn.example.net").unwrap(), Class::In, 60, rdata::Cname::new(cname.clone()))).unwrap();
+        rmsg.push((cname, Class::In, 60, rdata::A::new(Ipv4Addr::new(1, 2, 3, 4)))).unwrap();
+
+        let flat = Message::from_octets(rewrite.flatten(&qmsg, rmsg.into_message().into_octets()).unwrap()).unwrap();
+        assert_eq!(flat.header_counts().ancount(), 1);
+        let rr = flat.answer().unwrap().next().unwrap().unwrap();
+        assert_eq!(rr.owner().to_string(), "app.example.com");
+        assert_eq!(rr.rtype(), Rtype::A);
+
+        let strip = rule("app.example.com", None, &[], &["A"]);
+        let stripped = Message::from_octets(strip.strip(flat.into_octets()).unwrap()).unwrap();
+        assert_eq!(stripped.header_counts().ancount(), 0);
+        assert_eq!(stripped.header_counts().qdcount(), 1);
+    }
+
+    #[test]
+    fn test_strip_sections() {
+        let rewrite = rule("app.example.com", Some("cdn.example.net"), &[], &["AAAA"]);
+        let mut qmsg = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        qmsg.push(Question::new_in(Dname::bytes_from_str("app.example.com").unwrap(), Rtype::Aaaa)).unwrap();
+        let mut qmsg = qmsg.additional();
+        qmsg.opt(|opt| {
+            opt.set_udp_payload_size(1232);
+            opt.set_dnssec_ok(true);
+            Ok(())
+        }).unwrap();
+        let qmsg = Message::from_octets(qmsg.into_message().into_octets()).unwrap();
+        let tmsg = rewrite.rewrite_query(&qmsg).unwrap().unwrap();
+        assert!(tmsg.opt().unwrap().dnssec_ok());
+
+        let owner = Dname::bytes_from_str("cdn.example.net").unwrap();
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&tmsg, domain::base::iana::Rcode::NoError).unwrap();
+        rmsg.push((owner.clone(), Class::In, 60, rdata::Aaaa::new("2001:db8::1".parse().unwrap()))).unwrap();
+        let mut rmsg = rmsg.authority();
+        let soa = rdata::Soa::new(owner.clone(), owner.clone(), Serial(1), 3600, 600, 86400, 300);
+        rmsg.push((owner.clone(), Class::In, 300, soa)).unwrap();
+        let mut rmsg = rmsg.additional();
+        rmsg.push((owner, Class::In, 60, rdata::A::new(Ipv4Addr::new(1, 2, 3, 4)))).unwrap();
+        rmsg.opt(|opt| {
+            opt.set_udp_payload_size(1232);
+            opt.set_dnssec_ok(true);
+            Ok(())
+        }).unwrap();
+
+        // 展平及移除记录后保留授权与附加部分, 只移除指定类型
+        let flat = rewrite.flatten(&qmsg, rmsg.into_message().into_octets()).unwrap();
+        let stripped = Message::from_octets(rewrite.strip(flat).unwrap()).unwrap();
+        assert_eq!(stripped.header_counts().ancount(), 0);
+        assert_eq!(stripped.header_counts().nscount(), 1);
+        assert_eq!(stripped.header_counts().arcount(), 2);
+        assert_eq!(stripped.authority().unwrap().next().unwrap().unwrap().rtype(), Rtype::Soa);
+        let opt = stripped.opt().unwrap();
+        assert_eq!(opt.udp_payload_size(), 1232);
+        assert!(opt.dnssec_ok());
+    }
+
+    #[test]
+    fn test_flatten_signed() {
+        let rewrite = rule("app.example.com", Some("cdn.example.net"), &[], &[]);
+        let qmsg = query("app.example.com", Rtype::A);
+        let tmsg = rewrite.rewrite_query(&qmsg).unwrap().unwrap();
+        let owner = Dname::bytes_from_str("cdn.example.net").unwrap();
+        let signer = Dname::bytes_from_str("example.net").unwrap();
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&tmsg, domain::base::iana::Rcode::NoError).unwrap();
+        rmsg.push((owner.clone(), Class::In, 60, rdata::A::new(Ipv4Addr::new(1, 2, 3, 4)))).unwrap();
+        let sig = rdata::Rrsig::new(Rtype::A, SecAlg::EcdsaP256Sha256, 3, 60, Serial(0), Serial(0), 0, signer, Bytes::new());
+        rmsg.push((owner, Class::In, 60, sig)).unwrap();
+
+        // 签名覆盖的是目标域名, 展平后不能挂在原域名下
+        let flat = Message::from_octets(rewrite.flatten(&qmsg, rmsg.into_message().into_octets()).unwrap()).unwrap();
+        assert_eq!(flat.header_counts().ancount(), 1);
+        let rr = flat.answer().unwrap().next().unwrap().unwrap();
+        assert_eq!(rr.owner().to_string(), "app.example.com");
+        assert_eq!(rr.rtype(), Rtype::A);
+    }
+}
@@ -0,0 +1,95 @@
+use std::{sync::{Arc, RwLock}, time::Instant};
+use anyhow::Result;
+use zshutdown::SHUTDOWN;
+use crate::acl::Acl;
+use crate::ratelimit::{RateLimiter, SWEEP_INTERVAL};
+use super::ServerConf;
+
+/// 访问控制及限速, 服务器与重新加载配置共用, 重新加载时替换
+#[derive(Debug, Default)]
+pub struct ZAccess {
+    acl: RwLock<Option<Arc<Acl>>>, // 未配置访问控制时为None
+    limiter: RwLock<Option<Arc<RateLimiter>>>, // 未配置限速时为None
+}
+
+impl ZAccess {
+
+    pub fn build(conf: &ServerConf) -> Result<Self> {
+        let acl = conf.acl.as_ref().map(Acl::new).transpose()?.map(Arc::new);
+        let limiter = conf.ratelimit.as_ref().map(|r| Arc::new(RateLimiter::new(r)));
+        Ok(Self { acl: RwLock::new(acl), limiter: RwLock::new(limiter) })
+    }
+
+    /// 替换为按新配置建立的访问控制及限速, 进行中的查询仍使用原来的.
+    /// 限速配置未变化时保留原有的令牌桶.
+    pub fn reload(&self, access: ZAccess) {
+        *self.acl.write().unwrap() = access.acl.into_inner().unwrap();
+        let new = access.limiter.into_inner().unwrap();
+        let mut limiter = self.limiter.write().unwrap();
+        if limiter.as_ref().map(|l| l.conf()) != new.as_ref().map(|l| l.conf()) {
+            *limiter = new;
+        }
+    }
+
+    pub(crate) fn acl(&self) -> Option<Arc<Acl>> {
+        self.acl.read().unwrap().clone()
+    }
+
+    pub(crate) fn limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.limiter.read().unwrap().clone()
+    }
+
+    /// 后台定期清理当前限速中补满的令牌桶, 直到服务关闭.
+    pub(crate) async fn sweeper(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Some(limiter) = self.limiter() {
+                        limiter.sweep(Instant::now());
+                    }
+                },
+                _ = SHUTDOWN.wait() => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server(acl: serde_json::Value) -> ServerConf {
+        serde_json::from_value(serde_json::json!({"port": 53, "worker": 1, "qsize": 16, "acl": acl})).unwrap()
+    }
+
+    fn limited(qps: u32) -> ServerConf {
+        serde_json::from_value(serde_json::json!({"port": 53, "worker": 1, "qsize": 16, "ratelimit": {"qps": qps}})).unwrap()
+    }
+
+    #[test]
+    fn test_reload() {
+        let access = ZAccess::build(&server(serde_json::json!({"deny": ["10.0.0.0/8"]}))).unwrap();
+        assert!(!access.acl().unwrap().allow("10.0.0.1".parse().unwrap()));
+        access.reload(ZAccess::build(&server(serde_json::json!({"allow": ["10.0.0.0/8"]}))).unwrap());
+        assert!(access.acl().unwrap().allow("10.0.0.1".parse().unwrap()));
+        access.reload(ZAccess::build(&server(serde_json::Value::Null)).unwrap());
+        assert!(access.acl().is_none());
+        assert!(ZAccess::build(&server(serde_json::json!({"deny": ["10.0.0.0/33"]}))).is_err());
+    }
+
+    #[test]
+    fn test_reload_ratelimit() {
+        let ip = "10.0.0.1".parse().unwrap();
+        let access = ZAccess::build(&limited(1)).unwrap();
+        assert!(access.limiter().unwrap().allow_query(ip));
+        assert!(!access.limiter().unwrap().allow_query(ip));
+        // 配置未变化时保留令牌桶
+        access.reload(ZAccess::build(&limited(1)).unwrap());
+        assert!(!access.limiter().unwrap().allow_query(ip));
+        access.reload(ZAccess::build(&limited(2)).unwrap());
+        assert!(access.limiter().unwrap().allow_query(ip));
+        access.reload(ZAccess::build(&server(serde_json::Value::Null)).unwrap());
+        assert!(access.limiter().is_none());
+    }
+}
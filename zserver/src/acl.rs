@@ -0,0 +1,108 @@
+use std::net::IpAddr;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use domain::base::iana::Rcode;
+use ipnet::IpNet;
+use zconfig::Acl as AclConf;
+use crate::base::empty_answer;
+
+fn parse_net(s: &str) -> Result<IpNet> {
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| anyhow!("Invalid ACL network: {:?}", s))
+}
+
+fn parse_nets(nets: &Option<Vec<String>>) -> Result<Option<Vec<IpNet>>> {
+    nets.as_ref().map(|nets| nets.iter().map(|n| parse_net(n)).collect()).transpose()
+}
+
+// IPv4映射的IPv6地址按IPv4匹配
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+/// 访问控制列表, 先匹配deny, 配置allow时只允许其中的客户端.
+#[derive(Debug)]
+pub struct Acl {
+    allow: Option<Vec<IpNet>>,
+    deny: Vec<IpNet>,
+    drop: bool, // 拒绝时丢弃请求, 否则应答REFUSED
+}
+
+impl Acl {
+
+    pub fn new(conf: &AclConf) -> Result<Self> {
+        Ok(Self {
+            allow: parse_nets(&conf.allow)?,
+            deny: parse_nets(&conf.deny)?.unwrap_or_default(),
+            drop: conf.action.as_deref().is_some_and(|a| a.eq_ignore_ascii_case("drop")),
+        })
+    }
+
+    /// 允许该客户端查询时返回true
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.as_ref().is_none_or(|allow| allow.iter().any(|net| net.contains(&ip)))
+    }
+
+    /// 拒绝时发给客户端的应答, 丢弃或无法解析时返回None
+    pub fn denied(&self, msg: &Bytes) -> Option<Bytes> {
+        if self.drop {
+            return None;
+        }
+        empty_answer(msg, Rcode::Refused, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::BytesMut;
+    use domain::base::{Dname, Message, MessageBuilder, Rtype};
+    use super::*;
+
+    fn build(json: serde_json::Value) -> Acl {
+        Acl::new(&serde_json::from_value(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_allow() {
+        let acl = build(serde_json::json!({
+            "allow": ["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "127.0.0.1", "::1"],
+            "deny": ["192.168.100.0/24"]
+        }));
+        for ip in ["10.1.2.3", "192.168.1.1", "127.0.0.1", "::1", "::ffff:10.0.0.1"] {
+            assert!(acl.allow(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "192.168.100.7", "127.0.0.2", "2001:db8::1"] {
+            assert!(!acl.allow(ip.parse().unwrap()), "{}", ip);
+        }
+
+        // 只配置deny时允许其他客户端
+        let acl = build(serde_json::json!({"deny": ["0.0.0.0/0"]}));
+        assert!(!acl.allow("1.1.1.1".parse().unwrap()));
+        assert!(acl.allow("2001:db8::1".parse().unwrap()));
+
+        assert!(Acl::new(&serde_json::from_value(serde_json::json!({"allow": ["lan"]})).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_denied() {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push((Dname::<Bytes>::from_str("example.com").unwrap(), Rtype::A)).unwrap();
+        let msg = builder.into_message().into_octets();
+
+        let res = build(serde_json::json!({"allow": ["127.0.0.1"]})).denied(&msg).unwrap();
+        let res = Message::from_octets(res).unwrap();
+        assert_eq!(res.header().rcode(), Rcode::Refused);
+        assert_eq!(res.header_counts().ancount(), 0);
+        assert_eq!(build(serde_json::json!({"allow": ["127.0.0.1"], "action": "drop"})).denied(&msg), None);
+    }
+}
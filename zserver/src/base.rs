@@ -2,7 +2,10 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use domain::base::{iana::Rcode, Message, MessageBuilder};
 use zqueue::{ZRequestQueue, ZResponseQueue};
+use crate::access::ZAccess;
 use super::ServerConf;
 
 pub enum ZServerType {
@@ -16,6 +19,17 @@ pub enum ZServerType {
 #[async_trait]
 pub trait ZServer: Send + Sync + 'static {
     async fn build(req_q: Arc<ZRequestQueue>, res_q: Arc<ZResponseQueue>, conf: ServerConf) -> Result<Self> where Self:Sized;
+    /// 访问控制, 用于重新加载配置
+    fn access(&self) -> Arc<ZAccess>;
     async fn start(&self) -> Result<()>;
     async fn stop(&self) -> Result<()>;
-}
\ No newline at end of file
+}
+
+/// 根据查询构造不含记录的应答, 无法解析时返回None
+pub(crate) fn empty_answer(msg: &Bytes, rcode: Rcode, tc: bool) -> Option<Bytes> {
+    let msg = Message::from_octets(msg.clone()).ok()?;
+    let mut answer = MessageBuilder::from_target(BytesMut::with_capacity(512)).ok()?
+        .start_answer(&msg, rcode).ok()?;
+    answer.header_mut().set_tc(tc);
+    Some(answer.into_message().into_octets())
+}
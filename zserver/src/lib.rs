@@ -1,4 +1,7 @@
+mod access;
+mod acl;
 mod base;
+mod ratelimit;
 mod udp;
 
 #[macro_use] extern crate log;
@@ -8,6 +11,7 @@ use std::sync::Arc;
 use udp::UdpZserver;
 
 use zqueue::{ZRequestQueue, ZResponseQueue};
+pub use access::ZAccess;
 pub use base::{ZServer, ZServerType};
 use zconfig::Server as ServerConf;
 pub struct ZServerBuilder {}
@@ -0,0 +1,308 @@
+use std::{collections::{hash_map::RandomState, HashMap}, hash::{BuildHasher, Hash}, net::IpAddr, sync::Mutex, time::{Duration, Instant}};
+
+use bytes::Bytes;
+use domain::base::{iana::Rcode, Message, Rtype};
+use ipnet::IpNet;
+use zconfig::RateLimit;
+use crate::base::empty_answer;
+
+// 后台清理已补满令牌的桶的间隔
+pub(crate) const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+// 分片数, 减少锁竞争并缩短清理时持锁的时间
+const SHARDS: usize = 16;
+// 每类限制最多跟踪的条目数
+const MAX_ENTRIES: usize = 65536;
+
+/// 超出限制时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Drop,
+    Refused,
+    Truncate, // 应答TC=1, 客户端可改用TCP重试
+}
+
+impl Action {
+    fn parse(s: Option<&str>) -> Self {
+        match s.map(|s| s.to_ascii_lowercase()).as_deref() {
+            Some("refused") => Action::Refused,
+            Some("truncate") => Action::Truncate,
+            _ => Action::Drop,
+        }
+    }
+}
+
+// 令牌桶
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+
+    fn refill(&self, rate: f64, burst: f64, now: Instant) -> f64 {
+        (self.tokens + now.saturating_duration_since(self.last).as_secs_f64() * rate).min(burst)
+    }
+
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        self.tokens = self.refill(rate, burst, now);
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+// 按键分片的令牌桶, 每个分片最多保存 `capacity` 个条目
+#[derive(Debug)]
+struct Buckets<K> {
+    rate: f64,
+    burst: f64,
+    capacity: usize,
+    hasher: RandomState,
+    shards: Vec<Mutex<HashMap<K, Bucket>>>,
+}
+
+impl<K: Hash + Eq + Clone> Buckets<K> {
+
+    fn new(rate: u32, burst: u32) -> Self {
+        Self { rate: rate as f64, burst: burst as f64, capacity: MAX_ENTRIES / SHARDS, hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect() }
+    }
+
+    fn take(&self, key: K, now: Instant) -> bool {
+        let (rate, burst) = (self.rate, self.burst);
+        let shard = &self.shards[self.hasher.hash_one(&key) as usize % SHARDS];
+        let mut map = shard.lock().unwrap();
+        // 分片已满时淘汰任意一个条目, 不在查询路径上遍历
+        if map.len() >= self.capacity && !map.contains_key(&key) {
+            if let Some(evicted) = map.keys().next().cloned() {
+                map.remove(&evicted);
+            }
+        }
+        map.entry(key).or_insert(Bucket { tokens: burst, last: now }).take(rate, burst, now)
+    }
+
+    // 补满的桶与新建的桶相同, 逐个分片移除
+    fn sweep(&self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().retain(|_, b| b.refill(rate, burst, now) < burst);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+}
+
+// RRL按客户端网段、域名(NXDOMAIN为SOA所有者)、类型及应答码区分应答
+type ResponseKey = (IpNet, String, u16, u8);
+
+/// 按客户端网段限制查询速率(令牌桶)及相同应答的速率(RRL)
+#[derive(Debug)]
+pub struct RateLimiter {
+    conf: RateLimit, // 重新加载时比较配置是否变化
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    action: Action,
+    queries: Option<Buckets<IpNet>>,
+    responses: Option<Buckets<ResponseKey>>,
+}
+
+impl RateLimiter {
+
+    pub fn new(conf: &RateLimit) -> Self {
+        Self {
+            conf: conf.clone(),
+            ipv4_prefix: conf.ipv4_prefix.unwrap_or(24).min(32),
+            ipv6_prefix: conf.ipv6_prefix.unwrap_or(56).min(128),
+            action: Action::parse(conf.action.as_deref()),
+            queries: conf.qps.map(|qps| Buckets::new(qps, conf.burst.unwrap_or(qps))),
+            responses: conf.rrl.map(|rrl| Buckets::new(rrl, rrl)),
+        }
+    }
+
+    pub fn conf(&self) -> &RateLimit {
+        &self.conf
+    }
+
+    /// 清理补满的令牌桶
+    pub(crate) fn sweep(&self, now: Instant) {
+        self.queries.iter().for_each(|q| q.sweep(now));
+        self.responses.iter().for_each(|r| r.sweep(now));
+    }
+
+    fn prefix(&self, ip: IpAddr) -> IpNet {
+        let len = if ip.is_ipv4() { self.ipv4_prefix } else { self.ipv6_prefix };
+        IpNet::new(ip, len).map(|net| net.trunc()).unwrap_or_else(|_| IpNet::from(ip))
+    }
+
+    /// 客户端查询未超出限制时返回true
+    pub fn allow_query(&self, ip: IpAddr) -> bool {
+        self.allow_query_at(ip, Instant::now())
+    }
+
+    fn allow_query_at(&self, ip: IpAddr, now: Instant) -> bool {
+        match &self.queries {
+            Some(queries) => queries.take(self.prefix(ip), now),
+            None => true,
+        }
+    }
+
+    /// 发往客户端的应答未超出限制时返回true, 无法解析的应答不限制
+    pub fn allow_response(&self, ip: IpAddr, msg: &Bytes) -> bool {
+        self.allow_response_at(ip, msg, Instant::now())
+    }
+
+    fn allow_response_at(&self, ip: IpAddr, msg: &Bytes, now: Instant) -> bool {
+        let responses = match &self.responses {
+            Some(r) => r,
+            None => return true,
+        };
+        let msg = match Message::from_octets(msg.clone()) {
+            Ok(m) => m,
+            Err(_) => return true,
+        };
+        let question = match msg.sole_question() {
+            Ok(q) => q,
+            Err(_) => return true,
+        };
+        let rcode = msg.header().rcode();
+        // NXDOMAIN按区域计数, 避免随机子域名绕过限制
+        let name = match rcode {
+            Rcode::NXDomain => soa_owner(&msg).unwrap_or_else(|| question.qname().to_string()),
+            _ => question.qname().to_string(),
+        };
+        let key = (self.prefix(ip), name.to_ascii_lowercase(), question.qtype().to_int(), rcode.to_int());
+        responses.take(key, now)
+    }
+
+    /// 超出限制时发给客户端的应答, 丢弃或无法解析时返回None
+    pub fn limited(&self, msg: &Bytes) -> Option<Bytes> {
+        let rcode = match self.action {
+            Action::Drop => return None,
+            Action::Refused => Rcode::Refused,
+            Action::Truncate => Rcode::NoError,
+        };
+        empty_answer(msg, rcode, self.action == Action::Truncate)
+    }
+}
+
+// 授权部分SOA记录的所有者, 即应答所属的区域
+fn soa_owner(msg: &Message<Bytes>) -> Option<String> {
+    msg.authority().ok()?.flatten().find(|rr| rr.rtype() == Rtype::Soa).map(|rr| rr.owner().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::BytesMut;
+    use domain::base::{iana::Class, Dname, MessageBuilder, Serial};
+    use domain::rdata::Soa;
+    use super::*;
+
+    fn limiter(json: serde_json::Value) -> RateLimiter {
+        RateLimiter::new(&serde_json::from_value(json).unwrap())
+    }
+
+    fn query(name: &str) -> Bytes {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push((Dname::<Bytes>::from_str(name).unwrap(), Rtype::A)).unwrap();
+        builder.into_message().into_octets()
+    }
+
+    #[test]
+    fn test_queries() {
+        let limiter = limiter(serde_json::json!({"qps": 2, "burst": 3}));
+        let now = Instant::now();
+        let client = "192.168.1.10".parse().unwrap();
+        for _ in 0..3 {
+            assert!(limiter.allow_query_at(client, now));
+        }
+        assert!(!limiter.allow_query_at(client, now));
+        // 同一/24网段共用令牌桶
+        assert!(!limiter.allow_query_at("192.168.1.20".parse().unwrap(), now));
+        assert!(limiter.allow_query_at("192.168.2.10".parse().unwrap(), now));
+        // 每秒补充2个令牌
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow_query_at(client, later));
+        assert!(!limiter.allow_query_at(client, later));
+        // 补满后被清理
+        let later = now + SWEEP_INTERVAL;
+        assert!(limiter.allow_query_at("10.0.0.1".parse().unwrap(), later));
+        limiter.sweep(later);
+        assert_eq!(limiter.queries.as_ref().unwrap().len(), 1);
+        // 未配置RRL时不限制应答
+        assert!(limiter.allow_response_at(client, &query("example.com"), now));
+    }
+
+    #[test]
+    fn test_responses() {
+        let limiter = limiter(serde_json::json!({"rrl": 1, "ipv6_prefix": 64}));
+        let now = Instant::now();
+        let client = "2001:db8::1".parse().unwrap();
+        assert!(limiter.allow_query_at(client, now));
+        assert!(limiter.allow_response_at(client, &query("example.com"), now));
+        assert!(!limiter.allow_response_at("2001:db8::2".parse().unwrap(), &query("EXAMPLE.com"), now));
+        assert!(limiter.allow_response_at(client, &query("www.example.com"), now));
+        assert!(limiter.allow_response_at("2001:db8:0:1::1".parse().unwrap(), &query("example.com"), now));
+        assert!(limiter.allow_response_at(client, &Bytes::from_static(b"bogus"), now));
+    }
+
+    fn nxdomain(name: &str) -> Bytes {
+        let qmsg = Message::from_octets(query(name)).unwrap();
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+            .start_answer(&qmsg, Rcode::NXDomain).unwrap().authority();
+        let zone = Dname::<Bytes>::from_str("example.com").unwrap();
+        let soa = Soa::new(zone.clone(), zone.clone(), Serial(1), 3600, 600, 86400, 300);
+        builder.push((zone, Class::In, 300, soa)).unwrap();
+        builder.into_message().into_octets()
+    }
+
+    #[test]
+    fn test_nxdomain() {
+        let limiter = limiter(serde_json::json!({"rrl": 2}));
+        let now = Instant::now();
+        let client = "192.0.2.1".parse().unwrap();
+        // 随机子域名的NXDOMAIN按区域共用限制
+        assert!(limiter.allow_response_at(client, &nxdomain("a1.example.com"), now));
+        assert!(limiter.allow_response_at(client, &nxdomain("b2.example.com"), now));
+        assert!(!limiter.allow_response_at(client, &nxdomain("c3.example.com"), now));
+        assert!(limiter.allow_response_at(client, &query("a1.example.com"), now));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buckets = Buckets::new(1, 1);
+        buckets.capacity = 2;
+        let now = Instant::now();
+        for i in 0..1000u32 {
+            assert!(buckets.take(i, now));
+        }
+        assert!(buckets.len() <= 2 * SHARDS);
+        buckets.sweep(now + SWEEP_INTERVAL);
+        assert_eq!(buckets.len(), 0);
+    }
+
+    #[test]
+    fn test_limited() {
+        let msg = query("example.com");
+        assert_eq!(limiter(serde_json::json!({"qps": 1})).limited(&msg), None);
+
+        let res = limiter(serde_json::json!({"qps": 1, "action": "refused"})).limited(&msg).unwrap();
+        let res = Message::from_octets(res).unwrap();
+        assert_eq!(res.header().rcode(), Rcode::Refused);
+        assert!(res.header().qr() && !res.header().tc());
+        assert_eq!(res.header().id(), Message::from_octets(msg.clone()).unwrap().header().id());
+
+        let res = limiter(serde_json::json!({"qps": 1, "action": "truncate"})).limited(&msg).unwrap();
+        let res = Message::from_octets(res).unwrap();
+        assert_eq!(res.header().rcode(), Rcode::NoError);
+        assert!(res.header().tc());
+        assert_eq!(res.sole_question().unwrap().qname().to_string(), "example.com");
+    }
+}
@@ -1,17 +1,23 @@
-use std::{sync::Arc, net::SocketAddr};
+use std::{sync::Arc, net::SocketAddr, time::SystemTime};
 use anyhow::{Result};
 use async_trait::async_trait;
-use bytes::BytesMut;
-use tokio::{net::UdpSocket, signal};
+use bytes::{Bytes, BytesMut};
+use tokio::net::UdpSocket;
+use crate::access::ZAccess;
 use crate::base::ZServer;
+use zdnstap::DNSTAP;
+use zshutdown::SHUTDOWN;
+use zstats::STATS;
 use zqueue::{ZRequestQueue, ZResponseQueue, ZQueueHander};
 use super::ServerConf;
 
 #[derive(Debug, Clone)]
 pub struct UdpZserver {
     socket: Arc<UdpSocket>,
+    local: SocketAddr,
     req_q: Arc<ZRequestQueue>,
     res_q: Arc<ZResponseQueue>,
+    access: Arc<ZAccess>, // 重新加载配置时替换
 }
 
 impl UdpZserver {
@@ -34,7 +40,24 @@ impl UdpZserver {
                         }
                     };
                     buf.resize(len, 0);
-                    match self.req_q.send((src, buf.freeze())).await {
+                    let received = SystemTime::now();
+                    DNSTAP.client_query(src, self.local, received, &buf);
+                    let buf = buf.freeze();
+                    if let Some(acl) = self.access.acl().filter(|a| !a.allow(src.ip())) {
+                        debug!("Denied query from {:?}", src);
+                        if let Some(msg) = acl.denied(&buf) {
+                            self.reply(src, msg, received).await;
+                        }
+                        continue;
+                    }
+                    if let Some(limiter) = self.access.limiter().filter(|l| !l.allow_query(src.ip())) {
+                        STATS.ratelimited("query");
+                        if let Some(msg) = limiter.limited(&buf) {
+                            self.reply(src, msg, received).await;
+                        }
+                        continue;
+                    }
+                    match self.req_q.send((src, buf, received)).await {
                         Ok(_) => {},
                         Err(e) => {error!("Failed to push request to queue, error:{:?}", e)}
                     }
@@ -42,12 +65,7 @@ impl UdpZserver {
                 
                 res = self.res_q.recv() => {
                     match res {
-                        Ok((src, msg)) => {
-                            match self.socket.send_to(&msg, src).await{
-                                Ok(_) => continue,
-                                Err(e) => error!("Failed to send data to client({:?}), error:{:?}", src, e),
-                            }
-                        },
+                        Ok((src, msg, received)) => self.reply(src, msg, received).await,
                         Err(e) => {
                             error!("Failed to read response queue, error:{:?}", e);
                         },
@@ -60,13 +78,31 @@ impl UdpZserver {
                 //         Err(e) => error!("Failed to send data to client({:?}), error:{:?}", src, e),
                 //     }
                 // }
-                _ = signal::ctrl_c() => {
+                _ = SHUTDOWN.wait() => {
                     return self.stop().await;
                 }
             }
         }
     }
 
+    // `received` 为收到查询的时间, 用于dnstap记录
+    async fn reply(&self, src: SocketAddr, msg: Bytes, received: SystemTime) {
+        let msg = match self.access.limiter().filter(|l| !l.allow_response(src.ip(), &msg)) {
+            Some(limiter) => {
+                STATS.ratelimited("response");
+                match limiter.limited(&msg) {
+                    Some(msg) => msg,
+                    None => return,
+                }
+            },
+            None => msg,
+        };
+        DNSTAP.client_response(src, self.local, received, &msg);
+        if let Err(e) = self.socket.send_to(&msg, src).await {
+            error!("Failed to send data to client({:?}), error:{:?}", src, e);
+        }
+    }
+
 }
 
 #[async_trait]
@@ -75,14 +111,27 @@ impl ZServer for UdpZserver {
     async fn build(req_q: Arc<ZRequestQueue>, res_q: Arc<ZResponseQueue>, conf: ServerConf) -> Result<Self> {
         let socket_addr = format!("0.0.0.0:{}", conf.port).parse::<SocketAddr>()?;
         let socket = Arc::new(UdpSocket::bind(socket_addr).await?);
-        Ok(Self { socket, req_q: req_q.clone(), res_q: res_q.clone() })
+        let local = socket.local_addr()?;
+        let access = Arc::new(ZAccess::build(&conf)?);
+        tokio::spawn(access.clone().sweeper());
+        Ok(Self { socket, local, req_q: req_q.clone(), res_q: res_q.clone(), access })
+    }
+
+    fn access(&self) -> Arc<ZAccess> {
+        self.access.clone()
     }
 
     async fn start(&self) -> Result<()> {
         self.serve().await
     }
 
+    /// 停止接收请求, 继续发送应答直到应答队列关闭且为空.
     async fn stop(&self) -> Result<()> {
+        info!("zserver stops accepting requests, {} queued", self.req_q.len());
+        self.req_q.close();
+        while let Ok((src, msg, received)) = self.res_q.recv().await {
+            self.reply(src, msg, received).await;
+        }
         info!("Service is down...");
         Ok(())
     }
@@ -0,0 +1,5 @@
+#[macro_use] extern crate log;
+
+mod shutdown;
+
+pub use shutdown::{Shutdown, SHUTDOWN};
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use tokio::signal::{self, unix::{signal as unix_signal, SignalKind}};
+use tokio::sync::watch;
+
+lazy_static! {
+    pub static ref SHUTDOWN: Shutdown = Shutdown::default();
+}
+
+/// 关闭通知, 各组件等待通知后停止接收新任务.
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>, // 保留一个接收端, 避免通知时通道已关闭
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender, receiver }
+    }
+}
+
+impl Shutdown {
+
+    /// 收到SIGINT或SIGTERM时通知关闭, 再次收到时立即退出.
+    pub fn listen(&'static self) -> Result<()> {
+        let mut terminate = unix_signal(SignalKind::terminate())?;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = signal::ctrl_c() => info!("Received SIGINT, shutting down"),
+                _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+            }
+            self.trigger();
+            tokio::select! {
+                _ = signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+            warn!("Received a second signal, exiting immediately");
+            std::process::exit(1);
+        });
+        Ok(())
+    }
+
+    pub fn trigger(&self) {
+        let _ = self.sender.send(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// 等待关闭通知, 已通知时立即返回
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let shutdown = Arc::new(Shutdown::default());
+        assert!(!shutdown.is_triggered());
+        let waiter = shutdown.clone();
+        let handle = tokio::spawn(async move { waiter.wait().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!handle.is_finished());
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+        assert!(shutdown.is_triggered());
+        // 通知后再等待立即返回
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+}
@@ -5,6 +5,7 @@ use anyhow::{anyhow, Result};
 use tokio::sync::{mpsc};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Barrier;
+use zstats::STATS;
 
 pub struct ZSpeedTest;
 
@@ -15,6 +16,7 @@ impl ZSpeedTest {
             return Err(anyhow!("empty ip list."));
         }
         if ip_list.len() == 1 {
+            STATS.speedtest("single");
             return Ok(ip_list[0]);
         }
         let res_ip = ip_list[0];
@@ -34,10 +36,12 @@ impl ZSpeedTest {
             res = receiver.recv() => {
                 if let Some(res_ip) = res {
                     receiver.close();
+                    STATS.speedtest("fastest");
                     return Ok(res_ip);
                 }
             },
             _ = sleep(Duration::from_secs(2)) => {
+              STATS.speedtest("timeout");
               return Ok(res_ip);
             }
         }
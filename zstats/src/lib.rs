@@ -0,0 +1,4 @@
+mod stats;
+pub mod metrics;
+
+pub use stats::{Stats, STATS};
@@ -0,0 +1,93 @@
+use std::fmt::Write;
+
+// 耗时直方图的桶(秒)
+pub const BUCKETS: [f64; 12] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// 固定桶的直方图
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS.len()], // 各桶的计数, 未累加
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+
+    pub fn observe(&mut self, value: f64) {
+        if let Some(i) = BUCKETS.iter().position(|b| value <= *b) {
+            self.counts[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// 输出 `_bucket`, `_sum` 与 `_count` 样本
+    pub fn write(&self, out: &mut String, name: &str, labels: &[(&str, &str)]) {
+        let mut total = 0;
+        for (bucket, count) in BUCKETS.iter().zip(self.counts.iter()) {
+            total += count;
+            let le = bucket.to_string();
+            sample(out, &format!("{}_bucket", name), &[labels, &[("le", le.as_str())]].concat(), total as f64);
+        }
+        sample(out, &format!("{}_bucket", name), &[labels, &[("le", "+Inf")]].concat(), self.count as f64);
+        sample(out, &format!("{}_sum", name), labels, self.sum);
+        sample(out, &format!("{}_count", name), labels, self.count as f64);
+    }
+}
+
+/// 输出指标的 `# HELP` 及 `# TYPE` 行
+pub fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.25);
+        histogram.observe(0.5);
+        histogram.observe(10.0);
+        let mut out = String::new();
+        histogram.write(&mut out, "rtt_seconds", &[("upstream", "1.1.1.1:53")]);
+        assert!(out.starts_with("rtt_seconds_bucket{upstream=\"1.1.1.1:53\",le=\"0.001\"} 0\n"));
+        assert!(out.contains("rtt_seconds_bucket{upstream=\"1.1.1.1:53\",le=\"0.25\"} 1\n"));
+        assert!(out.contains("rtt_seconds_bucket{upstream=\"1.1.1.1:53\",le=\"0.5\"} 2\n"));
+        assert!(out.contains("rtt_seconds_bucket{upstream=\"1.1.1.1:53\",le=\"5\"} 2\n"));
+        assert!(out.contains("rtt_seconds_bucket{upstream=\"1.1.1.1:53\",le=\"+Inf\"} 3\n"));
+        assert!(out.ends_with("rtt_seconds_sum{upstream=\"1.1.1.1:53\"} 10.75\nrtt_seconds_count{upstream=\"1.1.1.1:53\"} 3\n"));
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut out = String::new();
+        header(&mut out, "queries_total", "counter", "Total queries.");
+        sample(&mut out, "queries_total", &[], 3.0);
+        sample(&mut out, "upstream_errors_total", &[("upstream", "a\"b")], 1.0);
+        assert_eq!(out, "# HELP queries_total Total queries.\n# TYPE queries_total counter\nqueries_total 3\nupstream_errors_total{upstream=\"a\\\"b\"} 1\n");
+    }
+}
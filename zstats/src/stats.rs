@@ -0,0 +1,305 @@
+use std::{collections::BTreeMap, sync::{Mutex, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use crate::metrics::{self, Histogram};
+
+// 计算最近查询速率的窗口(秒)
+const WINDOW: usize = 60;
+// 单独统计的查询类型, 其它类型计入 `OTHER`, 避免指标标签过多
+const QTYPES: [&str; 22] = ["A", "AAAA", "ANY", "CAA", "CNAME", "DNSKEY", "DS", "HTTPS", "MX", "NAPTR", "NONE",
+    "NS", "NSEC", "NSEC3", "NULL", "PTR", "RRSIG", "SOA", "SRV", "SVCB", "TLSA", "TXT"];
+
+lazy_static! {
+    pub static ref STATS: Stats = Stats::new();
+}
+
+// 最近 WINDOW 秒内每秒的查询数
+struct Window {
+    buckets: [u64; WINDOW],
+    last: u64, // 最后写入的秒数
+}
+
+impl Window {
+
+    fn advance(&mut self, now: u64) {
+        if now > self.last {
+            for sec in (self.last + 1)..=now.min(self.last + WINDOW as u64) {
+                self.buckets[sec as usize % WINDOW] = 0;
+            }
+            self.last = now;
+        }
+    }
+
+    fn add(&mut self, now: u64) {
+        self.advance(now);
+        self.buckets[now as usize % WINDOW] += 1;
+    }
+
+    // 不含当前未结束的一秒
+    fn rate(&mut self, now: u64) -> f64 {
+        self.advance(now);
+        let secs = now.min(WINDOW as u64 - 1);
+        if secs == 0 {
+            return 0.0;
+        }
+        let total: u64 = (1..=secs).map(|i| self.buckets[(now - i) as usize % WINDOW]).sum();
+        total as f64 / secs as f64
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Upstream {
+    queries: u64,
+    errors: u64,
+    rtt: Histogram, // 成功查询的耗时
+    last_latency: Duration,
+}
+
+/// 运行统计
+pub struct Stats {
+    started: Instant,
+    queries: AtomicU64,
+    window: Mutex<Window>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    cache_refreshes: AtomicU64,
+    responses: Mutex<BTreeMap<(String, String), u64>>, // 按查询类型及应答码计数
+    duration: Mutex<Histogram>,
+    upstreams: Mutex<BTreeMap<String, Upstream>>,
+    speedtests: Mutex<BTreeMap<&'static str, u64>>,
+    ratelimited: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            queries: AtomicU64::new(0),
+            window: Mutex::new(Window { buckets: [0; WINDOW], last: 0 }),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            cache_refreshes: AtomicU64::new(0),
+            responses: Mutex::new(BTreeMap::new()),
+            duration: Mutex::new(Histogram::default()),
+            upstreams: Mutex::new(BTreeMap::new()),
+            speedtests: Mutex::new(BTreeMap::new()),
+            ratelimited: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    /// 记录一次客户端查询
+    pub fn query(&self) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.window.lock().unwrap().add(self.now());
+    }
+
+    /// 记录一次应答及处理耗时
+    pub fn response(&self, qtype: &str, rcode: &str, latency: Duration) {
+        let qtype = if QTYPES.contains(&qtype) { qtype } else { "OTHER" };
+        *self.responses.lock().unwrap().entry((qtype.to_string(), rcode.to_string())).or_default() += 1;
+        self.duration.lock().unwrap().observe(latency.as_secs_f64());
+    }
+
+    pub fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次后台解析并写入缓存
+    pub fn cache_refresh(&self) {
+        self.cache_refreshes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次上游查询, 失败或超时时 `ok` 为false.
+    pub fn upstream(&self, name: &str, latency: Duration, ok: bool) {
+        let mut upstreams = self.upstreams.lock().unwrap();
+        let upstream = upstreams.entry(name.to_string()).or_default();
+        upstream.queries += 1;
+        if ok {
+            upstream.rtt.observe(latency.as_secs_f64());
+            upstream.last_latency = latency;
+        } else {
+            upstream.errors += 1;
+        }
+    }
+
+    /// 记录一次测速结果, 如 `fastest`, `timeout`
+    pub fn speedtest(&self, outcome: &'static str) {
+        *self.speedtests.lock().unwrap().entry(outcome).or_default() += 1;
+    }
+
+    /// 记录一次超出限速, `kind` 为 `query` 或 `response`
+    pub fn ratelimited(&self, kind: &'static str) {
+        *self.ratelimited.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    pub fn to_json(&self) -> Value {
+        let uptime = self.started.elapsed().as_secs_f64();
+        let queries = self.queries.load(Ordering::Relaxed);
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let misses = self.cache_misses.load(Ordering::Relaxed);
+        let upstreams: Vec<Value> = self.upstreams.lock().unwrap().iter().map(|(name, u)| {
+            let ok = u.rtt.count();
+            json!({
+                "name": name,
+                "queries": u.queries,
+                "errors": u.errors,
+                "avg_latency_ms": if ok > 0 { u.rtt.sum() * 1000.0 / ok as f64 } else { 0.0 },
+                "last_latency_ms": u.last_latency.as_secs_f64() * 1000.0,
+            })
+        }).collect();
+        json!({
+            "uptime_secs": uptime as u64,
+            "queries": {
+                "total": queries,
+                "qps": self.window.lock().unwrap().rate(self.now()),
+                "avg_qps": if uptime > 0.0 { queries as f64 / uptime } else { 0.0 },
+            },
+            "cache": {
+                "hits": hits,
+                "misses": misses,
+                "hit_ratio": if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+                "refreshes": self.cache_refreshes.load(Ordering::Relaxed),
+            },
+            "upstreams": upstreams,
+        })
+    }
+
+    /// Prometheus文本格式的指标
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        metrics::header(&mut out, "zzdns_uptime_seconds", "gauge", "Seconds since zzdns started.");
+        metrics::sample(&mut out, "zzdns_uptime_seconds", &[], self.started.elapsed().as_secs() as f64);
+        metrics::header(&mut out, "zzdns_queries_total", "counter", "Client queries received.");
+        metrics::sample(&mut out, "zzdns_queries_total", &[], self.queries.load(Ordering::Relaxed) as f64);
+
+        metrics::header(&mut out, "zzdns_responses_total", "counter", "Responses by query type and response code.");
+        for ((qtype, rcode), count) in self.responses.lock().unwrap().iter() {
+            metrics::sample(&mut out, "zzdns_responses_total", &[("qtype", qtype), ("rcode", rcode)], *count as f64);
+        }
+        metrics::header(&mut out, "zzdns_query_duration_seconds", "histogram", "Time taken to answer client queries.");
+        self.duration.lock().unwrap().write(&mut out, "zzdns_query_duration_seconds", &[]);
+
+        let counters = [
+            ("zzdns_cache_hits_total", "Queries answered from the cache.", &self.cache_hits),
+            ("zzdns_cache_misses_total", "Queries not found in the cache.", &self.cache_misses),
+            ("zzdns_cache_refreshes_total", "Records resolved and written to the cache in the background.", &self.cache_refreshes),
+        ];
+        for (name, help, counter) in counters {
+            metrics::header(&mut out, name, "counter", help);
+            metrics::sample(&mut out, name, &[], counter.load(Ordering::Relaxed) as f64);
+        }
+
+        let upstreams = self.upstreams.lock().unwrap();
+        metrics::header(&mut out, "zzdns_upstream_queries_total", "counter", "Queries sent to each upstream.");
+        for (name, u) in upstreams.iter() {
+            metrics::sample(&mut out, "zzdns_upstream_queries_total", &[("upstream", name)], u.queries as f64);
+        }
+        metrics::header(&mut out, "zzdns_upstream_errors_total", "counter", "Failed or timed out upstream queries.");
+        for (name, u) in upstreams.iter() {
+            metrics::sample(&mut out, "zzdns_upstream_errors_total", &[("upstream", name)], u.errors as f64);
+        }
+        metrics::header(&mut out, "zzdns_upstream_rtt_seconds", "histogram", "Round trip time of successful upstream queries.");
+        for (name, u) in upstreams.iter() {
+            u.rtt.write(&mut out, "zzdns_upstream_rtt_seconds", &[("upstream", name)]);
+        }
+        drop(upstreams);
+
+        metrics::header(&mut out, "zzdns_speedtest_total", "counter", "Speed test outcomes when choosing an IP.");
+        for (outcome, count) in self.speedtests.lock().unwrap().iter() {
+            metrics::sample(&mut out, "zzdns_speedtest_total", &[("outcome", outcome)], *count as f64);
+        }
+        metrics::header(&mut out, "zzdns_ratelimited_total", "counter", "Queries and responses over the rate limit.");
+        for (kind, count) in self.ratelimited.lock().unwrap().iter() {
+            metrics::sample(&mut out, "zzdns_ratelimited_total", &[("kind", kind)], *count as f64);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let mut window = Window { buckets: [0; WINDOW], last: 0 };
+        for sec in 1..=10 {
+            for _ in 0..sec {
+                window.add(sec);
+            }
+        }
+        // 第10秒未结束, 只统计第0~9秒
+        assert_eq!(window.rate(10), 45.0 / 10.0);
+        assert_eq!(window.rate(11), 55.0 / 11.0);
+        // 超过窗口后旧的计数被清除
+        assert_eq!(window.rate(200), 0.0);
+        window.add(200);
+        assert_eq!(window.rate(201), 1.0 / 59.0);
+    }
+
+    #[test]
+    fn test_json() {
+        let stats = Stats::new();
+        stats.query();
+        stats.cache_hit();
+        stats.cache_hit();
+        stats.cache_hit();
+        stats.cache_miss();
+        stats.upstream("1.1.1.1:53", Duration::from_millis(20), true);
+        stats.upstream("1.1.1.1:53", Duration::from_millis(40), true);
+        stats.upstream("1.1.1.1:53", Duration::from_secs(1), false);
+        let report = stats.to_json();
+        assert_eq!(report["queries"]["total"], 1);
+        assert_eq!(report["cache"]["hit_ratio"], 0.75);
+        assert_eq!(report["upstreams"][0]["name"], "1.1.1.1:53");
+        assert_eq!(report["upstreams"][0]["queries"], 3);
+        assert_eq!(report["upstreams"][0]["errors"], 1);
+        assert_eq!(report["upstreams"][0]["avg_latency_ms"], 30.0);
+        assert_eq!(report["upstreams"][0]["last_latency_ms"], 40.0);
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let stats = Stats::new();
+        stats.query();
+        stats.response("A", "NOERROR", Duration::from_millis(3));
+        stats.response("AAAA", "SERVFAIL", Duration::from_millis(30));
+        stats.response("A", "NOERROR", Duration::from_millis(1));
+        stats.response("TYPE65280", "NOERROR", Duration::from_millis(10));
+        stats.response("TYPE65281", "NOERROR", Duration::from_millis(10));
+        stats.cache_miss();
+        stats.upstream("https://dns.example/dns-query", Duration::from_millis(20), true);
+        stats.upstream("https://dns.example/dns-query", Duration::from_secs(3), false);
+        stats.speedtest("fastest");
+        stats.ratelimited("query");
+        let out = stats.to_prometheus();
+        assert!(out.contains("# TYPE zzdns_queries_total counter\nzzdns_queries_total 1\n"));
+        assert!(out.contains("zzdns_responses_total{qtype=\"A\",rcode=\"NOERROR\"} 2\n"));
+        assert!(out.contains("zzdns_responses_total{qtype=\"AAAA\",rcode=\"SERVFAIL\"} 1\n"));
+        assert!(out.contains("zzdns_query_duration_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(out.contains("zzdns_responses_total{qtype=\"OTHER\",rcode=\"NOERROR\"} 2\n"));
+        assert!(!out.contains("TYPE65280"));
+        assert!(out.contains("zzdns_query_duration_seconds_count 5\n"));
+        assert!(out.contains("zzdns_cache_misses_total 1\n"));
+        assert!(out.contains("zzdns_upstream_errors_total{upstream=\"https://dns.example/dns-query\"} 1\n"));
+        assert!(out.contains("zzdns_upstream_rtt_seconds_count{upstream=\"https://dns.example/dns-query\"} 1\n"));
+        assert!(out.contains("zzdns_speedtest_total{outcome=\"fastest\"} 1\n"));
+        assert!(out.contains("zzdns_ratelimited_total{kind=\"query\"} 1\n"));
+    }
+}
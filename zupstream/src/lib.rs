@@ -1,6 +1,12 @@
+#[macro_use] extern crate log;
+
 mod base;
 mod udp;
+mod tcp;
+mod tls;
+mod quic;
 mod https;
+mod recursive;
 mod upstream;
 
 pub use upstream::ZUpstream;
\ No newline at end of file
@@ -0,0 +1,69 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use quinn::{ClientConfig, Connection, Endpoint};
+use tokio::sync::{Mutex, mpsc::Sender};
+use crate::{base::QHandler, tls::tls_config};
+
+/// DNS over QUIC(RFC 9250)上游, 复用同一连接, 每个查询一个双向流
+#[derive(Clone)]
+pub struct QuicUpstream
+{
+    server_addr: SocketAddr,
+    endpoint: Endpoint,
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+impl QuicUpstream {
+    pub async fn build(server_addr: SocketAddr) -> Result<Box<dyn QHandler>>{
+        let bind_addr: SocketAddr = if server_addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+        let mut endpoint = Endpoint::client(bind_addr)?;
+        endpoint.set_default_client_config(ClientConfig::new(Arc::new(tls_config(&[b"doq"]))));
+        Ok(Box::new(Self {
+            server_addr,
+            endpoint,
+            connection: Arc::new(Mutex::new(None)),
+        }))
+    }
+
+    // 已有连接断开时重新建立
+    async fn connection(&self) -> Result<Connection> {
+        let mut connection = self.connection.lock().await;
+        if let Some(conn) = connection.as_ref() {
+            if conn.close_reason().is_none() {
+                return Ok(conn.clone());
+            }
+        }
+        let conn = self.endpoint.connect(self.server_addr, &self.server_addr.ip().to_string())?.await?;
+        *connection = Some(conn.clone());
+        Ok(conn)
+    }
+}
+
+#[async_trait]
+impl QHandler for QuicUpstream {
+
+    async fn query(&self, qmsg: Bytes, sender: Sender<Bytes>) -> Result<()> {
+        // DoQ要求报文ID为0, 应答后恢复原ID
+        let mut buf = BytesMut::with_capacity(qmsg.len() + 2);
+        buf.put_u16(qmsg.len() as u16);
+        buf.put_u16(0);
+        buf.put_slice(&qmsg[2..]);
+        let (mut send, recv) = self.connection().await?.open_bi().await?;
+        send.write_all(&buf).await?;
+        send.finish().await?;
+        let res = recv.read_to_end(u16::MAX as usize + 2).await?;
+        if res.len() < 4 {
+            return Err(anyhow::anyhow!("short DoQ response from {}", self.server_addr));
+        }
+        let mut res = BytesMut::from(&res[2..]);
+        res[..2].copy_from_slice(&qmsg[..2]);
+        tokio::select! {
+            _ = sender.closed() => {}
+            _ = sender.send_timeout(res.freeze(), Duration::from_secs(1)) => {}
+        }
+        Ok(())
+    }
+
+}
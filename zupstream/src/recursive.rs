@@ -0,0 +1,565 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use domain::base::{Dname, Message, MessageBuilder, ParsedDname, Question, Record, RecordSection, Rtype, ToDname};
+use domain::base::iana::{Class, Rcode};
+use domain::rdata::AllRecordData;
+use tokio::{sync::mpsc::Sender, time::timeout};
+use crate::base::QHandler;
+use crate::udp::exchange;
+
+type Rr = Record<Dname<Bytes>, AllRecordData<Bytes, Dname<Bytes>>>;
+// 委派: 子区域, NS域名, TTL
+type Delegation = (Dname<Bytes>, Vec<Dname<Bytes>>, u32);
+// 区域的权威服务器地址及过期时间
+type Servers = (Vec<IpAddr>, Instant);
+// 应答所在区域及权威服务器的应答
+type Lookup<'a> = Pin<Box<dyn Future<Output = Result<(Dname<Bytes>, Message<Bytes>)>> + Send + 'a>>;
+
+// 根服务器IPv4地址, 见 https://www.internic.net/domain/named.root
+const ROOT_HINTS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+// 单个权威服务器的查询超时
+const QUERY_TIMEOUT: Duration = Duration::from_millis(800);
+// 超时或出错的服务器记录的RTT
+const RTT_PENALTY: Duration = Duration::from_secs(2);
+// 每次查询最多尝试的服务器数量
+const MAX_SERVERS: usize = 3;
+const MAX_REFERRALS: usize = 32;
+const MAX_CNAME_CHAIN: usize = 8;
+// 解析无glue的NS地址时的最大嵌套层数
+const MAX_DEPTH: usize = 4;
+// 委派缓存的TTL范围(秒)
+const MIN_DELEGATION_TTL: u32 = 60;
+const MAX_DELEGATION_TTL: u32 = 86400;
+const UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// 递归解析: 从根服务器开始迭代查询, 缓存委派信息, 使用QNAME最小化(RFC 9156),
+/// 按RTT选择权威服务器. 只使用IPv4地址的权威服务器.
+#[derive(Clone)]
+pub struct RecursiveUpstream {
+    roots: Vec<IpAddr>,
+    port: u16,
+    delegations: Arc<Mutex<HashMap<String, Servers>>>,
+    rtts: Arc<Mutex<HashMap<IpAddr, Duration>>>,
+}
+
+impl RecursiveUpstream {
+
+    /// `hints` 为named.root格式的根提示文件, 为空或 `.` 时使用内置的根服务器地址.
+    pub async fn build(hints: String, port: u16) -> Result<Box<dyn QHandler>> {
+        let roots = match hints.as_str() {
+            "" | "." => ROOT_HINTS.iter().map(|a| IpAddr::V4(*a)).collect(),
+            file => {
+                let text = tokio::fs::read_to_string(file).await
+                    .map_err(|e| anyhow!("Failed to read root hints({:?}), error: {}", file, e))?;
+                parse_hints(&text)
+            },
+        };
+        if roots.is_empty() {
+            return Err(anyhow!("No root server address in hints {:?}", hints));
+        }
+        Ok(Box::new(Self::new(roots, port)))
+    }
+
+    pub fn new(roots: Vec<IpAddr>, port: u16) -> Self {
+        Self {
+            roots,
+            port,
+            delegations: Arc::new(Mutex::new(HashMap::new())),
+            rtts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 迭代解析查询, 跟随CNAME链, 返回以 `qmsg` 为问题的应答.
+    pub async fn resolve(&self, qmsg: &Message<Bytes>) -> Result<Bytes> {
+        let question = qmsg.sole_question()?;
+        let qtype = question.qtype();
+        let dnssec_ok = qmsg.opt().is_some_and(|o| o.dnssec_ok());
+        let mut name = question.qname().to_dname::<Bytes>()?;
+        let mut answers: Vec<Rr> = Vec::new();
+        let mut authority = Vec::new();
+        let mut rcode = Rcode::NoError;
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            let (zone, rmsg) = self.lookup(name.clone(), qtype, dnssec_ok, 0).await?;
+            rcode = rmsg.header().rcode();
+            // 区域外的CNAME目标不采用该服务器的记录, 在下一轮重新解析
+            answers.extend(bailiwick(records(rmsg.answer()?)?, &name, &zone));
+            authority = records(rmsg.authority()?)?.into_iter().filter(|r| r.owner().ends_with(&zone)).collect();
+            let target = chase(&answers, &name);
+            let resolved = answers.iter().any(|r| *r.owner() == target && (r.rtype() == qtype || qtype == Rtype::Any));
+            if target == name || resolved || rcode != Rcode::NoError || qtype == Rtype::Cname {
+                break;
+            }
+            name = target;
+        }
+
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, rcode)?;
+        rmsg.header_mut().set_ra(true);
+        for record in answers {
+            rmsg.push(record)?;
+        }
+        let mut rmsg = rmsg.authority();
+        for record in authority {
+            rmsg.push(record)?;
+        }
+        let mut rmsg = rmsg.additional();
+        if qmsg.opt().is_some() {
+            rmsg.opt(|opt| {
+                opt.set_udp_payload_size(UDP_PAYLOAD_SIZE);
+                opt.set_dnssec_ok(dnssec_ok);
+                Ok(())
+            })?;
+        }
+        Ok(rmsg.into_message().into_octets())
+    }
+
+    // 从最近的已知委派开始查询 `name`, 返回其权威服务器的应答.
+    fn lookup(&self, name: Dname<Bytes>, qtype: Rtype, dnssec_ok: bool, depth: usize) -> Lookup<'_> {
+        Box::pin(async move {
+            // DS记录由父区域应答
+            let (mut zone, mut servers) = self.closest(&name, qtype == Rtype::Ds);
+            let mut minimise = true;
+            let mut labels = zone.label_count() + 1;
+
+            for _ in 0..MAX_REFERRALS {
+                let (qname, qt) = match minimise && labels < name.label_count() {
+                    true => (suffix(&name, labels), Rtype::A),
+                    false => (name.clone(), qtype),
+                };
+                let rmsg = self.send(&servers, &qname, qt, dnssec_ok).await?;
+
+                if let Some((child, ns_names, ttl)) = referral(&rmsg, &zone, &name)? {
+                    let addrs = self.ns_addrs(&rmsg, &zone, &child, &ns_names, depth).await;
+                    if addrs.is_empty() {
+                        return Err(anyhow!("No address for nameservers of {}", child));
+                    }
+                    self.store(&child, addrs.clone(), ttl);
+                    labels = child.label_count() + 1;
+                    zone = child;
+                    servers = addrs;
+                    continue;
+                }
+                if qname != name {
+                    // 部分服务器对空的非终端节点返回NXDOMAIN, 此时改用完整域名查询.
+                    match rmsg.header().rcode() {
+                        Rcode::NXDomain => minimise = false,
+                        _ => labels += 1,
+                    }
+                    continue;
+                }
+                return Ok((zone, rmsg));
+            }
+            Err(anyhow!("Too many referrals for {}", name))
+        })
+    }
+
+    // 委派中NS的地址: 优先使用区域内的glue记录, 否则递归解析NS域名.
+    async fn ns_addrs(&self, rmsg: &Message<Bytes>, zone: &Dname<Bytes>, child: &Dname<Bytes>,
+        ns_names: &[Dname<Bytes>], depth: usize) -> Vec<IpAddr> {
+        let mut addrs = Vec::new();
+        let additional = rmsg.additional().map_err(anyhow::Error::from).and_then(records).unwrap_or_default();
+        for record in additional {
+            if let AllRecordData::A(a) = record.data() {
+                if ns_names.contains(record.owner()) && record.owner().ends_with(zone) {
+                    addrs.push(IpAddr::V4(a.addr()));
+                }
+            }
+        }
+        if !addrs.is_empty() || depth >= MAX_DEPTH {
+            return addrs;
+        }
+        // 位于子区域内却没有glue的NS无法解析
+        for ns in ns_names.iter().filter(|n| !n.ends_with(child)) {
+            let (ns_zone, rmsg) = match self.lookup(ns.clone(), Rtype::A, false, depth + 1).await {
+                Ok(r) => r,
+                Err(e) => {
+                    debug!("Failed to resolve nameserver {}, error: {:?}", ns, e);
+                    continue;
+                }
+            };
+            let answer = rmsg.answer().map_err(anyhow::Error::from).and_then(records).unwrap_or_default();
+            addrs.extend(bailiwick(answer, ns, &ns_zone).iter().filter_map(|r| match r.data() {
+                AllRecordData::A(a) => Some(IpAddr::V4(a.addr())),
+                _ => None,
+            }));
+            if !addrs.is_empty() {
+                break;
+            }
+        }
+        addrs
+    }
+
+    // 按RTT从小到大依次尝试服务器, 返回第一个NOERROR或NXDOMAIN应答.
+    async fn send(&self, servers: &[IpAddr], qname: &Dname<Bytes>, qtype: Rtype, dnssec_ok: bool) -> Result<Message<Bytes>> {
+        let mut servers = servers.to_vec();
+        if let Ok(rtts) = self.rtts.lock() {
+            servers.sort_by_key(|s| rtts.get(s).copied().unwrap_or_default());
+        }
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512))?;
+        builder.header_mut().set_random_id();
+        let id = builder.header().id();
+        let mut builder = builder.question();
+        builder.push(Question::new_in(qname.clone(), qtype))?;
+        let mut builder = builder.additional();
+        builder.opt(|opt| {
+            opt.set_udp_payload_size(UDP_PAYLOAD_SIZE);
+            opt.set_dnssec_ok(dnssec_ok);
+            Ok(())
+        })?;
+        let qmsg = builder.into_message().into_octets();
+
+        let mut last_err = anyhow!("No nameserver for {}", qname);
+        for server in servers.iter().take(MAX_SERVERS) {
+            let start = Instant::now();
+            let res = match timeout(QUERY_TIMEOUT, exchange(SocketAddr::new(*server, self.port), &qmsg)).await {
+                Ok(r) => r,
+                Err(_) => Err(anyhow!("Query {} {} to {} timeout", qname, qtype, server)),
+            };
+            let rmsg = match res.and_then(|r| Ok(Message::from_octets(r)?)) {
+                Ok(r) if r.header().id() == id && answers(&r, qname, qtype) => r,
+                Ok(_) => {
+                    last_err = anyhow!("Mismatched response to {} {} from {}", qname, qtype, server);
+                    continue;
+                },
+                Err(e) => {
+                    self.update_rtt(*server, RTT_PENALTY);
+                    last_err = e;
+                    continue;
+                }
+            };
+            self.update_rtt(*server, start.elapsed());
+            match rmsg.header().rcode() {
+                Rcode::NoError | Rcode::NXDomain => return Ok(rmsg),
+                rcode => last_err = anyhow!("Query {} {} to {} failed, rcode: {}", qname, qtype, server, rcode),
+            }
+        }
+        Err(last_err)
+    }
+
+    // 平滑RTT, 新样本占30%
+    fn update_rtt(&self, server: IpAddr, sample: Duration) {
+        if let Ok(mut rtts) = self.rtts.lock() {
+            let rtt = match rtts.get(&server) {
+                Some(rtt) => (*rtt * 7 + sample * 3) / 10,
+                None => sample,
+            };
+            rtts.insert(server, rtt);
+        }
+    }
+
+    // 查找 `name` 最近的已缓存委派, 找不到时从根服务器开始.
+    fn closest(&self, name: &Dname<Bytes>, skip_self: bool) -> (Dname<Bytes>, Vec<IpAddr>) {
+        if let Ok(delegations) = self.delegations.lock() {
+            let now = Instant::now();
+            for zone in name.iter_suffixes().skip(usize::from(skip_self)) {
+                if let Some((addrs, expire)) = delegations.get(&key(&zone)) {
+                    if *expire > now {
+                        return (zone, addrs.clone());
+                    }
+                }
+            }
+        }
+        (Dname::root_bytes(), self.roots.clone())
+    }
+
+    fn store(&self, zone: &Dname<Bytes>, addrs: Vec<IpAddr>, ttl: u32) {
+        let ttl = ttl.clamp(MIN_DELEGATION_TTL, MAX_DELEGATION_TTL);
+        if let Ok(mut delegations) = self.delegations.lock() {
+            delegations.insert(key(zone), (addrs, Instant::now() + Duration::from_secs(ttl.into())));
+        }
+    }
+}
+
+#[async_trait]
+impl QHandler for RecursiveUpstream {
+
+    async fn query(&self, qmsg: Bytes, sender: Sender<Bytes>) -> Result<()> {
+        let res = self.resolve(&Message::from_octets(qmsg)?).await?;
+        tokio::select! {
+            _ = sender.closed() => {}
+            _ = sender.send_timeout(res, Duration::from_secs(1)) => {}
+        }
+        Ok(())
+    }
+}
+
+// 非权威且没有应答记录、authority中有 `zone` 之下的NS记录时为委派.
+fn referral(rmsg: &Message<Bytes>, zone: &Dname<Bytes>, name: &Dname<Bytes>) -> Result<Option<Delegation>> {
+    if rmsg.header().aa() || rmsg.header().rcode() != Rcode::NoError || rmsg.header_counts().ancount() > 0 {
+        return Ok(None);
+    }
+    let mut child = None;
+    let mut ns_names = Vec::new();
+    let mut ttl = u32::MAX;
+    for record in records(rmsg.authority()?)? {
+        if let AllRecordData::Ns(ns) = record.data() {
+            child.get_or_insert_with(|| record.owner().clone());
+            ns_names.push(ns.nsdname().clone());
+            ttl = ttl.min(record.ttl());
+        }
+    }
+    let child = match child {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    if child == *zone || !child.ends_with(zone) || !name.ends_with(&child) {
+        return Err(anyhow!("Lame delegation to {} from zone {}", child, zone));
+    }
+    Ok(Some((child, ns_names, ttl)))
+}
+
+fn records(section: RecordSection<&Bytes>) -> Result<Vec<Rr>> {
+    let mut list = Vec::new();
+    for rr in section {
+        let rr = rr?;
+        if rr.rtype() == Rtype::Opt {
+            continue;
+        }
+        if let Some(record) = rr.to_record::<AllRecordData<Bytes, ParsedDname<&Bytes>>>()? {
+            let owner = record.owner().to_dname::<Bytes>()?;
+            let (class, ttl) = (record.class(), record.ttl());
+            list.push(Record::new(owner, class, ttl, record.into_data().flatten_into()?));
+        }
+    }
+    Ok(list)
+}
+
+// 应答的ID之外, 问题须与查询相同
+fn answers(rmsg: &Message<Bytes>, qname: &Dname<Bytes>, qtype: Rtype) -> bool {
+    rmsg.sole_question().is_ok_and(|q| q.qname() == qname && q.qtype() == qtype && q.qclass() == Class::In)
+}
+
+// 只保留 `zone` 区域内、所有者为 `name` 或其CNAME链上名称的记录, 防止服务器写入无关域名的记录.
+fn bailiwick(records: Vec<Rr>, name: &Dname<Bytes>, zone: &Dname<Bytes>) -> Vec<Rr> {
+    let mut chain = vec![name.clone()];
+    let mut target = name.clone();
+    while chain.len() <= MAX_CNAME_CHAIN && target.ends_with(zone) {
+        let next = records.iter().find_map(|r| match r.data() {
+            AllRecordData::Cname(cname) if *r.owner() == target => Some(cname.cname().clone()),
+            _ => None,
+        });
+        match next {
+            Some(n) if !chain.contains(&n) => {
+                chain.push(n.clone());
+                target = n;
+            },
+            _ => break,
+        }
+    }
+    records.into_iter()
+        .filter(|r| r.owner().ends_with(zone) && chain.contains(r.owner()))
+        .collect()
+}
+
+// 沿应答中的CNAME链查找最终的目标域名
+fn chase(answers: &[Rr], name: &Dname<Bytes>) -> Dname<Bytes> {
+    let mut target = name.clone();
+    for _ in 0..MAX_CNAME_CHAIN {
+        let next = answers.iter().find_map(|r| match r.data() {
+            AllRecordData::Cname(cname) if *r.owner() == target => Some(cname.cname().clone()),
+            _ => None,
+        });
+        match next {
+            Some(n) => target = n,
+            None => break,
+        }
+    }
+    target
+}
+
+// `name` 右侧 `labels` 个标签(含根标签)组成的域名
+fn suffix(name: &Dname<Bytes>, labels: usize) -> Dname<Bytes> {
+    name.iter_suffixes()
+        .find(|s| s.label_count() == labels)
+        .unwrap_or_else(|| name.clone())
+}
+
+fn key(name: &Dname<Bytes>) -> String {
+    name.to_string().to_ascii_lowercase()
+}
+
+/// 解析named.root格式的根提示, 取其中的A记录地址, 也支持每行一个IP地址.
+pub fn parse_hints(text: &str) -> Vec<IpAddr> {
+    let mut addrs = Vec::new();
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let addr = match fields.as_slice() {
+            [addr] => addr.parse::<Ipv4Addr>().ok(),
+            [.., rtype, addr] if rtype.eq_ignore_ascii_case("A") => addr.parse::<Ipv4Addr>().ok(),
+            _ => None,
+        };
+        if let Some(addr) = addr {
+            addrs.push(IpAddr::V4(addr));
+        }
+    }
+    addrs
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Question, Record, Rtype, iana::{Class, Rcode}};
+    use domain::rdata::{A, AllRecordData, Cname};
+    use tokio::net::UdpSocket;
+    use zzone::Zone;
+    use super::{bailiwick, chase, parse_hints, RecursiveUpstream, Rr};
+
+    const TEST_ZONE: &str = r#"
+@       IN SOA  ns hostmaster 1 3600 600 86400 300
+@       IN NS   ns
+ns      IN A    127.0.0.1
+example IN NS   ns.example
+ns.example IN A 127.0.0.2
+sub     IN NS   ns1.example.test.
+"#;
+
+    const EXAMPLE_ZONE: &str = r#"
+@       IN SOA  ns hostmaster 1 3600 600 86400 300
+@       IN NS   ns
+ns      IN A    127.0.0.2
+ns1     IN A    127.0.0.3
+www     IN A    192.0.2.1
+alias   IN CNAME www
+"#;
+
+    const SUB_ZONE: &str = r#"
+@       IN SOA  ns1.example.test. hostmaster 1 3600 600 86400 300
+@       IN NS   ns1.example.test.
+host    IN A    192.0.2.3
+"#;
+
+    // 在环回地址上运行只应答单个区域的权威服务器
+    async fn serve(addr: SocketAddr, zone: Zone) {
+        let socket = UdpSocket::bind(addr).await.unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((len, src)) = socket.recv_from(&mut buf).await {
+                let qmsg = Message::from_octets(Bytes::copy_from_slice(&buf[..len])).unwrap();
+                let _ = socket.send_to(&zone.answer(&qmsg).unwrap(), src).await;
+            }
+        });
+    }
+
+    fn query(name: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(Dname::bytes_from_str(name).unwrap(), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    async fn resolve(upstream: &RecursiveUpstream, name: &str, qtype: Rtype) -> Message<Bytes> {
+        Message::from_octets(upstream.resolve(&query(name, qtype)).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        // 各权威服务器使用相同端口的不同环回地址, glue记录中只有地址.
+        let port = UdpSocket::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        serve(SocketAddr::new([127, 0, 0, 1].into(), port), Zone::parse("test", TEST_ZONE).unwrap()).await;
+        serve(SocketAddr::new([127, 0, 0, 2].into(), port), Zone::parse("example.test", EXAMPLE_ZONE).unwrap()).await;
+        serve(SocketAddr::new([127, 0, 0, 3].into(), port), Zone::parse("sub.test", SUB_ZONE).unwrap()).await;
+        let upstream = RecursiveUpstream::new(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))], port);
+
+        let rmsg = resolve(&upstream, "www.example.test", Rtype::A).await;
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert!(rmsg.header().ra());
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+        assert!(upstream.delegations.lock().unwrap().contains_key("example.test"));
+
+        let rmsg = resolve(&upstream, "alias.example.test", Rtype::A).await;
+        assert_eq!(rmsg.header_counts().ancount(), 2);
+
+        // sub.test 的NS位于 example.test 区域内, 没有glue
+        let rmsg = resolve(&upstream, "host.sub.test", Rtype::A).await;
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let rmsg = resolve(&upstream, "missing.example.test", Rtype::A).await;
+        assert_eq!(rmsg.header().rcode(), Rcode::NXDomain);
+        assert_eq!(rmsg.header_counts().nscount(), 1);
+
+        assert_eq!(upstream.rtts.lock().unwrap().len(), 3);
+    }
+
+    fn record(owner: &str, data: AllRecordData<Bytes, Dname<Bytes>>) -> Rr {
+        Record::new(name(owner), Class::In, 300, data)
+    }
+
+    fn name(s: &str) -> Dname<Bytes> {
+        Dname::bytes_from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_bailiwick() {
+        let a = |owner: &str| record(owner, AllRecordData::A(A::new(Ipv4Addr::new(192, 0, 2, 1))));
+        let cname = |owner: &str, target: &str| record(owner, AllRecordData::Cname(Cname::new(name(target))));
+        let records = vec![
+            cname("alias.example.test", "www.example.test"),
+            a("www.example.test"),
+            cname("www.example.test", "cdn.other.test"),
+            a("cdn.other.test"),
+            a("bank.test"),
+            a("unrelated.example.test"),
+        ];
+        let kept = bailiwick(records, &name("alias.example.test"), &name("example.test"));
+        let owners: Vec<String> = kept.iter().map(|r| r.owner().to_string()).collect();
+        assert_eq!(owners, vec!["alias.example.test", "www.example.test", "www.example.test"]);
+        // 区域外的CNAME目标由 `chase` 重新解析
+        assert_eq!(chase(&kept, &name("alias.example.test")), name("cdn.other.test"));
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_question() {
+        // 应答ID与查询相同, 问题不同
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            while let Ok((len, src)) = socket.recv_from(&mut buf).await {
+                let qmsg = Message::from_octets(Bytes::copy_from_slice(&buf[..len])).unwrap();
+                let mut forged = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap();
+                forged.header_mut().set_id(qmsg.header().id());
+                let mut forged = forged.question();
+                forged.push(Question::new_in(name("bank.test"), Rtype::A)).unwrap();
+                let forged = Message::from_octets(forged.into_message().into_octets()).unwrap();
+                let rmsg = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap()
+                    .start_answer(&forged, Rcode::NoError).unwrap();
+                let _ = socket.send_to(rmsg.as_slice(), src).await;
+            }
+        });
+        let upstream = RecursiveUpstream::new(vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))], port);
+        let res = upstream.send(&[IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))], &name("www.example.test"), Rtype::A, false).await;
+        let err = res.err().unwrap();
+        assert!(err.to_string().starts_with("Mismatched response"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_hints() {
+        let hints = ";       This file holds the information on root name servers\n\
+            .                        3600000      NS    A.ROOT-SERVERS.NET.\n\
+            A.ROOT-SERVERS.NET.      3600000      A     198.41.0.4\n\
+            A.ROOT-SERVERS.NET.      3600000      AAAA  2001:503:ba3e::2:30\n\
+            127.0.0.1\n";
+        assert_eq!(parse_hints(hints), vec![IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4)), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))]);
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+use std::net::SocketAddr;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::sync::mpsc::Sender;
+use crate::base::QHandler;
+use crate::udp::query_tcp;
+
+/// 只通过TCP查询的上游
+#[derive(Clone)]
+pub struct TcpUpstream
+{
+    server_addr: SocketAddr,
+}
+
+impl TcpUpstream {
+    pub async fn build(server_addr: SocketAddr) -> Result<Box<dyn QHandler>>{
+        Ok(Box::new(Self {
+            server_addr
+        }))
+    }
+}
+
+#[async_trait]
+impl QHandler for TcpUpstream {
+
+    async fn query(&self, qmsg: Bytes, sender: Sender<Bytes>) -> Result<()> {
+        let res = query_tcp(self.server_addr, &qmsg).await?;
+        tokio::select! {
+            _ = sender.closed() => {}
+            _ = sender.send_timeout(res, Duration::from_secs(1)) => {}
+        }
+        Ok(())
+    }
+
+}
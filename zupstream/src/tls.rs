@@ -0,0 +1,60 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::mpsc::Sender};
+use tokio_rustls::{TlsConnector, rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName}};
+use crate::base::QHandler;
+
+/// 使用内置根证书的TLS配置, alpn为DoQ等协议需要的ALPN标识
+pub(crate) fn tls_config(alpn: &[&[u8]]) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+    }));
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    config
+}
+
+/// DNS over TLS(RFC 7858)上游, 按IP地址校验服务器证书
+#[derive(Clone)]
+pub struct TlsUpstream
+{
+    server_addr: SocketAddr,
+    connector: TlsConnector,
+}
+
+impl TlsUpstream {
+    pub async fn build(server_addr: SocketAddr) -> Result<Box<dyn QHandler>>{
+        Ok(Box::new(Self {
+            server_addr,
+            connector: TlsConnector::from(Arc::new(tls_config(&[]))),
+        }))
+    }
+}
+
+#[async_trait]
+impl QHandler for TlsUpstream {
+
+    async fn query(&self, qmsg: Bytes, sender: Sender<Bytes>) -> Result<()> {
+        let stream = TcpStream::connect(self.server_addr).await?;
+        stream.set_nodelay(true)?;
+        let mut stream = self.connector.connect(ServerName::IpAddress(self.server_addr.ip()), stream).await?;
+        stream.write_u16(qmsg.len() as u16).await?;
+        stream.write_all(&qmsg).await?;
+        let len = stream.read_u16().await?;
+        let mut buf = BytesMut::with_capacity(len.into());
+        buf.resize(len.into(), 0);
+        stream.read_exact(&mut buf).await?;
+        tokio::select! {
+            _ = sender.closed() => {}
+            _ = sender.send_timeout(buf.freeze(), Duration::from_secs(1)) => {}
+        }
+        Ok(())
+    }
+
+}
@@ -3,7 +3,9 @@ use std::net::SocketAddr;
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use tokio::net::UdpSocket;
+use domain::base::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
 use tokio::sync::mpsc::Sender;
 use crate::base::QHandler;
 
@@ -24,20 +26,44 @@ impl UdpUpstream {
 
 #[async_trait]
 impl QHandler for UdpUpstream {
-    
+
     async fn query(&self, qmsg: Bytes, sender: Sender<Bytes>) -> Result<()> {
-        let udp_local_addr = "0.0.0.0:0".parse::<SocketAddr>().unwrap();
-        let udp_socket = UdpSocket::bind(udp_local_addr).await?;
-        let mut buf = BytesMut::with_capacity(1024);
-        buf.resize(1024, 0);
-        udp_socket.send_to(&qmsg, self.server_addr).await?;
-        let (len, _addr) = udp_socket.recv_from(&mut buf).await?;
-        buf.resize(len, 0);
+        let res = exchange(self.server_addr, &qmsg).await?;
         tokio::select! {
             _ = sender.closed() => {}
-            _ = sender.send_timeout(buf.freeze(), Duration::from_secs(1)) => {}
+            _ = sender.send_timeout(res, Duration::from_secs(1)) => {}
         }
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+/// 通过UDP查询, 应答被截断(TC)时改用TCP重新查询.
+pub(crate) async fn exchange(server_addr: SocketAddr, qmsg: &Bytes) -> Result<Bytes> {
+    let udp_local_addr = match server_addr {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse::<SocketAddr>().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse::<SocketAddr>().unwrap(),
+    };
+    let udp_socket = UdpSocket::bind(udp_local_addr).await?;
+    let mut buf = BytesMut::with_capacity(4096);
+    buf.resize(4096, 0);
+    udp_socket.send_to(qmsg, server_addr).await?;
+    let (len, _addr) = udp_socket.recv_from(&mut buf).await?;
+    buf.resize(len, 0);
+    let res = buf.freeze();
+    if Message::from_octets(res.clone()).is_ok_and(|m| m.header().tc()) {
+        return query_tcp(server_addr, qmsg).await;
+    }
+    Ok(res)
+}
+
+pub(crate) async fn query_tcp(server_addr: SocketAddr, qmsg: &Bytes) -> Result<Bytes> {
+    let mut stream = TcpStream::connect(server_addr).await?;
+    stream.write_u16(qmsg.len() as u16).await?;
+    stream.write_all(qmsg).await?;
+    let len = stream.read_u16().await?;
+    let mut buf = BytesMut::with_capacity(len.into());
+    buf.resize(len.into(), 0);
+    stream.read_exact(&mut buf).await?;
+    Ok(buf.freeze())
+}
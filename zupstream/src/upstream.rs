@@ -1,53 +1,144 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::{Arc, RwLock}, time::{Duration, Instant, SystemTime}};
 use anyhow::Result;
 use bytes::{Bytes, BytesMut};
 use domain::base::{Message, MessageBuilder, iana::Rcode};
-use tokio::{sync::mpsc, time::sleep};
-use crate::{base::QHandler, udp::{UdpUpstream}, https::HttpsUpstream};
-use zconfig::Upstream as UpstreamConf;
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
+use crate::{base::QHandler, udp::{UdpUpstream}, tcp::TcpUpstream, tls::TlsUpstream, quic::QuicUpstream, https::HttpsUpstream, recursive::RecursiveUpstream};
+use zconfig::{Upstream as UpstreamConf, UpstreamType};
+use zdnstap::{Peer, Protocol, DNSTAP};
+use zstats::STATS;
 
 #[derive(Clone)]
+struct Upstream {
+    name: String, // 用于统计
+    handler: Box<dyn QHandler>,
+    peer: Option<Peer>, // dnstap中记录的地址及协议, 递归解析时为None
+}
+
+// 一组上游, 重新加载配置时整体替换
+struct UpstreamSet {
+    upstreams: Vec<Upstream>,
+    timeout: Duration,
+}
+
 pub struct ZUpstream {
-    upstreams: Vec<Box<dyn QHandler>>
+    set: RwLock<Arc<UpstreamSet>>,
 }
 
+// 转发查询的超时, 递归解析需要多次往返, 使用更长的超时.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(1);
+const RECURSIVE_TIMEOUT: Duration = Duration::from_secs(5);
 
-impl ZUpstream {
 
-    pub async fn build(upconf_list: Vec<UpstreamConf>) -> Result<Self> {
+impl UpstreamSet {
+
+    async fn build(upconf_list: Vec<UpstreamConf>) -> Result<Self> {
         
         
-        let mut upstreams:Vec<Box<dyn QHandler>> = Vec::new();
+        let mut upstreams = Vec::new();
+        let mut timeout = FORWARD_TIMEOUT;
         for conf in upconf_list.iter() {
-            if let Ok(res) = conf.get_host().parse::<SocketAddr>() {
-                let upstream = UdpUpstream::build(res).await?;
-                upstreams.push(upstream);
-            }else {
-                let upstream = HttpsUpstream::build(conf.get_host()).await?;
-                upstreams.push(upstream);
+            let host = conf.get_host()?;
+            match conf.get_type()? {
+                UpstreamType::Recursive => {
+                    let upstream = RecursiveUpstream::build(host.clone(), conf.port.unwrap_or(53)).await?;
+                    upstreams.push(Upstream { name: format!("recursive:{}", host), handler: upstream, peer: None });
+                    timeout = RECURSIVE_TIMEOUT;
+                },
+                UpstreamType::Udp => {
+                    let addr = host.parse::<SocketAddr>()?;
+                    let upstream = UdpUpstream::build(addr).await?;
+                    let peer = Some(Peer { protocol: Protocol::Udp, addr: Some(addr) });
+                    upstreams.push(Upstream { name: addr.to_string(), handler: upstream, peer });
+                },
+                UpstreamType::Tcp => {
+                    let addr = host.parse::<SocketAddr>()?;
+                    let upstream = TcpUpstream::build(addr).await?;
+                    let peer = Some(Peer { protocol: Protocol::Tcp, addr: Some(addr) });
+                    upstreams.push(Upstream { name: format!("tcp://{}", addr), handler: upstream, peer });
+                },
+                UpstreamType::Https => {
+                    let upstream = HttpsUpstream::build(host.clone()).await?;
+                    let peer = Some(Peer { protocol: Protocol::Doh, addr: None });
+                    upstreams.push(Upstream { name: host, handler: upstream, peer });
+                },
+                UpstreamType::Tls => {
+                    let addr = host.parse::<SocketAddr>()?;
+                    let upstream = TlsUpstream::build(addr).await?;
+                    let peer = Some(Peer { protocol: Protocol::Dot, addr: Some(addr) });
+                    upstreams.push(Upstream { name: format!("tls://{}", addr), handler: upstream, peer });
+                },
+                UpstreamType::Quic => {
+                    let addr = host.parse::<SocketAddr>()?;
+                    let upstream = QuicUpstream::build(addr).await?;
+                    let peer = Some(Peer { protocol: Protocol::Doq, addr: Some(addr) });
+                    upstreams.push(Upstream { name: format!("quic://{}", addr), handler: upstream, peer });
+                },
             }
         }
-        Ok(Self { upstreams })
+        Ok(Self { upstreams, timeout })
+    }
+
+    // 查询单个上游, 超时视为失败并记录统计.
+    fn spawn_query(&self, upstream: Upstream, qmsg: Bytes, sender: mpsc::Sender<(String, Bytes)>) -> JoinHandle<()> {
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let query_time = SystemTime::now();
+            if let Some(peer) = upstream.peer {
+                DNSTAP.forwarder_query(peer, query_time, &qmsg);
+            }
+            let (answer, mut received) = mpsc::channel::<Bytes>(1);
+            let ok = matches!(tokio::time::timeout(timeout, upstream.handler.query(qmsg, answer)).await, Ok(Ok(_)));
+            STATS.upstream(&upstream.name, started.elapsed(), ok);
+            if let Ok(bytes) = received.try_recv() {
+                if let Some(peer) = upstream.peer {
+                    DNSTAP.forwarder_response(peer, query_time, &bytes);
+                }
+                let _ = sender.send((upstream.name, bytes)).await;
+            }
+        })
+    }
+}
+
+impl ZUpstream {
+
+    pub async fn build(upconf_list: Vec<UpstreamConf>) -> Result<Self> {
+        Ok(Self { set: RwLock::new(Arc::new(UpstreamSet::build(upconf_list).await?)) })
+    }
+
+    /// 按新配置建立上游后整体替换, 进行中的查询仍使用原来的上游.
+    pub async fn reload(&self, upconf_list: Vec<UpstreamConf>) -> Result<()> {
+        let set = UpstreamSet::build(upconf_list).await?;
+        *self.set.write().unwrap() = Arc::new(set);
+        Ok(())
+    }
+
+    fn set(&self) -> Arc<UpstreamSet> {
+        self.set.read().unwrap().clone()
     }
     
     pub async fn query(&self, qmsg: Bytes) -> Result<Bytes> {
+        Ok(self.query_named(qmsg).await?.0)
+    }
+
+    /// 同 `query`, 并返回最先应答的上游名称, 超时则为None.
+    pub async fn query_named(&self, qmsg: Bytes) -> Result<(Bytes, Option<String>)> {
         
-        let (sender, mut receiver) = mpsc::channel::<Bytes>(1);
+        let set = self.set();
+        let (sender, mut receiver) = mpsc::channel::<(String, Bytes)>(1);
         
-        for upstream in self.upstreams.iter() {
-            let qmsg = qmsg.clone();
-            let sender = sender.clone();
-            let upstream = upstream.clone();
-            tokio::spawn(async move {
-                let _ = upstream.query(qmsg, sender).await;
-            });
+        for upstream in set.upstreams.iter() {
+            set.spawn_query(upstream.clone(), qmsg.clone(), sender.clone());
         }
         let mut res = BytesMut::with_capacity(1024);
+        let mut name = None;
         
         tokio::select! {
             buf = receiver.recv() => {
-                if let Some(buf) = buf {
+                if let Some((upstream, buf)) = buf {
                     receiver.close();
+                    name = Some(upstream);
                     res.resize(buf.len(), 0);
                     res.copy_from_slice(&buf);
                 }else {
@@ -58,7 +149,7 @@ impl ZUpstream {
                     res.copy_from_slice(&buf); 
                 }
             },
-            _ = sleep(Duration::from_secs(1)) => {
+            _ = sleep(set.timeout) => {
                 let buf = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
                 .start_answer(&Message::from_octets(qmsg)?, Rcode::ServFail)?
                 .into_message().into_octets();
@@ -66,31 +157,26 @@ impl ZUpstream {
                 res.copy_from_slice(&buf); 
             }
         }
-        Ok(res.freeze())
+        Ok((res.freeze(), name))
     }
 
     pub async fn query_all(&self, qmsg: &Bytes) -> Result<Vec<Bytes>> {
-        let max_size = self.upstreams.len();
-        let (sender, mut receiver) = mpsc::channel::<Bytes>(max_size);
+        let set = self.set();
+        let max_size = set.upstreams.len();
+        let (sender, mut receiver) = mpsc::channel::<(String, Bytes)>(max_size);
         let mut list = Vec::new();
         let mut handlers = Vec::new();
-        for upstream in self.upstreams.iter() {
-            let qmsg = qmsg.clone();
-            let sender = sender.clone();
-            let upstream = upstream.clone();
-            let handler = tokio::spawn(async move {
-                let _ = upstream.query(qmsg, sender).await;
-            });
-            handlers.push(handler);
+        for upstream in set.upstreams.iter() {
+            handlers.push(set.spawn_query(upstream.clone(), qmsg.clone(), sender.clone()));
         }
         loop {
             tokio::select! {
                 res = receiver.recv() => {
-                    if let Some(bytes) = res {
+                    if let Some((_, bytes)) = res {
                         list.push(bytes);
                     }
                 },
-                _ = sleep(Duration::from_secs(1)) => {
+                _ = sleep(set.timeout) => {
                     break;
                 }
             }
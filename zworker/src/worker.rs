@@ -1,5 +1,4 @@
 use anyhow::Result;
-use tokio::signal;
 use zresolver::ZResolver;
 use std::sync::Arc;
 use zqueue::{ZRequestQueue, ZResponseQueue, ZQueueHander};
@@ -21,35 +20,23 @@ impl ZWorker {
         Self { name, req_q, res_q, zresolver }
     }
 
+    // 处理请求直到请求队列关闭且为空, 关闭时队列中的请求仍会处理完.
     pub async fn serve(&self) -> Result<()> {
         info!("{} is running...", self.name);
-        loop {
-            tokio::select! {
-                res = self.req_q.recv() => {
-                    let (src, msg) = match res {
-                        Ok(r) => r,
-                        Err(e) => {
-                            error!("{}, Unable to read data, error:{}", self.name, e);
-                            continue;
-                        }
-                    };
-                    let msg = match self.zresolver.resolve(msg).await {
-                        Ok(r) => r,
-                        Err(e) => {
-                            error!("{}, Failed to process request, error:{}", self.name, e);
-                            continue;
-                        }
-                    };
-                    match self.res_q.send((src, msg)).await {
-                        Ok(_) => {}, // info!("{}, successfully processed a request.", self.name);
-                        Err(e) => error!("{}, Failed to send message to reply queue, error:{}", self.name, e),
-                    }
-                }
-                _ = signal::ctrl_c() => {
-                    return self.stop().await;
+        while let Ok((src, msg, received)) = self.req_q.recv().await {
+            let msg = match self.zresolver.resolve(src, msg).await {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("{}, Failed to process request, error:{}", self.name, e);
+                    continue;
                 }
+            };
+            match self.res_q.send((src, msg, received)).await {
+                Ok(_) => {}, // info!("{}, successfully processed a request.", self.name);
+                Err(e) => error!("{}, Failed to send message to reply queue, error:{}", self.name, e),
             }
         }
+        self.stop().await
     }
 
     pub async fn start(&self) -> Result<()> { 
@@ -57,6 +44,7 @@ impl ZWorker {
     }
 
     pub async fn stop(&self) -> Result<()> {
+        debug!("{} stopped", self.name);
         Ok(())
     }
 }
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use zconfig::{config_path, Override};
+
+pub const VERSION: &str = concat!("zzdns ", env!("CARGO_PKG_VERSION"));
+
+pub const USAGE: &str = "Usage: zzdns [OPTIONS]
+
+Options:
+  -c, --config <PATH>     Config file [env: ZZDNS_CONFIG] [default: config/config.{json,toml,yaml} or ../config/...]
+  -p, --port <PORT>       Override server.port [env: ZZDNS_PORT]
+  -w, --worker <N>        Override server.worker [env: ZZDNS_WORKER]
+  -u, --upstream <HOST>   Override upstreams, may be repeated [env: ZZDNS_UPSTREAMS, comma separated]
+      --admin <ADDR>      Override admin.listen [env: ZZDNS_ADMIN]
+      --set <PATH=VALUE>  Override any setting, e.g. cache.max_ttl=600, may be repeated
+      --check-config      Validate the config and exit
+  -V, --version           Print version
+  -h, --help              Print help";
+
+/// 启动参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub config: PathBuf,
+    pub overrides: Vec<Override>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Check(Options),
+    Version,
+    Help,
+}
+
+fn number(flag: &str, value: &str) -> Result<Value> {
+    value.parse::<u16>().map(|n| json!(n)).map_err(|_| anyhow!("invalid value {:?} for {}: expected a number between 0 and 65535", value, flag))
+}
+
+fn upstreams(hosts: &[String]) -> Value {
+    Value::Array(hosts.iter().map(|h| json!({"host": h})).collect())
+}
+
+/// 解析命令行参数, 命令行优先于环境变量, 二者均优先于配置文件
+pub fn parse<I, E>(args: I, env: E) -> Result<Command>
+where
+    I: IntoIterator<Item = String>,
+    E: Fn(&str) -> Option<String>,
+{
+    let mut config = env("ZZDNS_CONFIG").map(PathBuf::from);
+    let mut overrides = Vec::new();
+    if let Some(port) = env("ZZDNS_PORT") {
+        overrides.push(Override::new("server.port", number("ZZDNS_PORT", &port)?));
+    }
+    if let Some(worker) = env("ZZDNS_WORKER") {
+        overrides.push(Override::new("server.worker", number("ZZDNS_WORKER", &worker)?));
+    }
+    if let Some(hosts) = env("ZZDNS_UPSTREAMS") {
+        let hosts: Vec<String> = hosts.split(',').map(|h| h.trim().to_string()).filter(|h| !h.is_empty()).collect();
+        overrides.push(Override::new("upstreams", upstreams(&hosts)));
+    }
+    if let Some(admin) = env("ZZDNS_ADMIN") {
+        overrides.push(Override::new("admin.listen", json!(admin)));
+    }
+
+    let mut check = false;
+    let mut hosts = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // 支持 --flag value 及 --flag=value
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let takes_value = matches!(flag.as_str(), "-c" | "--config" | "-p" | "--port" | "-w" | "--worker"
+            | "-u" | "--upstream" | "--admin" | "--set");
+        let value = if takes_value {
+            match inline.or_else(|| args.next()) {
+                Some(v) => v,
+                None => return Err(anyhow!("missing value for {}", flag)),
+            }
+        } else if inline.is_some() {
+            return Err(anyhow!("{} does not take a value", flag));
+        } else {
+            String::new()
+        };
+        match flag.as_str() {
+            "-c" | "--config" => config = Some(PathBuf::from(value)),
+            "-p" | "--port" => overrides.push(Override::new("server.port", number(&flag, &value)?)),
+            "-w" | "--worker" => overrides.push(Override::new("server.worker", number(&flag, &value)?)),
+            "-u" | "--upstream" => hosts.push(value),
+            "--admin" => overrides.push(Override::new("admin.listen", json!(value))),
+            "--set" => overrides.push(Override::parse(&value)?),
+            "--check-config" => check = true,
+            "-V" | "--version" => return Ok(Command::Version),
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => return Err(anyhow!("unknown argument {:?}", arg)),
+        }
+    }
+    if !hosts.is_empty() {
+        overrides.push(Override::new("upstreams", upstreams(&hosts)));
+    }
+
+    let options = Options { config: config.unwrap_or_else(config_path), overrides };
+    Ok(if check { Command::Check(options) } else { Command::Run(options) })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn run(args: &[&str], env: &[(&str, &str)]) -> Result<Command> {
+        let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        parse(args.iter().map(|a| a.to_string()), |k| env.get(k).cloned())
+    }
+
+    #[test]
+    fn test_parse() {
+        let command = run(&["--config", "/etc/zzdns.json", "-p", "5353", "--set=cache.max_ttl=600",
+            "-u", "1.1.1.1", "--upstream=https://dns.alidns.com/dns-query"], &[]).unwrap();
+        assert_eq!(command, Command::Run(Options {
+            config: PathBuf::from("/etc/zzdns.json"),
+            overrides: vec![
+                Override::new("server.port", json!(5353)),
+                Override::new("cache.max_ttl", json!(600)),
+                Override::new("upstreams", json!([{"host": "1.1.1.1"}, {"host": "https://dns.alidns.com/dns-query"}])),
+            ],
+        }));
+        assert_eq!(run(&["--check-config", "-c", "a.json"], &[]).unwrap(),
+            Command::Check(Options { config: PathBuf::from("a.json"), overrides: vec![] }));
+        assert_eq!(run(&["-V"], &[]).unwrap(), Command::Version);
+        assert_eq!(run(&["--help", "--bogus"], &[]).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn test_env() {
+        let env = [("ZZDNS_CONFIG", "env.json"), ("ZZDNS_PORT", "5300"), ("ZZDNS_UPSTREAMS", "1.1.1.1, 8.8.8.8")];
+        // 命令行的覆盖项在后, 优先生效
+        let command = run(&["--port", "5353"], &env).unwrap();
+        assert_eq!(command, Command::Run(Options {
+            config: PathBuf::from("env.json"),
+            overrides: vec![
+                Override::new("server.port", json!(5300)),
+                Override::new("upstreams", json!([{"host": "1.1.1.1"}, {"host": "8.8.8.8"}])),
+                Override::new("server.port", json!(5353)),
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(run(&["--port", "70000"], &[]).unwrap_err().to_string(),
+            "invalid value \"70000\" for --port: expected a number between 0 and 65535");
+        assert_eq!(run(&["--config"], &[]).unwrap_err().to_string(), "missing value for --config");
+        assert_eq!(run(&["--check-config=yes"], &[]).unwrap_err().to_string(), "--check-config does not take a value");
+        assert_eq!(run(&["serve"], &[]).unwrap_err().to_string(), "unknown argument \"serve\"");
+        assert!(run(&[], &[("ZZDNS_WORKER", "many")]).is_err());
+    }
+}
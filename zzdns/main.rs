@@ -1,40 +1,187 @@
-use std::{sync::Arc};
+mod cli;
+
+use std::{net::SocketAddr, process, sync::Arc, time::Duration};
+use anyhow::{anyhow, Result};
+use cli::{Command, Options};
+use zadmin::ZAdmin;
 use zcacher::ZCacher;
+use zdnssec::ZValidator;
+use zfilter::ZFilter;
+use zlocal::ZLocal;
 use zresolver::ZResolver;
 use zserver::*;
 use zqueue::*;
 use zworker::*;
-use zconfig::CONFIG;
+use zconfig::Config;
 use zupstream::ZUpstream;
-use zpreloader::ZPreloader;
+use zpreloader::{ZPopularity, ZPreloader};
+use zquerylog::ZQueryLog;
+use zdnstap::DNSTAP;
+use zreload::ZReloader;
+use zshutdown::SHUTDOWN;
+use zzone::ZAuthority;
+use zrewrite::ZRewrite;
+
+// 关闭时等待处理完队列中请求的默认时间(秒)
+const SHUTDOWN_TIMEOUT: u64 = 5;
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init_timed();
-    let qsize = CONFIG.server.qsize.into();
-    let worker = CONFIG.server.worker.into();
+    let command = match cli::parse(std::env::args().skip(1), |k| std::env::var(k).ok()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("zzdns: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        },
+    };
+    let res = match command {
+        Command::Version => {
+            println!("{}", cli::VERSION);
+            return;
+        },
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        },
+        Command::Check(options) => check(options).await,
+        Command::Run(options) => {
+            pretty_env_logger::init_timed();
+            run(options).await
+        },
+    };
+    if let Err(e) = res {
+        eprintln!("zzdns: {:#}", e);
+        process::exit(1);
+    }
+}
+
+// 检查配置及其引用的文件, 不启动服务
+async fn check(options: Options) -> Result<()> {
+    let conf = zconfig::load_with(&options.config, &options.overrides)?;
+    ZFilter::build(conf.filter.clone()).await.map_err(|e| anyhow!("Invalid filter config, error:{:#}", e))?;
+    ZLocal::build(conf.local.clone()).await.map_err(|e| anyhow!("Invalid local config, error:{:#}", e))?;
+    ZAuthority::build(conf.zones.clone()).await.map_err(|e| anyhow!("Invalid zones config, error:{:#}", e))?;
+    ZRewrite::new(conf.rewrites.clone()).map_err(|e| anyhow!("Invalid rewrites config, error:{:#}", e))?;
+    ZQueryLog::build(conf.querylog.clone()).map_err(|e| anyhow!("Invalid querylog config, error:{:#}", e))?;
+    if let Some(admin) = &conf.admin {
+        admin.listen.parse::<SocketAddr>().map_err(|e| anyhow!("Invalid admin listen address {:?}, error:{}", admin.listen, e))?;
+    }
+    println!("Config {:?} is valid", options.config);
+    Ok(())
+}
+
+async fn run(options: Options) -> Result<()> {
+    let conf: Config = zconfig::load_with(&options.config, &options.overrides)?;
+    SHUTDOWN.listen()?;
+    DNSTAP.start(conf.dnstap.clone())?;
+    let qsize = conf.server.qsize.into();
+    let worker = conf.server.worker.into();
     let req_q = Arc::new(ZRequestQueue::new(qsize));
     let res_q = Arc::new(ZResponseQueue::new(qsize));
-    let zserver = ZServerBuilder::build(CONFIG.server.clone(), req_q.clone(), res_q.clone()).await.unwrap(); 
-    let zupstream = Arc::new(ZUpstream::build(CONFIG.upstreams.clone()).await.unwrap());
-    let zcacher = Arc::new(ZCacher::new(CONFIG.cache.clone(), zupstream.clone()));
-    let zresolver = Arc::new(ZResolver::new(zupstream.clone(), zcacher.clone()));
+    let zserver = ZServerBuilder::build(conf.server.clone(), req_q.clone(), res_q.clone()).await?;
+    let zupstream = Arc::new(ZUpstream::build(conf.upstreams.clone()).await?);
+    let zvalidator = Arc::new(ZValidator::build(conf.dnssec.clone(), zupstream.clone())?);
+    let zcacher = Arc::new(ZCacher::new(conf.cache.clone(), zupstream.clone(), zvalidator.clone())?);
+    let zfilter = Arc::new(ZFilter::build(conf.filter.clone()).await?);
+    let zlocal = Arc::new(ZLocal::build(conf.local.clone()).await?);
+    let zauthority = Arc::new(ZAuthority::build(conf.zones.clone()).await?);
+    let zrewrite = Arc::new(ZRewrite::new(conf.rewrites.clone())?);
+    let zpopularity = Arc::new(ZPopularity::new(conf.cache.popular_file.clone(), conf.cache.popular_size));
+    let zquerylog = Arc::new(ZQueryLog::build(conf.querylog.clone())?);
+    let zresolver = Arc::new(ZResolver::new(zupstream.clone(), zcacher.clone(), zfilter, zlocal, zauthority, zrewrite, zvalidator)
+        .with_popularity(zpopularity.clone())
+        .with_querylog(zquerylog.clone()));
+    let querylog = zquerylog.clone();
+    let querylog_handle = tokio::spawn(async move {
+        querylog.serve().await;
+    });
+    if let Err(e) = zcacher.load_snapshot().await {
+        log::warn!("Failed to load cache snapshot, error:{:?}", e);
+    }
     let zcacher2 = zcacher.clone();
+    let mut workers = Vec::new();
     for i in 0..worker {
         let zworker = ZWorker::new(i, req_q.clone(), res_q.clone(), zresolver.clone());
-        tokio::spawn(async move {
+        workers.push(tokio::spawn(async move {
             zworker.start().await.unwrap();
-        });
+        }));
     }
     
-    tokio::spawn(async move {
-        zcacher.start().await.unwrap();
+    let cacher = zcacher.clone();
+    let cacher_handle = tokio::spawn(async move {
+        cacher.start().await.unwrap();
     });
 
+    let zpreloader = Arc::new(ZPreloader::new(conf.cache.preload_files(), zcacher2.clone()));
+    let zreloader = Arc::new(ZReloader::new(options.config, conf.clone(), zupstream.clone(), zresolver.clone(),
+        zcacher2.clone(), zpreloader.clone())
+        .with_overrides(options.overrides)
+        .with_access(zserver.access()));
+    let zadmin = ZAdmin::build(conf.admin.clone(), zcacher2.clone())?
+        .with_queues(req_q.clone(), res_q.clone())
+        .with_preloader(zpreloader.clone())
+        .with_reloader(zreloader.clone());
     tokio::spawn(async move {
-        ZPreloader::load(CONFIG.cache.preload_file.clone(), zcacher2.clone()).await;
+        if let Err(e) = zadmin.serve().await {
+            log::error!("Failed to start admin server, error:{:?}", e);
+        }
+    });
+    tokio::spawn(async move {
+        if let Err(e) = zreloader.serve().await {
+            log::error!("Failed to watch config file, error:{:?}", e);
+        }
+    });
+    tokio::spawn(async move {
+        zpreloader.serve().await;
+    });
+
+    let popularity_handle = tokio::spawn(async move {
+        match zpopularity.load().await {
+            Ok(domains) => {
+                for domain in domains {
+                    let _ = zcacher2.push(domain).await;
+                }
+            },
+            Err(e) => log::warn!("Failed to load popular domains, error:{:?}", e),
+        }
+        if let Err(e) = zpopularity.serve(zcacher2).await {
+            log::error!("Failed to save popular domains, error:{:?}", e);
+        }
     });
 
-    zserver.start().await.unwrap();
+    let mut server_handle = tokio::spawn(async move {
+        zserver.start().await
+    });
+    tokio::select! {
+        _ = SHUTDOWN.wait() => {},
+        res = &mut server_handle => return res?,
+    }
 
+    // 服务停止接收请求后, 等待工人处理完队列中的请求
+    let timeout = Duration::from_secs(conf.server.shutdown_timeout.unwrap_or(SHUTDOWN_TIMEOUT));
+    req_q.close();
+    let drained = tokio::time::timeout(timeout, async {
+        for worker in workers.iter_mut() {
+            let _ = worker.await;
+        }
+    }).await.is_ok();
+    let dropped = req_q.len();
+    for worker in workers.iter() {
+        worker.abort();
+    }
+    // 发送完已处理的应答
+    res_q.close();
+    server_handle.await??;
+    // 停止后台解析并保存快照, 写完查询日志及dnstap
+    zcacher.close();
+    let _ = cacher_handle.await;
+    zquerylog.close();
+    let _ = querylog_handle.await;
+    let _ = popularity_handle.await;
+    DNSTAP.stop().await;
+    if !drained {
+        return Err(anyhow!("Requests were not drained within {:?}, {} queued requests dropped", timeout, dropped));
+    }
+    log::info!("zzdns stopped");
+    Ok(())
 }
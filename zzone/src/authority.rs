@@ -0,0 +1,46 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use domain::base::Message;
+use tokio::fs;
+use zconfig::Zone as ZoneConf;
+use crate::zone::{normalize, Zone};
+
+#[derive(Debug, Clone, Default)]
+pub struct ZAuthority {
+    zones: Vec<Zone>,
+}
+
+impl ZAuthority {
+
+    pub async fn build(conf: Option<Vec<ZoneConf>>) -> Result<Self> {
+        let mut zones = Vec::new();
+        for zconf in conf.unwrap_or_default() {
+            let text = fs::read_to_string(&zconf.file).await
+                .map_err(|e| anyhow!("Failed to read zone file({:?}), error: {}", zconf.file, e))?;
+            let zone = Zone::parse(&zconf.origin, &text)?;
+            info!("zauthority loaded zone {:?} from {:?}", zone.apex(), zconf.file);
+            zones.push(zone);
+        }
+        Ok(Self { zones })
+    }
+
+    /// 查询的域名位于权威区域内时返回权威应答, 否则返回 `None`.
+    pub fn answer(&self, qmsg: &Message<Bytes>) -> Result<Option<Bytes>> {
+        if self.zones.is_empty() {
+            return Ok(None);
+        }
+        let question = qmsg.sole_question()?;
+        let qname = normalize(&question.qname().to_string());
+        match self.find(&qname) {
+            Some(zone) => Ok(Some(zone.answer(qmsg)?)),
+            None => Ok(None),
+        }
+    }
+
+    // 选择apex最长的区域
+    fn find(&self, qname: &str) -> Option<&Zone> {
+        self.zones.iter()
+            .filter(|z| z.contains(qname))
+            .max_by_key(|z| z.apex().len())
+    }
+}
@@ -0,0 +1,7 @@
+#[macro_use] extern crate log;
+
+mod authority;
+mod zone;
+
+pub use authority::ZAuthority;
+pub use zone::Zone;
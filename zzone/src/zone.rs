@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use domain::base::{Dname, Message, MessageBuilder, Record, Rtype, iana::Rcode};
+use domain::master::entry::MasterRecord;
+use domain::master::reader::{Reader, ReaderItem};
+use domain::rdata::ZoneRecordData;
+
+// 未指定 $TTL 时的默认TTL
+const DEFAULT_TTL: u32 = 3600;
+// CNAME链最大长度
+const MAX_CNAME_CHAIN: usize = 8;
+
+// 查询结果类型
+enum Outcome {
+    Answer,
+    NoData,
+    NxDomain,
+    Referral(Vec<MasterRecord>),
+}
+
+/// 从zone文件加载的权威区域
+#[derive(Debug, Clone)]
+pub struct Zone {
+    apex: String,
+    soa: MasterRecord,
+    nodes: HashMap<String, Vec<MasterRecord>>,
+}
+
+impl Zone {
+
+    /// 解析RFC 1035格式的zone文件内容, 未指定 $ORIGIN 时使用 `origin`.
+    pub fn parse(origin: &str, text: &str) -> Result<Self> {
+        let apex = normalize(origin);
+        let text = format!("$ORIGIN {}.\n$TTL {}\n{}", apex, DEFAULT_TTL, text);
+        let mut nodes: HashMap<String, Vec<MasterRecord>> = HashMap::new();
+        let mut soa = None;
+
+        for item in Reader::new(text.as_str()) {
+            let record = match item.map_err(|e| anyhow!("Failed to parse zone {:?}, error: {}", apex, e))? {
+                ReaderItem::Record(r) => r,
+                item => {
+                    warn!("Unsupported zone file entry in zone {:?}: {}", apex, item);
+                    continue;
+                }
+            };
+            let owner = normalize(&record.owner().to_string());
+            if owner != apex && !is_subdomain(&owner, &apex) {
+                warn!("Ignoring out-of-zone record in zone {:?}: {}", apex, record);
+                continue;
+            }
+            if owner == apex && record.rtype() == Rtype::Soa {
+                soa = Some(record.clone());
+            }
+            nodes.entry(owner).or_default().push(record);
+        }
+
+        let soa = soa.ok_or_else(|| anyhow!("Zone {:?} has no SOA record", apex))?;
+        Ok(Self { apex, soa, nodes })
+    }
+
+    pub fn apex(&self) -> &str {
+        &self.apex
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        name == self.apex || is_subdomain(name, &self.apex)
+    }
+
+    /// 生成权威应答, `qname` 需位于本区域内.
+    pub fn answer(&self, qmsg: &Message<Bytes>) -> Result<Bytes> {
+        let question = qmsg.sole_question()?;
+        let qtype = question.qtype();
+        let mut name = normalize(&question.qname().to_string());
+        let mut answers = Vec::new();
+        let mut outcome = Outcome::Answer;
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            if let Some(ns) = self.find_cut(&name) {
+                outcome = Outcome::Referral(ns);
+                break;
+            }
+            let records = match self.lookup(&name)? {
+                Some(r) => r,
+                None => {
+                    outcome = if self.has_descendants(&name) { Outcome::NoData } else { Outcome::NxDomain };
+                    break;
+                }
+            };
+            let matched: Vec<MasterRecord> = records.iter()
+                .filter(|r| qtype == Rtype::Any || r.rtype() == qtype)
+                .cloned()
+                .collect();
+            if !matched.is_empty() {
+                answers.extend(matched);
+                outcome = Outcome::Answer;
+                break;
+            }
+            let target = records.iter().find_map(|r| match r.data() {
+                ZoneRecordData::Cname(cname) => Some((r.clone(), cname.cname().to_string())),
+                _ => None,
+            });
+            match target {
+                Some((cname, target)) => {
+                    answers.push(cname);
+                    name = normalize(&target);
+                    outcome = Outcome::Answer;
+                    if !self.contains(&name) {
+                        break;
+                    }
+                },
+                None => {
+                    outcome = Outcome::NoData;
+                    break;
+                }
+            }
+        }
+
+        let rcode = match outcome {
+            Outcome::NxDomain => Rcode::NXDomain,
+            _ => Rcode::NoError,
+        };
+        let mut rmsg = MessageBuilder::from_target(BytesMut::with_capacity(1024))?
+                .start_answer(qmsg, rcode)?;
+        let header = rmsg.header_mut();
+        header.set_aa(!matches!(outcome, Outcome::Referral(_)));
+        header.set_ra(true);
+        for record in answers {
+            rmsg.push(record)?;
+        }
+
+        let mut authority = rmsg.authority();
+        let mut glue = Vec::new();
+        match outcome {
+            Outcome::Answer => {
+                for ns in self.records(&self.apex, Rtype::Ns) {
+                    authority.push(ns)?;
+                }
+            },
+            Outcome::NoData | Outcome::NxDomain => authority.push(self.negative_soa())?,
+            Outcome::Referral(ns_list) => {
+                for ns in ns_list {
+                    if let ZoneRecordData::Ns(ns) = ns.data() {
+                        let target = normalize(&ns.nsdname().to_string());
+                        glue.extend(self.records(&target, Rtype::A));
+                        glue.extend(self.records(&target, Rtype::Aaaa));
+                    }
+                    authority.push(ns)?;
+                }
+            },
+        }
+        let mut additional = authority.additional();
+        for record in glue {
+            additional.push(record)?;
+        }
+        Ok(additional.into_message().into_octets())
+    }
+
+    // 查找 apex 与 name 之间(含name)的委派点, 返回其NS记录.
+    fn find_cut(&self, name: &str) -> Option<Vec<MasterRecord>> {
+        let relative = name.strip_suffix(self.apex.as_str())?.trim_end_matches('.');
+        if relative.is_empty() {
+            return None;
+        }
+        let labels: Vec<&str> = relative.split('.').collect();
+        for i in (0..labels.len()).rev() {
+            let cut = format!("{}.{}", labels[i..].join("."), self.apex);
+            let ns = self.records(&cut, Rtype::Ns);
+            if !ns.is_empty() {
+                return Some(ns);
+            }
+        }
+        None
+    }
+
+    // 精确匹配优先, 其次匹配最近的通配符记录, 通配符记录的owner替换为查询域名.
+    fn lookup(&self, name: &str) -> Result<Option<Vec<MasterRecord>>> {
+        if let Some(records) = self.nodes.get(name) {
+            return Ok(Some(records.clone()));
+        }
+        let mut parent = name;
+        while let Some((_, p)) = parent.split_once('.') {
+            if !self.contains(p) {
+                break;
+            }
+            if let Some(records) = self.nodes.get(&format!("*.{}", p)) {
+                let owner = Dname::bytes_from_str(name)?;
+                return Ok(Some(records.iter()
+                    .map(|r| Record::new(owner.clone(), r.class(), r.ttl(), r.data().clone()))
+                    .collect()));
+            }
+            if self.nodes.contains_key(p) || self.has_descendants(p) {
+                break;
+            }
+            parent = p;
+        }
+        Ok(None)
+    }
+
+    fn records(&self, name: &str, rtype: Rtype) -> Vec<MasterRecord> {
+        self.nodes.get(name)
+            .map(|rs| rs.iter().filter(|r| r.rtype() == rtype).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // 空的非终端节点, 如只存在 a.b.example.com 时的 b.example.com
+    fn has_descendants(&self, name: &str) -> bool {
+        self.nodes.keys().any(|k| is_subdomain(k, name))
+    }
+
+    // 否定应答的SOA记录, TTL取SOA TTL与minimum字段的较小值(RFC 2308).
+    fn negative_soa(&self) -> MasterRecord {
+        let mut soa = self.soa.clone();
+        if let ZoneRecordData::Soa(data) = self.soa.data() {
+            soa.set_ttl(soa.ttl().min(data.minimum()));
+        }
+        soa
+    }
+}
+
+pub fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn is_subdomain(name: &str, parent: &str) -> bool {
+    name.len() > parent.len() + 1
+        && name.ends_with(parent)
+        && name.as_bytes()[name.len() - parent.len() - 1] == b'.'
+}
+
+
+#[cfg(test)]
+mod test {
+    use bytes::{Bytes, BytesMut};
+    use domain::base::{Dname, Message, MessageBuilder, Question, Rtype, iana::Rcode};
+    use super::Zone;
+
+    const ZONE: &str = r#"
+@       IN SOA  ns1 hostmaster 2022101901 3600 600 86400 300
+@       IN NS   ns1
+ns1     IN A    10.0.0.1
+www     IN A    10.0.0.80
+alias   IN CNAME www
+ext     IN CNAME example.org.
+*.apps  IN A    10.0.0.90
+a.b     IN TXT  "deep"
+sub     IN NS   ns.sub
+ns.sub  IN A    10.0.1.1
+"#;
+
+    fn query(name: &str, qtype: Rtype) -> Message<Bytes> {
+        let mut builder = MessageBuilder::from_target(BytesMut::with_capacity(512)).unwrap().question();
+        builder.push(Question::new_in(Dname::bytes_from_str(name).unwrap(), qtype)).unwrap();
+        Message::from_octets(builder.into_message().into_octets()).unwrap()
+    }
+
+    fn answer(zone: &Zone, name: &str, qtype: Rtype) -> Message<Bytes> {
+        Message::from_octets(zone.answer(&query(name, qtype)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_zone_answer() {
+        let zone = Zone::parse("home.lan.", ZONE).unwrap();
+        assert_eq!(zone.apex(), "home.lan");
+
+        let rmsg = answer(&zone, "www.home.lan", Rtype::A);
+        assert!(rmsg.header().aa());
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+        assert_eq!(rmsg.header_counts().nscount(), 1);
+
+        let rmsg = answer(&zone, "alias.home.lan", Rtype::A);
+        assert_eq!(rmsg.header_counts().ancount(), 2);
+
+        let rmsg = answer(&zone, "ext.home.lan", Rtype::A);
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let rmsg = answer(&zone, "x.apps.home.lan", Rtype::A);
+        assert_eq!(rmsg.header_counts().ancount(), 1);
+
+        let rmsg = answer(&zone, "www.home.lan", Rtype::Aaaa);
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+        assert_eq!(rmsg.header_counts().nscount(), 1);
+
+        let rmsg = answer(&zone, "b.home.lan", Rtype::A);
+        assert_eq!(rmsg.header().rcode(), Rcode::NoError);
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+
+        let rmsg = answer(&zone, "missing.home.lan", Rtype::A);
+        assert_eq!(rmsg.header().rcode(), Rcode::NXDomain);
+        assert!(rmsg.header().aa());
+        assert_eq!(rmsg.header_counts().nscount(), 1);
+
+        let rmsg = answer(&zone, "host.sub.home.lan", Rtype::A);
+        assert!(!rmsg.header().aa());
+        assert_eq!(rmsg.header_counts().ancount(), 0);
+        assert_eq!(rmsg.header_counts().nscount(), 1);
+        assert_eq!(rmsg.header_counts().arcount(), 1);
+    }
+
+    #[test]
+    fn test_zone_without_soa() {
+        assert!(Zone::parse("home.lan", "www IN A 10.0.0.80\n").is_err());
+    }
+}